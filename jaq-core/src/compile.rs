@@ -3,7 +3,9 @@
 use crate::load::{self, lex, parse};
 use crate::{ops, Bind as Arg, Filter};
 use alloc::collections::{BTreeMap, BTreeSet};
-use alloc::{boxed::Box, string::String, vec::Vec};
+use alloc::string::{String, ToString};
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt::{self, Display};
 
 type NativeId = usize;
 type ModId = usize;
@@ -12,23 +14,37 @@ type VarSkip = usize;
 type Arity = usize;
 
 /// Index of a term in the look-up table.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TermId(pub(crate) usize);
 
+impl fmt::Debug for TermId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Index of a module and byte range of a term in the module's code.
+///
+/// Modules are indexed in the order in which they are passed to [`Compiler::compile`].
+pub type Span = (usize, core::ops::Range<usize>);
+
 /// Look-up table for terms and functions.
 #[derive(Clone, Debug)]
 pub struct Lut<F> {
     /// `terms[tid]` yields the term corresponding to the term ID `tid`
     pub(crate) terms: Vec<Term>,
     pub(crate) funs: Vec<F>,
+    /// `defs[tid]` yields the name and arity of the definition with body `tid`
+    pub(crate) defs: BTreeMap<TermId, String>,
+    /// `natives[nid]` yields the name and arity of the native function `funs[nid]`
+    pub(crate) natives: Vec<String>,
+    /// `spans[tid]` yields the location of the term `tid` in the source code, if known
+    pub(crate) spans: BTreeMap<TermId, Span>,
 }
 
 impl<F> Default for Lut<F> {
     fn default() -> Self {
-        Lut {
-            terms: Vec::new(),
-            funs: Vec::new(),
-        }
+        Self::new(Vec::new())
     }
 }
 
@@ -40,14 +56,22 @@ impl<F> Default for Filter<F> {
 
 impl<F> Lut<F> {
     fn new(terms: Vec<Term>) -> Self {
-        let funs = Vec::new();
-        Self { terms, funs }
+        Self {
+            terms,
+            funs: Vec::new(),
+            defs: BTreeMap::new(),
+            natives: Vec::new(),
+            spans: BTreeMap::new(),
+        }
     }
 
     fn map_funs<F2>(self, f: impl Fn(F) -> F2) -> Lut<F2> {
         Lut {
             funs: self.funs.into_iter().map(f).collect(),
             terms: self.terms,
+            defs: self.defs,
+            natives: self.natives,
+            spans: self.spans,
         }
     }
 
@@ -64,9 +88,10 @@ pub(crate) enum Tailrec {
     Catch,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub(crate) enum Term<T = TermId> {
     /// Identity (`.`)
+    #[default]
     Id,
     ToString,
 
@@ -82,6 +107,8 @@ pub(crate) enum Term<T = TermId> {
 
     /// Bound variable (`$x`), label (`label $x`), or filter argument (`a`)
     Var(VarId),
+    /// Breakpoint (`$__break__`), storing the names of all variables visible at it
+    Breakpoint(Box<[(String, VarId)]>),
     /// Call to a filter (`filter`, `filter(…)`)
     CallDef(TermId, Box<[Arg<T>]>, VarSkip, Option<Tailrec>),
     Native(NativeId, Box<[Arg<T>]>),
@@ -146,12 +173,6 @@ pub(crate) enum Fold<T> {
     Foreach(Option<T>),
 }
 
-impl<T> Default for Term<T> {
-    fn default() -> Self {
-        Self::Id
-    }
-}
-
 #[derive(Clone, Debug)]
 pub(crate) enum Pattern<F> {
     Var,
@@ -161,8 +182,11 @@ pub(crate) enum Pattern<F> {
 /// Compilation error.
 pub type Error<S> = (S, Undefined);
 
+/// Compilation error together with a hint to fix it.
+pub type Hinted<S> = (Error<S>, Hint);
+
 /// Compilation errors.
-pub type Errors<S, P> = load::Errors<S, P, Vec<Error<S>>>;
+pub type Errors<S, P, E = Error<S>> = load::Errors<S, P, Vec<E>>;
 
 /// Type of an undefined symbol.
 #[derive(Debug)]
@@ -190,6 +214,124 @@ impl Undefined {
     }
 }
 
+/// Information that may help to fix an undefined symbol.
+#[derive(Debug, Default)]
+pub struct Hint {
+    /// names of defined symbols of the same type that are similar to the undefined one,
+    /// most similar first
+    pub similar: Vec<String>,
+    /// arities of filters that have the same name as the undefined filter, in ascending order
+    pub arities: Vec<Arity>,
+}
+
+impl Hint {
+    /// Suggest up to three of the `defined` names that are similar to `name`.
+    fn similar<'a>(name: &str, defined: impl Iterator<Item = &'a str>) -> Self {
+        let len = name.chars().count();
+        // permit one edit per three characters, or one for names like `$x`
+        let max = core::cmp::max(len / 3, usize::from(len == 2));
+        // names starting with `!` are internal and cannot be written by users
+        let defined = defined.filter(|x| *x != name && !x.starts_with('!'));
+        // strings whose lengths differ by `n` have a distance of at least `n`
+        let defined = defined.filter(|x| x.chars().count().abs_diff(len) <= max);
+        let dist = defined.map(|x| (distance(name, x), x));
+        let similar: BTreeSet<_> = dist.filter(|(d, _)| *d <= max).collect();
+        let similar = similar.into_iter().take(3).map(|(_, x)| x.to_string());
+        Self {
+            similar: similar.collect(),
+            arities: Vec::new(),
+        }
+    }
+
+    /// Suggest filters that are similar to `name`, or that have the same `name`.
+    fn filter<'a>(name: &str, defined: impl Iterator<Item = (&'a str, Arity)> + Clone) -> Self {
+        let arities = defined.clone().filter(|(x, _)| *x == name);
+        let arities: BTreeSet<_> = arities.map(|(_, arity)| arity).collect();
+        Self {
+            arities: arities.into_iter().collect(),
+            ..Self::similar(name, defined.map(|(x, _)| x))
+        }
+    }
+
+    /// Return the arities of filters with the undefined name, such as "1, 2, or 3".
+    pub fn arities_str(&self) -> Option<String> {
+        or_list(self.arities.iter().map(|a| a.to_string()))
+    }
+
+    /// Return a suggestion of similar names, such as "did you mean `a` or `b`?".
+    pub fn suggestion(&self) -> Option<String> {
+        let similar = self.similar.iter().map(|x| alloc::format!("`{x}`"));
+        Some(alloc::format!("did you mean {}?", or_list(similar)?))
+    }
+}
+
+/// Join strings like "a", "a or b", or "a, b, or c", returning `None` if there are none.
+fn or_list(xs: impl ExactSizeIterator<Item = String>) -> Option<String> {
+    let len = xs.len();
+    let sep = |i| match (i, len) {
+        (0, _) => "",
+        (_, 2) => " or ",
+        _ if i + 1 == len => ", or ",
+        _ => ", ",
+    };
+    let list = xs.enumerate().map(|(i, x)| alloc::format!("{}{x}", sep(i)));
+    Some(list.collect::<String>()).filter(|s| !s.is_empty())
+}
+
+/// Return the edit distance between two strings, counting
+/// insertions, deletions, substitutions, and transpositions of characters.
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // `d[i][j]` is the distance between the first `i` chars of `a` and the first `j` of `b`
+    let mut d: Vec<Vec<usize>> = (0..=a.len()).map(|i| Vec::from([i])).collect();
+    d[0].extend(1..=b.len());
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let subst = d[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut min = subst.min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                min = min.min(d[i - 2][j - 2] + 1);
+            }
+            d[i].push(min);
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Intermediate representation of a compiled filter, obtained by [`Filter::ir`].
+///
+/// Its [`Display`] implementation prints one line per term in the look-up table.
+/// Terms that are bodies of definitions are annotated with the name and arity
+/// of their definition, and calls to definitions and native functions
+/// are annotated with the name and arity of their targets.
+pub struct Ir<'a, F>(pub(crate) &'a Filter<F>);
+
+impl<F> Display for Ir<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Filter(main, lut) = self.0;
+        writeln!(f, "main = {main:?}")?;
+        for (tid, t) in lut.terms.iter().enumerate() {
+            let tid = TermId(tid);
+            write!(f, "{tid:?}")?;
+            if let Some(def) = lut.defs.get(&tid) {
+                write!(f, " ({def})")?;
+            }
+            match t {
+                Term::CallDef(id, args, skip, tr) => {
+                    let def = lut.defs.get(id).map_or("?", |d| d);
+                    writeln!(f, " = CallDef({id:?} {def}, {args:?}, {skip}, {tr:?})")
+                }
+                Term::Native(id, args) => {
+                    let native = lut.natives.get(*id).map_or("?", |n| n);
+                    writeln!(f, " = Native({native}, {args:?})")
+                }
+                t => writeln!(f, " = {t:?}"),
+            }?;
+        }
+        Ok(())
+    }
+}
+
 /// jq program compiler.
 ///
 /// This contains strings of type `S` and native functions of type `F`.
@@ -210,7 +352,10 @@ pub struct Compiler<S, F> {
     /// `tailrecs` stores every tail-recursive definition `id`
     tailrecs: BTreeSet<TermId>,
 
-    errs: Vec<Error<S>>,
+    errs: Vec<Hinted<S>>,
+
+    /// code of the module that is currently compiled
+    code: S,
 }
 
 // TODO: remove S: Default
@@ -226,6 +371,7 @@ impl<S: Default, F> Default for Compiler<S, F> {
             tailrecs: BTreeSet::new(),
             locals: Locals::default(),
             errs: Vec::new(),
+            code: S::default(),
         }
     }
 }
@@ -261,6 +407,12 @@ struct Def {
     tailrec: bool,
 }
 
+impl<S: Display, A> Display for Sig<S, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.name, self.args.len())
+    }
+}
+
 impl<S: Eq, A> Sig<S, A> {
     fn matches(&self, name: S, args: &[TermId]) -> bool {
         name == self.name && args.len() == self.args.len()
@@ -498,9 +650,19 @@ impl<'s, F> Compiler<&'s str, F> {
 
     /// Compile the given modules.
     pub fn compile<P>(
-        mut self,
+        self,
         mods: load::Modules<&'s str, P>,
     ) -> Result<Filter<F>, Errors<&'s str, P>> {
+        let errs = |(file, errs): (_, Vec<_>)| (file, errs.into_iter().map(|(e, _)| e).collect());
+        self.compile_with_hints(mods)
+            .map_err(|e| e.into_iter().map(errs).collect())
+    }
+
+    /// Compile the given modules, returning every error together with a hint to fix it.
+    pub fn compile_with_hints<P>(
+        mut self,
+        mods: load::Modules<&'s str, P>,
+    ) -> Result<Filter<F>, Errors<&'s str, P, Hinted<&'s str>>> {
         self.imported_vars = mods
             .iter()
             .enumerate()
@@ -509,6 +671,7 @@ impl<'s, F> Compiler<&'s str, F> {
 
         let mut errs = Vec::new();
         for (file, m) in mods {
+            self.code = file.code;
             self.module(m);
             if !self.errs.is_empty() {
                 errs.push((file, core::mem::take(&mut self.errs)));
@@ -543,6 +706,8 @@ impl<'s, F> Compiler<&'s str, F> {
             assert!(main_sig.matches("main", &[]));
             assert!(!main_def.rec && main_def.tailrec);
             //std::println!("main: {:?}", main_def.id);
+            let natives = self.lut.funs.iter().map(|(sig, _f)| sig.to_string());
+            self.lut.natives = natives.collect();
             Ok(Filter(main_def.id, self.lut.map_funs(|(_sig, f)| f)))
         } else {
             Err(errs)
@@ -609,7 +774,10 @@ impl<'s, F> Compiler<&'s str, F> {
         self.locals.push_parent(d.name, args, def);
         // at the beginning, we assume that any function can call itself tail-recursively
         assert!(tr.insert(tid));
-        self.lut.terms[tid.0] = self.term(d.body, &tr);
+        let span = self.term_span(&d.body);
+        let body = self.term(d.body.term, &tr);
+        self.set_span(tid, span);
+        self.lut.terms[tid.0] = body;
         let def = self.locals.pop_parent(d.name, d.args.len());
         // only if there is at least one recursive call and all calls are tail-recursive,
         // then the definition is tail-recursive
@@ -620,6 +788,7 @@ impl<'s, F> Compiler<&'s str, F> {
             name: d.name,
             args: d.args.iter().map(|a| bind_from(a, ())).collect(),
         };
+        self.lut.defs.insert(tid, sig.to_string());
         (sig, def)
     }
 
@@ -628,7 +797,11 @@ impl<'s, F> Compiler<&'s str, F> {
         match t {
             Id => Term::Id,
             Recurse => self.term(Call("!recurse", Vec::new()), &Tr::new()),
-            Arr(t) => Term::Arr(self.iterm(t.map_or_else(|| Call("!empty", Vec::new()), |t| *t))),
+            Arr(Some(t)) => Term::Arr(self.iterm(*t)),
+            Arr(None) => {
+                let empty = self.term(Call("!empty", Vec::new()), &Tr::new());
+                Term::Arr(self.lut.insert_term(empty))
+            }
             Neg(t) => Term::Neg(self.iterm(*t)),
             Pipe(l, None, r) => Term::Pipe(self.iterm(*l), None, self.iterm_tr(*r, tr)),
             Pipe(l, Some(pat), r) => {
@@ -639,7 +812,7 @@ impl<'s, F> Compiler<&'s str, F> {
             Label(x, t) => Term::Label(self.with_label(x, |c| c.iterm(*t))),
             Break(x) => self.break_(x),
             IfThenElse(if_thens, else_) => {
-                let else_ = else_.map_or(Term::Id, |else_| self.term(*else_, tr));
+                let else_ = else_.map_or(Term::Id, |else_| self.term(else_.term, tr));
                 if_thens.into_iter().rev().fold(else_, |acc, (if_, then_)| {
                     Term::Ite(
                         self.iterm(if_),
@@ -659,7 +832,7 @@ impl<'s, F> Compiler<&'s str, F> {
             }
             Def(defs, t) => {
                 defs.iter().for_each(|def| self.def_pre(def));
-                let t = self.term(*t, tr);
+                let t = self.term(t.term, tr);
                 // we have to process the siblings in *reverse*, because that way,
                 // all potential call-sites of a sibling are processed before the sibling itself
                 // (because a sibling can only be called by functions *after* it, not before it)
@@ -674,11 +847,16 @@ impl<'s, F> Compiler<&'s str, F> {
             // map `try f catch g` to `label $x | try f catch (g, break $x)`
             // and `try f` or `f?` to `label $x | try f catch (   break $x)`
             TryCatch(try_, catch) => {
-                let catch = match catch {
-                    None => Break(""),
-                    Some(c) => BinOp(c, parse::BinaryOp::Comma, Break("").into()),
-                };
-                let tc = self.with_label("", |c| Term::TryCatch(c.iterm(*try_), c.iterm(catch)));
+                let tc = self.with_label("", |c| {
+                    let try_ = c.iterm(*try_);
+                    let catch = catch.map(|catch| c.iterm(*catch));
+                    let break_ = c.break_("");
+                    let catch = match catch {
+                        None => break_,
+                        Some(catch) => Term::Comma(catch, c.lut.insert_term(break_)),
+                    };
+                    Term::TryCatch(try_, c.lut.insert_term(catch))
+                });
                 Term::Label(self.lut.insert_term(tc))
             }
             Fold(name, xs, pat, args) => {
@@ -733,7 +911,7 @@ impl<'s, F> Compiler<&'s str, F> {
             Str(fmt, parts) => {
                 use lex::StrPart;
                 let fmt = match fmt {
-                    Some(fmt) => self.iterm(Call(fmt, Vec::new())),
+                    Some(fmt) => self.iterm(parse::Spanned::token(Call(fmt, Vec::new()), fmt)),
                     None => self.lut.insert_term(Term::ToString),
                 };
                 let parts = parts.into_iter().map(|part| match part {
@@ -754,15 +932,44 @@ impl<'s, F> Compiler<&'s str, F> {
     /// Compile a term in a context that does *not* permit tail-recursion.
     ///
     /// One example of such a term is `t` in `1 + t` or `t | .+1`.
-    fn iterm(&mut self, t: parse::Term<&'s str>) -> TermId {
+    fn iterm(&mut self, t: parse::Spanned<&'s str>) -> TermId {
         // if anything in our term calls an ancestor of our term, then we know that
         // this ancestor cannot be tail-recursive!
         self.iterm_tr(t, &Tr::new())
     }
 
-    fn iterm_tr(&mut self, t: parse::Term<&'s str>, tr: &Tr) -> TermId {
-        let t = self.term(t, tr);
-        self.lut.insert_term(t)
+    fn iterm_tr(&mut self, t: parse::Spanned<&'s str>, tr: &Tr) -> TermId {
+        let span = self.term_span(&t);
+        let t = self.term(t.term, tr);
+        let tid = self.lut.insert_term(t);
+        self.set_span(tid, span);
+        tid
+    }
+
+    fn set_span(&mut self, tid: TermId, span: Option<Span>) {
+        if let Some(span) = span {
+            self.lut.spans.insert(tid, span);
+        }
+    }
+
+    /// Return the location of a term.
+    ///
+    /// Because a definition is compiled to its body,
+    /// the location of `def f: 1; f` is the location of `f`.
+    fn term_span(&self, mut t: &parse::Spanned<&'s str>) -> Option<Span> {
+        while let parse::Term::Def(_, body) = &t.term {
+            t = body
+        }
+        self.span(&t.span)
+    }
+
+    /// Return the location of a term that spans from the token `first` to the token `last`.
+    fn span(&self, (first, last): &(&'s str, &'s str)) -> Option<Span> {
+        // tokens such as `!empty` are not part of the code
+        let offset = |s: &str| (s.as_ptr() as usize).checked_sub(self.code.as_ptr() as usize);
+        let range = offset(first)?..offset(last)? + last.len();
+        let valid = range.start <= range.end && range.end <= self.code.len();
+        valid.then_some((self.mod_map.len(), range))
     }
 
     fn pattern(&mut self, p: parse::Pattern<&'s str>) -> Pattern<TermId> {
@@ -783,7 +990,8 @@ impl<'s, F> Compiler<&'s str, F> {
     }
 
     fn fail(&mut self, name: &'s str, undef: Undefined) -> Term {
-        self.errs.push((name, undef));
+        let hint = self.hint(name, &undef);
+        self.errs.push(((name, undef), hint));
         Term::default()
     }
 
@@ -803,7 +1011,11 @@ impl<'s, F> Compiler<&'s str, F> {
         if let Some(call) = self.call_mod_id(*mid, name, args) {
             return call;
         }
-        self.fail(name, Undefined::Filter(args.len()))
+        let defs = self.mod_map[*mid].iter();
+        let hint = Hint::filter(name, defs.map(|(sig, _def)| (sig.name, sig.args.len())));
+        self.errs
+            .push(((name, Undefined::Filter(args.len())), hint));
+        Term::default()
     }
 
     /// Resolve call to `filter(a1, ..., an)`.
@@ -826,26 +1038,83 @@ impl<'s, F> Compiler<&'s str, F> {
     }
 
     fn var(&mut self, x: &'s str) -> Term {
+        if let Some(v) = self.var_id(x) {
+            return Term::Var(v);
+        }
+        if x == "$__break__" {
+            return Term::Breakpoint(self.visible_vars());
+        }
+        self.fail(x, Undefined::Var)
+    }
+
+    fn var_id(&self, x: &'s str) -> Option<VarId> {
         let mut i = self.locals.vars.total;
 
         if let Some(v) = self.locals.vars.bound.get_last(&Bind::Var(x)) {
-            return Term::Var(i - v);
+            return Some(i - v);
         }
         for (x_, mid) in self.imported_vars.iter().rev() {
             if x == *x_ && *mid == self.mod_map.len() {
-                return Term::Var(i);
+                return Some(i);
             } else {
                 i += 1;
             }
         }
         for x_ in self.global_vars.iter().rev() {
             if x == *x_ {
-                return Term::Var(i);
+                return Some(i);
             } else {
                 i += 1;
             }
         }
-        self.fail(x, Undefined::Var)
+        None
+    }
+
+    /// Return the names and IDs of all variables that can be referred to at this point.
+    fn visible_vars(&self) -> Box<[(String, VarId)]> {
+        let vars = self.var_names().into_iter();
+        let vars = vars.filter_map(|x| Some((x.to_string(), self.var_id(x)?)));
+        vars.collect()
+    }
+
+    /// Return the names of all variables that can be referred to at this point.
+    fn var_names(&self) -> BTreeSet<&'s str> {
+        let locals = self.locals.vars.bound.0.keys().filter_map(|b| match b {
+            Bind::Var(x) => Some(*x),
+            _ => None,
+        });
+        let mid = self.mod_map.len();
+        let imported = self.imported_vars.iter().filter(|(_, m)| *m == mid);
+        let imported = imported.map(|(x, _)| *x);
+        locals
+            .chain(imported)
+            .chain(self.global_vars.iter().copied())
+            .collect()
+    }
+
+    /// Return hints for the undefined symbol `name` that is referred to at this point.
+    fn hint(&self, name: &str, undef: &Undefined) -> Hint {
+        let labels = self.locals.vars.bound.0.keys().filter_map(|b| match b {
+            // the empty label is used internally for `try`
+            Bind::Label(l) if !l.is_empty() => Some(*l),
+            _ => None,
+        });
+        let locals = self.locals.funs.0.keys().copied();
+        let included = self
+            .included_mods
+            .iter()
+            .flat_map(|mid| &self.mod_map[*mid]);
+        let included = included.map(|(sig, _def)| sig);
+        let natives = self.lut.funs.iter().map(|(sig, _f)| sig);
+        let globals = included
+            .chain(natives)
+            .map(|sig| (sig.name, sig.args.len()));
+        match undef {
+            Undefined::Mod => Hint::similar(name, self.imported_mods.iter().map(|(_, m)| *m)),
+            Undefined::Var => Hint::similar(name, self.var_names().into_iter()),
+            Undefined::Label => Hint::similar(name, labels),
+            Undefined::Filter(_) => Hint::filter(name, locals.chain(globals)),
+        }
     }
 
     fn break_(&mut self, x: &'s str) -> Term {
@@ -855,11 +1124,21 @@ impl<'s, F> Compiler<&'s str, F> {
         self.fail(x, Undefined::Label)
     }
 
-    fn obj_entry(&mut self, k: parse::Term<&'s str>, v: Option<parse::Term<&'s str>>) -> Term {
+    fn obj_entry(
+        &mut self,
+        k: parse::Spanned<&'s str>,
+        v: Option<parse::Spanned<&'s str>>,
+    ) -> Term {
         let (k, v) = match (k, v) {
-            (parse::Term::Var(x), None) => (
+            (
+                k @ parse::Spanned {
+                    term: parse::Term::Var(x),
+                    ..
+                },
+                None,
+            ) => (
                 self.lut.insert_term(Term::Str(x[1..].into())),
-                self.iterm(parse::Term::Var(x)),
+                self.iterm(k),
             ),
             (k, None) => {
                 use crate::path::{Part, Path};
@@ -1,6 +1,8 @@
 //! Exceptions and errors.
 
-use alloc::{string::String, string::ToString, vec::Vec};
+use crate::compile::{Lut, Span, Term, TermId};
+use crate::filter::Calls;
+use alloc::{boxed::Box, string::String, string::ToString, vec::Vec};
 use core::fmt::{self, Display};
 
 /// Exception.
@@ -12,28 +14,82 @@ pub struct Exn<'a, V>(pub(crate) Inner<'a, V>);
 
 #[derive(Clone, Debug)]
 pub(crate) enum Inner<'a, V> {
-    Err(Error<V>),
+    /// Error, with the term that caused it and the calls that led to it, if known
+    ///
+    /// This is boxed so that errors take no more space than tail calls.
+    Err(Box<(Error<V>, Option<Loc<'a>>)>),
     /// Tail-recursive call.
     ///
     /// This is used internally to execute tail-recursive filters.
     /// If this can be observed by users, then this is a bug.
-    TailCall(&'a crate::compile::TermId, crate::filter::Vars<'a, V>, V),
+    TailCall(&'a TermId, crate::filter::Vars<'a, V>, V),
     Break(usize),
 }
 
-impl<V> Exn<'_, V> {
+/// Term that caused an error, and the calls that led to it.
+pub(crate) type Loc<'a> = (&'a TermId, Calls<'a>);
+
+impl<'a, V> Exn<'a, V> {
     /// If the exception is an error, yield it, else yield the exception.
-    pub(crate) fn get_err(self) -> Result<Error<V>, Self> {
+    pub(crate) fn get_err(self) -> Result<(Error<V>, Option<Loc<'a>>), Self> {
         match self.0 {
-            Inner::Err(e) => Ok(e),
+            Inner::Err(e) => Ok(*e),
             _ => Err(self),
         }
     }
+
+    /// If the exception is an error without location,
+    /// record that it was caused by the term `id` in the calls `calls`.
+    pub(crate) fn at(mut self, id: &'a TermId, calls: &Calls<'a>) -> Self {
+        if let Inner::Err(e) = &mut self.0 {
+            e.1.get_or_insert_with(|| (id, calls.clone()));
+        }
+        self
+    }
 }
 
 impl<V> From<Error<V>> for Exn<'_, V> {
     fn from(e: Error<V>) -> Self {
-        Exn(Inner::Err(e))
+        Exn(Inner::Err(Box::new((e, None))))
+    }
+}
+
+/// Location where an error occurred, and the calls that led to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Backtrace {
+    /// location of the term that caused the error, if known
+    ///
+    /// If the error was caused in the first module (usually the standard library),
+    /// then this is the location of the innermost call from another module,
+    /// and this call is omitted from `calls`.
+    pub span: Option<Span>,
+    /// name and arity of called definitions, together with the location of their call,
+    /// starting with the innermost call
+    ///
+    /// Tail-recursive calls are only recorded once, and
+    /// calls from the first module are omitted.
+    pub calls: Vec<(String, Span)>,
+}
+
+impl Backtrace {
+    /// Resolve the location of an error to spans in the source code.
+    pub(crate) fn new<F>(lut: &Lut<F>, loc: Option<Loc>) -> Self {
+        let (id, calls) = match loc {
+            Some(loc) => loc,
+            None => return Self::default(),
+        };
+        // spans outside of the first module
+        let span = |id| lut.spans.get(id).filter(|(mid, _)| *mid > 0);
+        let mut calls = calls.iter().filter_map(|call| match &lut.terms[call.0] {
+            Term::CallDef(def, ..) => Some((lut.defs[def].to_string(), span(call)?.clone())),
+            _ => None,
+        });
+        let span = match span(id) {
+            Some(span) => Some(span.clone()),
+            None => calls.next().map(|(_f, span)| span),
+        };
+        let calls = calls.collect();
+        Self { span, calls }
     }
 }
 
@@ -88,6 +144,18 @@ impl<V> Error<V> {
             .into_iter()
             .collect()
     }
+
+    /// Apply a function to all values contained in the error.
+    pub fn map<W>(self, f: impl Fn(V) -> W) -> Error<W> {
+        let part = |p| match p {
+            Part::Val(v) => Part::Val(f(v)),
+            Part::Str(s) => Part::Str(s),
+        };
+        Error(match self.0 {
+            Part::Val(v) => Part::Val(f(v)),
+            Part::Str(parts) => Part::Str(parts.into_iter().map(part).collect()),
+        })
+    }
 }
 
 impl<V: From<String>> Error<V> {
@@ -5,7 +5,7 @@ use crate::compile::{Bind, Fold, Lut, Pattern, Tailrec, Term as Ast, TermId as I
 use crate::fold::fold;
 use crate::val::{ValT, ValX, ValXs};
 use crate::{exn, rc_lazy_list, Bind as Arg, Error, Exn, Inputs, RcList};
-use alloc::boxed::Box;
+use alloc::{boxed::Box, rc::Rc};
 use dyn_clone::DynClone;
 
 // we can unfortunately not make a `Box<dyn ... + Clone>`
@@ -18,6 +18,21 @@ dyn_clone::clone_trait_object!(<'a, V> Update<'a, V>);
 
 type BoxUpdate<'a, V> = Box<dyn Update<'a, V> + 'a>;
 
+/// Yield the outputs of `$ys`, recording them for `$key` if profiling is enabled.
+macro_rules! profile {
+    ($key:expr, $ys:expr) => {{
+        #[cfg(feature = "profile")]
+        {
+            use crate::profile::Key::*;
+            crate::profile::call($key, || $ys)
+        }
+        #[cfg(not(feature = "profile"))]
+        {
+            $ys
+        }
+    }};
+}
+
 type Results<'a, T, V> = box_iter::Results<'a, T, Exn<'a, V>>;
 
 /// List of bindings.
@@ -30,6 +45,21 @@ impl<'a, V> Vars<'a, V> {
     }
 }
 
+/// Call sites of the definitions that are currently run, starting with the innermost one.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Calls<'a>(Option<Rc<(&'a Id, Self)>>);
+
+impl<'a> Calls<'a> {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &'a Id> + '_ {
+        let mut calls = self;
+        core::iter::from_fn(move || {
+            let (call, rest) = &**calls.0.as_ref()?;
+            calls = rest;
+            Some(*call)
+        })
+    }
+}
+
 /// Filter execution context.
 #[derive(Clone)]
 pub struct Ctx<'a, V> {
@@ -39,6 +69,8 @@ pub struct Ctx<'a, V> {
     /// This is used to create fresh break IDs.
     labels: usize,
     inputs: &'a Inputs<'a, V>,
+    /// This is used to locate errors.
+    calls: Calls<'a>,
 }
 
 impl<'a, V> Ctx<'a, V> {
@@ -48,6 +80,7 @@ impl<'a, V> Ctx<'a, V> {
             vars: Vars(RcList::new().extend(vars.into_iter().map(Bind::Var))),
             labels: 0,
             inputs,
+            calls: Calls::default(),
         }
     }
 
@@ -69,6 +102,12 @@ impl<'a, V> Ctx<'a, V> {
         self
     }
 
+    /// Record a call to a definition.
+    fn cons_call(mut self, call: &'a Id) -> Self {
+        self.calls = Calls(Some(Rc::new((call, core::mem::take(&mut self.calls)))));
+        self
+    }
+
     /// Remove the `skip` most recent variable bindings.
     fn skip_vars(mut self, skip: usize) -> Self {
         if skip > 0 {
@@ -83,6 +122,7 @@ impl<'a, V> Ctx<'a, V> {
             vars,
             labels: self.labels,
             inputs: self.inputs,
+            calls: self.calls.clone(),
         }
     }
 
@@ -125,23 +165,25 @@ impl<'a, V: Clone> Ctx<'a, V> {
 fn bind_vars<'a, F: FilterT>(
     args: &'a [Arg<Id>],
     lut: &'a Lut<F>,
-    ctx: Ctx<'a, F::V>,
+    mut ctx: Ctx<'a, F::V>,
     cv: Cv<'a, F::V>,
 ) -> Results<'a, Cv<'a, F::V>, F::V> {
-    match args.split_first() {
-        Some((Arg::Var(arg), [])) => {
-            map_with(arg.run(lut, cv.clone()), (ctx, cv.1), |y, (ctx, v)| {
-                Ok((ctx.cons_var(y?), v))
-            })
-        }
-        Some((Arg::Fun(arg), [])) => box_once(Ok((ctx.cons_fun((arg, cv.0)), cv.1))),
-        Some((Arg::Var(arg), rest)) => {
-            flat_map_then_with(arg.run(lut, cv.clone()), (ctx, cv), |y, (ctx, cv)| {
-                bind_vars(rest, lut, ctx.cons_var(y), cv)
-            })
+    let mut args = args.iter();
+    // bind filter arguments without recursion
+    let arg = loop {
+        match args.next() {
+            Some(Arg::Fun(arg)) => ctx = ctx.cons_fun((arg, cv.0.clone())),
+            Some(Arg::Var(arg)) => break arg,
+            None => return box_once(Ok((ctx, cv.1))),
         }
-        Some((Arg::Fun(arg), rest)) => bind_vars(rest, lut, ctx.cons_fun((arg, cv.0.clone())), cv),
-        None => box_once(Ok((ctx, cv.1))),
+    };
+    match args.as_slice() {
+        [] => map_with(arg.run(lut, cv.clone()), (ctx, cv.1), |y, (ctx, v)| {
+            Ok((ctx.cons_var(y?), v))
+        }),
+        rest => flat_map_then_with(arg.run(lut, cv.clone()), (ctx, cv), |y, (ctx, cv)| {
+            bind_vars(rest, lut, ctx.cons_var(y), cv)
+        }),
     }
 }
 
@@ -307,28 +349,39 @@ impl<F: FilterT<F>> FilterT<F> for Id {
     fn run<'a>(&'a self, lut: &'a Lut<F>, cv: Cv<'a, Self::V>) -> ValXs<'a, Self::V> {
         use alloc::string::ToString;
         use core::iter::once;
-        match &lut.terms[self.0] {
+        #[cfg(feature = "trace")]
+        let span = crate::trace::enter(|| lut.spans.get(self), &cv.1);
+        // locate errors that are caused by this term
+        let at = move |calls: Calls<'a>| move |e: Error<Self::V>| Exn::from(e).at(self, &calls);
+        let ys = match &lut.terms[self.0] {
             Ast::Id => box_once(Ok(cv.1)),
             Ast::ToString => box_once(match cv.1.as_str() {
                 Some(_) => Ok(cv.1),
                 None => Ok(Self::V::from(cv.1.to_string())),
             }),
             Ast::Int(n) => box_once(Ok(Self::V::from(*n))),
-            Ast::Num(x) => box_once(Self::V::from_num(x).map_err(Exn::from)),
+            Ast::Num(x) => box_once(Self::V::from_num(x).map_err(at(cv.0.calls))),
             Ast::Str(s) => box_once(Ok(Self::V::from(s.clone()))),
             Ast::Arr(f) => box_once(f.run(lut, cv).collect()),
             Ast::ObjEmpty => box_once(Self::V::from_map([]).map_err(Exn::from)),
-            Ast::ObjSingle(k, v) => Box::new(
-                Self::cartesian(k, v, lut, cv).map(|(k, v)| Ok(Self::V::from_map([(k?, v?)])?)),
-            ),
+            Ast::ObjSingle(k, v) => {
+                let at = at(cv.0.calls.clone());
+                Box::new(
+                    Self::cartesian(k, v, lut, cv)
+                        .map(move |(k, v)| Self::V::from_map([(k?, v?)]).map_err(&at)),
+                )
+            }
             // TODO: write test for `try (break $x)`
             Ast::TryCatch(f, c) => {
                 Box::new(f.run(lut, (cv.0.clone(), cv.1)).flat_map(move |y| match y {
-                    Err(Exn(exn::Inner::Err(e))) => c.run(lut, (cv.0.clone(), e.into_val())),
+                    Err(Exn(exn::Inner::Err(e))) => c.run(lut, (cv.0.clone(), e.0.into_val())),
                     y => box_once(y),
                 }))
             }
-            Ast::Neg(f) => Box::new(f.run(lut, cv).map(|v| Ok((-v?)?))),
+            Ast::Neg(f) => {
+                let at = at(cv.0.calls.clone());
+                Box::new(f.run(lut, cv).map(move |v| (-v?).map_err(&at)))
+            }
 
             // `l | r`
             Ast::Pipe(l, None, r) => {
@@ -358,17 +411,7 @@ impl<F: FilterT<F>> FilterT<F> for Id {
             Ast::Ite(if_, then_, else_) => if_.pipe(lut, cv, move |cv, v| {
                 if v.as_bool() { then_ } else { else_ }.run(lut, cv)
             }),
-            Ast::Path(f, path) => {
-                let path = path.map_ref(|i| {
-                    let cv = cv.clone();
-                    crate::into_iter::collect_if_once(move || i.run(lut, cv))
-                });
-                flat_map_then_with(f.run(lut, cv), path, |y, path| {
-                    flat_map_then_with(path.explode(), y, |path, y| {
-                        Box::new(path.run(y).map(|r| r.map_err(Exn::from)))
-                    })
-                })
-            }
+            Ast::Path(f, path) => run_path(lut, self, (f, path), cv),
 
             Ast::Update(path, f) => path.update(
                 lut,
@@ -376,10 +419,11 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                 Box::new(move |v| f.run(lut, (cv.0.clone(), v))),
             ),
             Ast::UpdateMath(path, op, f) => f.pipe(lut, cv, move |cv, y| {
+                let at = at(cv.0.calls.clone());
                 path.update(
                     lut,
                     cv,
-                    Box::new(move |x| box_once(op.run(x, y.clone()).map_err(Exn::from))),
+                    Box::new(move |x| box_once(op.run(x, y.clone()).map_err(&at))),
                 )
             }),
             Ast::UpdateAlt(path, f) => f.pipe(lut, cv, move |cv, y| {
@@ -401,26 +445,17 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                 }
             }),
             Ast::Math(l, op, r) => {
-                Box::new(Self::cartesian(l, r, lut, cv).map(|(x, y)| Ok(op.run(x?, y?)?)))
+                let at = at(cv.0.calls.clone());
+                Box::new(
+                    Self::cartesian(l, r, lut, cv).map(move |(x, y)| op.run(x?, y?).map_err(&at)),
+                )
             }
             Ast::Cmp(l, op, r) => Box::new(
                 Self::cartesian(l, r, lut, cv).map(|(x, y)| Ok(Self::V::from(op.run(&x?, &y?)))),
             ),
 
             Ast::Fold(xs, pat, init, update, fold_type) => {
-                let xs = rc_lazy_list::List::from_iter(run_and_bind(xs, lut, cv.clone(), pat));
-                let init = init.run(lut, cv.clone());
-                let update = |ctx, v| update.run(lut, (ctx, v));
-                let inner = |_, y: &Self::V| Some(y.clone());
-                let inner_proj = |ctx, y: &Self::V| Some((ctx, y.clone()));
-                flat_map_then_with(init, xs, move |i, xs| match fold_type {
-                    Fold::Reduce => Box::new(fold(xs, i, update, |_| (), |_, _| None, Some)),
-                    Fold::Foreach(None) => Box::new(fold(xs, i, update, |_| (), inner, |_| None)),
-                    Fold::Foreach(Some(proj)) => flat_map_then(
-                        fold(xs, i, update, |ctx| ctx.clone(), inner_proj, |_| None),
-                        |(ctx, y)| proj.run(lut, (ctx, y)),
-                    ),
-                })
+                run_fold(lut, (xs, pat, init, update, fold_type), cv)
             }
 
             Ast::Var(v) => match cv.0.vars.get(*v).unwrap() {
@@ -429,33 +464,9 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                 Bind::Label(l) => box_once(Err(Exn(exn::Inner::Break(*l)))),
             },
             Ast::CallDef(id, args, skip, tailrec) => {
-                use core::ops::ControlFlow;
-                let with_vars = move |vars| Ctx {
-                    vars,
-                    labels: cv.0.labels,
-                    inputs: cv.0.inputs,
-                };
-                let cvs = bind_vars(args, lut, cv.0.clone().skip_vars(*skip), cv);
-                match tailrec {
-                    None => flat_map_then(cvs, |cv| id.run(lut, cv)),
-                    Some(Tailrec::Catch) => Box::new(crate::Stack::new(
-                        [flat_map_then(cvs, |cv| id.run(lut, cv))].into(),
-                        move |r| match r {
-                            Err(Exn(exn::Inner::TailCall(id_, vars, v))) if id == id_ => {
-                                ControlFlow::Continue(id.run(lut, (with_vars(vars), v)))
-                            }
-                            Ok(_) | Err(_) => ControlFlow::Break(r),
-                        },
-                    )),
-                    Some(Tailrec::Throw) => Box::new(cvs.map(move |cv| {
-                        cv.and_then(|cv| Err(Exn(exn::Inner::TailCall(id, cv.0.vars, cv.1))))
-                    })),
-                }
-            }
-            Ast::Native(id, args) => {
-                let cvs = bind_vars(args, lut, Ctx::new([], cv.0.inputs), cv);
-                flat_map_then(cvs, |cv| lut.funs[*id].run(lut, cv))
+                call_def(lut, self, (id, args, *skip, tailrec), cv)
             }
+            Ast::Native(id, args) => call_native(lut, self, (*id, args), cv),
             Ast::Label(id) => {
                 let ctx = cv.0.cons_label();
                 let labels = ctx.labels;
@@ -464,7 +475,15 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                     y => Some(y),
                 }))
             }
-        }
+            Ast::Breakpoint(_vars) => {
+                #[cfg(feature = "trace")]
+                breakpoint(|| lut.spans.get(self), _vars, &cv);
+                box_once(Ok(cv.1))
+            }
+        };
+        #[cfg(feature = "trace")]
+        let ys = crate::trace::leave(span, ys);
+        ys
     }
 
     fn update<'a>(
@@ -484,7 +503,7 @@ impl<F: FilterT<F>> FilterT<F> for Id {
             // I do not see how to implement this in jaq
             Ast::TryCatch(..) | Ast::Label(..) => err,
 
-            Ast::Id => f(cv.1),
+            Ast::Id | Ast::Breakpoint(_) => f(cv.1),
             Ast::Path(l, path) => {
                 let path = path.map_ref(|i| {
                     let cv = cv.clone();
@@ -546,6 +565,139 @@ impl<F: FilterT<F>> FilterT<F> for Id {
     }
 }
 
+// The following functions are not inlined into `run` in order to
+// keep its stack frame small, which matters for deeply recursive filters.
+
+/// Run the path `path` on the outputs of `f`, where both belong to the term `id`.
+fn run_path<'a, F: FilterT<F>>(
+    lut: &'a Lut<F>,
+    id: &'a Id,
+    (f, path): (&'a Id, &'a crate::path::Path<Id>),
+    cv: Cv<'a, F::V>,
+) -> ValXs<'a, F::V> {
+    let path = path.map_ref(|i| {
+        let cv = cv.clone();
+        crate::into_iter::collect_if_once(move || i.run(lut, cv))
+    });
+    let calls = cv.0.calls.clone();
+    flat_map_then_with(f.run(lut, cv), path, move |y, path| {
+        let calls = calls.clone();
+        flat_map_then_with(path.explode(), y, move |path, y| {
+            let calls = calls.clone();
+            Box::new(
+                path.run(y)
+                    .map(move |r| r.map_err(|e| Exn::from(e).at(id, &calls))),
+            )
+        })
+    })
+}
+
+/// Run a `reduce` or `foreach` term.
+fn run_fold<'a, F: FilterT<F>>(
+    lut: &'a Lut<F>,
+    (xs, pat, init, update, fold_type): (&'a Id, &'a Pattern<Id>, &'a Id, &'a Id, &'a Fold<Id>),
+    cv: Cv<'a, F::V>,
+) -> ValXs<'a, F::V> {
+    let xs = rc_lazy_list::List::from_iter(run_and_bind(xs, lut, cv.clone(), pat));
+    let init = init.run(lut, cv.clone());
+    let update = |ctx, v| update.run(lut, (ctx, v));
+    let inner = |_, y: &F::V| Some(y.clone());
+    let inner_proj = |ctx, y: &F::V| Some((ctx, y.clone()));
+    flat_map_then_with(init, xs, move |i, xs| match fold_type {
+        Fold::Reduce => Box::new(fold(xs, i, update, |_| (), |_, _| None, Some)),
+        Fold::Foreach(None) => Box::new(fold(xs, i, update, |_| (), inner, |_| None)),
+        Fold::Foreach(Some(proj)) => flat_map_then(
+            fold(xs, i, update, |ctx| ctx.clone(), inner_proj, |_| None),
+            |(ctx, y)| proj.run(lut, (ctx, y)),
+        ),
+    })
+}
+
+/// Run the definition with body `id`, which is called by the term `call`.
+fn call_def<'a, F: FilterT<F>>(
+    lut: &'a Lut<F>,
+    call: &'a Id,
+    (id, args, skip, tailrec): (&'a Id, &'a [Arg<Id>], usize, &'a Option<Tailrec>),
+    cv: Cv<'a, F::V>,
+) -> ValXs<'a, F::V> {
+    let ctx = cv.0.clone().skip_vars(skip);
+    match tailrec {
+        None => flat_map_then(bind_vars(args, lut, ctx.cons_call(call), cv), move |cv| {
+            profile!(Def(*id), id.run(lut, cv))
+        }),
+        Some(tailrec) => call_tailrec(lut, call, (id, args, tailrec), ctx, cv),
+    }
+}
+
+/// Run a tail-recursive definition with body `id`.
+fn call_tailrec<'a, F: FilterT<F>>(
+    lut: &'a Lut<F>,
+    call: &'a Id,
+    (id, args, tailrec): (&'a Id, &'a [Arg<Id>], &'a Tailrec),
+    ctx: Ctx<'a, F::V>,
+    cv: Cv<'a, F::V>,
+) -> ValXs<'a, F::V> {
+    use core::ops::ControlFlow;
+    let run = move |cv| profile!(Def(*id), id.run(lut, cv));
+    match tailrec {
+        Tailrec::Catch => {
+            let ctx = ctx.cons_call(call);
+            let ctx_ = ctx.clone();
+            Box::new(crate::Stack::new(
+                [flat_map_then(bind_vars(args, lut, ctx, cv), run)].into(),
+                move |r| match r {
+                    Err(Exn(exn::Inner::TailCall(id_, vars, v))) if id == id_ => {
+                        ControlFlow::Continue(run((ctx_.with_vars(vars), v)))
+                    }
+                    Ok(_) | Err(_) => ControlFlow::Break(r),
+                },
+            ))
+        }
+        // this is not a real call, so we do not record it
+        Tailrec::Throw => {
+            Box::new(bind_vars(args, lut, ctx, cv).map(move |cv| {
+                cv.and_then(|cv| Err(Exn(exn::Inner::TailCall(id, cv.0.vars, cv.1))))
+            }))
+        }
+    }
+}
+
+/// Run the native filter with index `id`, which is called by the term `call`.
+fn call_native<'a, F: FilterT<F>>(
+    lut: &'a Lut<F>,
+    call: &'a Id,
+    (id, args): (usize, &'a [Arg<Id>]),
+    cv: Cv<'a, F::V>,
+) -> ValXs<'a, F::V> {
+    let calls = cv.0.calls.clone();
+    let ctx = Ctx::new([], cv.0.inputs);
+    let run = move |cv| profile!(Native(id), lut.funs[id].run(lut, cv));
+    let ys = if args.is_empty() {
+        run((ctx, cv.1))
+    } else {
+        flat_map_then(bind_vars(args, lut, ctx, cv), run)
+    };
+    // native filters cannot locate their errors themselves
+    Box::new(ys.map(move |y| y.map_err(|e| e.at(call, &calls))))
+}
+
+/// Trace the values of the given variables at a breakpoint.
+#[cfg(feature = "trace")]
+fn breakpoint<'a, V: ValT>(
+    span: impl FnOnce() -> Option<&'a crate::compile::Span>,
+    vars: &[(alloc::string::String, usize)],
+    cv: &Cv<V>,
+) {
+    if let Some(span) = span().filter(|_| crate::trace::enabled()) {
+        let vars = vars.iter().filter_map(|(x, i)| match cv.0.vars.get(*i)? {
+            Bind::Var(v) => Some((&**x, v as &dyn core::fmt::Display)),
+            _ => None,
+        });
+        let vars: alloc::vec::Vec<_> = vars.collect();
+        crate::trace::breakpoint(span, &cv.1, &vars);
+    }
+}
+
 /// Function from a value to a stream of value results.
 ///
 /// `F` is the type of (natively implemented) filter functions.
@@ -59,14 +59,18 @@ mod into_iter;
 pub mod load;
 pub mod ops;
 pub mod path;
+#[cfg(feature = "profile")]
+pub mod profile;
 mod rc_iter;
 mod rc_lazy_list;
 mod rc_list;
 mod stack;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub mod val;
 
 pub use compile::Compiler;
-pub use exn::{Error, Exn};
+pub use exn::{Backtrace, Error, Exn};
 pub use filter::{Ctx, Cv, FilterT, Native, RunPtr, UpdatePtr};
 pub use rc_iter::RcIter;
 pub use val::{ValR, ValT, ValX, ValXs};
@@ -122,12 +126,60 @@ impl<T> Bind<T, T> {
 #[derive(Debug, Clone)]
 pub struct Filter<F>(compile::TermId, compile::Lut<F>);
 
+impl<F> Filter<F> {
+    /// Return the intermediate representation of the filter for inspection.
+    ///
+    /// This is mostly useful for debugging;
+    /// its format may change between versions.
+    pub fn ir(&self) -> compile::Ir<'_, F> {
+        compile::Ir(self)
+    }
+
+    /// Return and reset the execution statistics recorded on the current thread.
+    ///
+    /// Every definition and native filter is identified by its name and arity;
+    /// definitions are additionally identified by the ID of their body,
+    /// which corresponds to the ID in the [intermediate representation](Self::ir).
+    /// The statistics are sorted by descending time.
+    ///
+    /// Statistics are only recorded when profiling is [enabled](profile::enable).
+    #[cfg(feature = "profile")]
+    pub fn profile(&self) -> alloc::vec::Vec<(String, profile::Stats)> {
+        use profile::Key;
+        let lut = &self.1;
+        let name = |key| match key {
+            Key::Def(id) => alloc::format!("{} {id:?}", lut.defs[&id]),
+            Key::Native(id) => alloc::format!("{} (native)", lut.natives[id]),
+        };
+        let stats = profile::take().into_iter();
+        let mut stats: alloc::vec::Vec<_> = stats.map(|(k, s)| (name(k), s)).collect();
+        stats.sort_by_key(|(_, s)| core::cmp::Reverse(s.time));
+        stats
+    }
+}
+
 impl<F: FilterT> Filter<F> {
     /// Run a filter on given input, yielding output values.
     pub fn run<'a>(&'a self, cv: Cv<'a, F::V>) -> impl Iterator<Item = ValR<F::V>> + 'a {
-        self.0
-            .run(&self.1, cv)
-            .map(|v| v.map_err(|e| e.get_err().ok().unwrap()))
+        let ys = self.0.run(&self.1, cv);
+        ys.map(|v| v.map_err(|e| e.get_err().ok().unwrap().0))
+    }
+
+    /// Run a filter on given input, yielding output values or
+    /// errors together with the location where they occurred.
+    ///
+    /// Locations refer to the modules passed to [`Compiler::compile`].
+    pub fn run_with_backtrace<'a>(
+        &'a self,
+        cv: Cv<'a, F::V>,
+    ) -> impl Iterator<Item = Result<F::V, (Error<F::V>, Backtrace)>> + 'a {
+        let ys = self.0.run(&self.1, cv);
+        ys.map(|v| {
+            v.map_err(|e| {
+                let (e, loc) = e.get_err().ok().unwrap();
+                (e, Backtrace::new(&self.1, loc))
+            })
+        })
     }
 
     /// Run a filter on given input, panic if it does not yield the given output.
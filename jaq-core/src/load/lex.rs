@@ -94,6 +94,8 @@ pub type Error<S> = (Expect<S>, S);
 pub struct Lexer<S> {
     i: S,
     e: Vec<Error<S>>,
+    /// comments, starting with `#` and ending before the final line break
+    comments: Vec<S>,
 }
 
 impl<'a> Lexer<&'a str> {
@@ -101,11 +103,25 @@ impl<'a> Lexer<&'a str> {
     #[must_use]
     pub fn new(i: &'a str) -> Self {
         let e = Vec::new();
-        Self { i, e }
+        let comments = Vec::new();
+        Self { i, e, comments }
     }
 
     /// Lex, returning the resulting tokens and errors.
-    pub fn lex(mut self) -> Result<Vec<Token<&'a str>>, Vec<Error<&'a str>>> {
+    pub fn lex(self) -> Result<Vec<Token<&'a str>>, Vec<Error<&'a str>>> {
+        self.lex_with_comments().map(|(tokens, _comments)| tokens)
+    }
+
+    /// Lex, returning the resulting tokens and comments, or errors.
+    ///
+    /// Every comment starts with `#` and
+    /// spans all lines that end with an odd number of backslashes,
+    /// excluding the final line break.
+    /// This is useful for tools that print programs, such as formatters.
+    #[allow(clippy::type_complexity)]
+    pub fn lex_with_comments(
+        mut self,
+    ) -> Result<(Vec<Token<&'a str>>, Vec<&'a str>), Vec<Error<&'a str>>> {
         let tokens = self.tokens();
         self.space();
         if !self.i.is_empty() {
@@ -113,7 +129,7 @@ impl<'a> Lexer<&'a str> {
         }
 
         if self.e.is_empty() {
-            Ok(tokens)
+            Ok((tokens, self.comments))
         } else {
             Err(self.e)
         }
@@ -154,10 +170,10 @@ impl<'a> Lexer<&'a str> {
     fn space(&mut self) {
         loop {
             self.i = self.i.trim_start();
-            match self.i.strip_prefix('#') {
-                Some(comment) => self.i = comment,
-                None => break,
+            if !self.i.starts_with('#') {
+                break;
             }
+            let start = self.i;
             // ignore all lines that end with an odd number of backslashes
             loop {
                 let (before, after) = self.i.split_once('\n').unwrap_or((self.i, ""));
@@ -168,6 +184,10 @@ impl<'a> Lexer<&'a str> {
                     break;
                 }
             }
+            let comment = &start[..start.len() - self.i.len()];
+            let comment = comment.strip_suffix('\n').unwrap_or(comment);
+            self.comments
+                .push(comment.strip_suffix('\r').unwrap_or(comment));
         }
     }
 
@@ -0,0 +1,454 @@
+//! Static analysis of loaded modules that warns about suspicious code.
+//!
+//! Unlike compilation errors, warnings do not prevent a program from running.
+//! They are returned in the same structure as [compilation errors](crate::compile::Errors),
+//! namely per file, where every warning refers to a part of the file's code.
+use super::parse::{BinaryOp, Def, Pattern, Spanned, Term};
+use super::{lex::StrPart, Errors, Modules};
+use crate::path::{Opt, Part};
+use alloc::vec::Vec;
+
+/// Type of a suspicious piece of code.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Lint {
+    /// definition that is never called
+    UnusedDef,
+    /// parameter that is never used in the body of its definition
+    UnusedParam,
+    /// variable that shadows a variable bound outside of it
+    ShadowedVar,
+    /// definition that shadows a definition of the standard library with the same arity
+    ShadowedStd,
+    /// `catch` of a term that can never fail
+    UnreachableCatch,
+    /// optional iteration over a scalar, such as `1 | .[]?`, which never yields a value
+    ScalarIter,
+    /// usage whose semantics differ between jq and jaq, with an explanation
+    Differs(&'static str),
+}
+
+impl Lint {
+    /// Description of the warning.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnusedDef => "unused definition",
+            Self::UnusedParam => "unused parameter",
+            Self::ShadowedVar => "variable shadows outer variable",
+            Self::ShadowedStd => "definition shadows standard filter",
+            Self::UnreachableCatch => "unreachable catch, because the term cannot fail",
+            Self::ScalarIter => "optional iteration over scalar never yields values",
+            Self::Differs(s) => s,
+        }
+    }
+}
+
+/// Warning, consisting of the code it refers to and its type.
+pub type Warning<S> = (S, Lint);
+
+/// Warnings for multiple modules.
+pub type Warnings<S, P> = Errors<S, P, Vec<Warning<S>>>;
+
+/// Filters whose semantics differ between jq and jaq, with their arities.
+const DIFFERS: [(&str, usize, &str); 3] = [
+    (
+        "input",
+        0,
+        "`input` yields an error in jq, but no value in jaq when there is no more input",
+    ),
+    (
+        "input_line_number",
+        0,
+        "`input_line_number` yields the number of lines read by jq, but the line of the input in jaq",
+    ),
+    (
+        "join",
+        1,
+        "`join` converts elements to strings in jq, but adds them with `+` in jaq",
+    ),
+];
+
+/// Filters that can be used in paths in jq, but not in jaq.
+const PATH_ONLY_JQ: [&str; 3] = ["first", "last", "limit"];
+
+/// Lint all modules except for the standard library, which is the first module.
+///
+/// Only files that have warnings are returned.
+pub fn lint<'s, P: Clone>(mods: &Modules<&'s str, P>) -> Warnings<&'s str, P> {
+    let std = mods
+        .first()
+        .map_or(&[][..], |(_file, prelude)| &prelude.body[..]);
+    let std: Vec<_> = std.iter().map(|d| (d.name, d.args.len())).collect();
+    let last = mods.len().saturating_sub(1);
+    let mut warnings = Vec::new();
+    for (mid, (file, m)) in mods.iter().enumerate().skip(1) {
+        let mut linter = Linter::new(&std);
+        for d in &m.body {
+            // the main filter is not a real definition, see [`Modules`]
+            if mid == last {
+                linter.term(&d.body)
+            } else {
+                // definitions of a module may be used by other modules
+                linter.def(d, true)
+            }
+        }
+        if !linter.warnings.is_empty() {
+            warnings.push((file.clone(), linter.warnings));
+        }
+    }
+    warnings
+}
+
+/// Binding of a filter or a variable, and whether it has been used.
+struct Bound<'s> {
+    name: &'s str,
+    /// number of arguments, or `None` for variables
+    arity: Option<usize>,
+    used: bool,
+}
+
+struct Linter<'a, 's> {
+    std: &'a [(&'s str, usize)],
+    /// bound filters and variables, innermost last
+    scope: Vec<Bound<'s>>,
+    /// positions in `scope` of the definitions whose bodies are traversed
+    ///
+    /// Calls from the body of a definition to itself do not count as use.
+    defining: Vec<usize>,
+    warnings: Vec<Warning<&'s str>>,
+}
+
+impl<'a, 's> Linter<'a, 's> {
+    fn new(std: &'a [(&'s str, usize)]) -> Self {
+        Self {
+            std,
+            scope: Vec::new(),
+            defining: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn warn(&mut self, s: &'s str, lint: Lint) {
+        self.warnings.push((s, lint))
+    }
+
+    fn bind(&mut self, name: &'s str, arity: Option<usize>, used: bool) {
+        self.scope.push(Bound { name, arity, used })
+    }
+
+    fn bind_var(&mut self, x: &'s str) {
+        if self.scope.iter().any(|b| b.arity.is_none() && b.name == x) {
+            self.warn(x, Lint::ShadowedVar)
+        }
+        self.bind(x, None, false)
+    }
+
+    /// Mark the innermost binding with given name and arity as used.
+    ///
+    /// Return false if there is no such binding,
+    /// meaning that the name refers to a filter defined outside the module.
+    fn use_(&mut self, name: &str, arity: Option<usize>) -> bool {
+        let pos = self
+            .scope
+            .iter()
+            .rposition(|b| b.name == name && b.arity == arity);
+        if let Some(pos) = pos.filter(|pos| !self.defining.contains(pos)) {
+            self.scope[pos].used = true
+        }
+        pos.is_some()
+    }
+
+    /// Remove bindings made after `len` bindings, and return them.
+    fn unbind(&mut self, len: usize) -> Vec<Bound<'s>> {
+        self.scope.split_off(len)
+    }
+
+    /// Bind and lint a definition, where `used` indicates whether it is exported.
+    fn def(&mut self, d: &Def<&'s str>, used: bool) {
+        if self.std.contains(&(d.name, d.args.len())) {
+            self.warn(d.name, Lint::ShadowedStd)
+        }
+        // bind the definition before its body to permit recursion
+        self.bind(d.name, Some(d.args.len()), used);
+        let len = self.scope.len();
+        self.defining.push(len - 1);
+        for arg in &d.args {
+            match arg.strip_prefix('$') {
+                // `def f($x)` binds both `$x` and `x`
+                Some(x) => {
+                    self.bind_var(arg);
+                    self.bind(x, Some(0), false)
+                }
+                None => self.bind(arg, Some(0), false),
+            }
+        }
+        self.term(&d.body);
+        self.defining.pop();
+        let params = self.unbind(len);
+        for arg in &d.args {
+            let is_arg = |b: &&Bound| b.name == *arg || arg.strip_prefix('$') == Some(b.name);
+            if !params.iter().filter(is_arg).any(|b| b.used) {
+                self.warn(arg, Lint::UnusedParam)
+            }
+        }
+    }
+
+    fn pattern(&mut self, p: &Pattern<&'s str>) {
+        match p {
+            Pattern::Var(x) => self.bind_var(x),
+            Pattern::Arr(ps) => ps.iter().for_each(|p| self.pattern(p)),
+            Pattern::Obj(kps) => kps.iter().for_each(|(k, p)| {
+                self.term(k);
+                self.pattern(p)
+            }),
+        }
+    }
+
+    fn term(&mut self, t: &Spanned<&'s str>) {
+        match &t.term {
+            Term::Id | Term::Recurse | Term::Num(_) | Term::Break(_) => (),
+            Term::Str(_fmt, parts) => parts.iter().for_each(|p| match p {
+                StrPart::Term(t) => self.term(t),
+                StrPart::Str(_) | StrPart::Char(_) => (),
+            }),
+            Term::Arr(t) => t.iter().for_each(|t| self.term(t)),
+            Term::Obj(kvs) => kvs.iter().for_each(|(k, v)| {
+                self.term(k);
+                v.iter().for_each(|v| self.term(v))
+            }),
+            Term::Neg(t) | Term::Label(_, t) => self.term(t),
+            Term::Pipe(l, p, r) => {
+                self.term(l);
+                let len = self.scope.len();
+                p.iter().for_each(|p| self.pattern(p));
+                self.term(r);
+                self.unbind(len);
+            }
+            Term::BinOp(l, op, r) => {
+                self.binop(l, op, r);
+                self.term(l);
+                self.term(r)
+            }
+            Term::Fold(fold, xs, p, args) => {
+                if let [_init, update, ..] = &args[..] {
+                    if multiple(update) {
+                        let msg = "fold continues with all outputs of the update in jaq, but only with the last in jq";
+                        self.warn(fold, Lint::Differs(msg))
+                    }
+                }
+                self.term(xs);
+                let (init, rest) = match &args[..] {
+                    [init, rest @ ..] => (Some(init), rest),
+                    [] => (None, &[][..]),
+                };
+                init.into_iter().for_each(|t| self.term(t));
+                let len = self.scope.len();
+                self.pattern(p);
+                rest.iter().for_each(|t| self.term(t));
+                self.unbind(len);
+            }
+            Term::TryCatch(t, c) => {
+                if let Some(c) = c {
+                    if infallible(t) {
+                        if let Some(s) = token(c).or_else(|| token(t)) {
+                            self.warn(s, Lint::UnreachableCatch)
+                        }
+                    }
+                }
+                self.term(t);
+                c.iter().for_each(|c| self.term(c))
+            }
+            Term::IfThenElse(if_thens, else_) => {
+                for (if_, then_) in if_thens {
+                    self.term(if_);
+                    self.term(then_)
+                }
+                else_.iter().for_each(|t| self.term(t))
+            }
+            Term::Def(defs, t) => {
+                let len = self.scope.len();
+                defs.iter().for_each(|d| self.def(d, false));
+                self.term(t);
+                for b in self.unbind(len) {
+                    if !b.used {
+                        self.warn(b.name, Lint::UnusedDef)
+                    }
+                }
+            }
+            Term::Call(name, args) => {
+                let arity = args.len();
+                if !self.use_(name, Some(arity)) {
+                    let differs = DIFFERS.iter().find(|(n, a, _)| n == name && *a == arity);
+                    if let Some((.., msg)) = differs {
+                        self.warn(name, Lint::Differs(msg))
+                    }
+                }
+                args.iter().for_each(|t| self.term(t))
+            }
+            Term::Var(x) => {
+                self.use_(x, None);
+            }
+            Term::Path(t, path) => {
+                if let Some(s) = scalar(t) {
+                    if let Some((Part::Range(None, None), Opt::Optional)) = path.0.first() {
+                        self.warn(s, Lint::ScalarIter)
+                    }
+                }
+                self.term(t);
+                for (part, _opt) in &path.0 {
+                    match part {
+                        Part::Index(i) => self.term(i),
+                        Part::Range(from, upto) => {
+                            from.iter().chain(upto).for_each(|t| self.term(t))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lint a binary operation whose semantics may differ between jq and jaq.
+    fn binop(&mut self, l: &Spanned<&'s str>, op: &BinaryOp, r: &Spanned<&'s str>) {
+        use crate::ops::Math;
+        if op.is_right_assoc() {
+            // `op` is an assignment
+            if let Some(f) = calls(l).find(|f| PATH_ONLY_JQ.contains(f)) {
+                let msg =
+                    "filter can be used on the left-hand side of assignments in jq, but not in jaq";
+                self.warn(f, Lint::Differs(msg))
+            }
+        }
+        if let (BinaryOp::Math(Math::Div), Term::Num(n)) = (op, &r.term) {
+            if n.parse::<f64>() == Ok(0.0) {
+                let msg = "division by zero yields an error in jq, but a number in jaq";
+                self.warn(n, Lint::Differs(msg))
+            }
+        }
+        if matches!(op, BinaryOp::Math(_) | BinaryOp::Cmp(_)) && multiple(l) && multiple(r) {
+            if let Some(s) = token(l) {
+                let msg = "outputs of cartesian products are ordered differently in jq and jaq";
+                self.warn(s, Lint::Differs(msg))
+            }
+        }
+    }
+}
+
+/// Return true if the term yields multiple outputs syntactically, such as `1, 2`.
+fn multiple<S>(t: &Spanned<S>) -> bool {
+    matches!(t.term, Term::BinOp(_, BinaryOp::Comma, _))
+}
+
+/// Return the names of all filters called by a term,
+/// excluding those called by definitions in the term.
+fn calls<'a, 's>(t: &'a Spanned<&'s str>) -> alloc::boxed::Box<dyn Iterator<Item = &'s str> + 'a> {
+    use alloc::boxed::Box;
+    use core::iter::once;
+    match &t.term {
+        Term::Call(name, args) => Box::new(once(*name).chain(args.iter().flat_map(calls))),
+        Term::Pipe(l, _, r) | Term::BinOp(l, _, r) => Box::new(calls(l).chain(calls(r))),
+        Term::Path(t, _) | Term::Label(_, t) | Term::Neg(t) | Term::Def(_, t) => calls(t),
+        Term::IfThenElse(if_thens, else_) => Box::new(
+            if_thens
+                .iter()
+                .flat_map(|(i, t)| calls(i).chain(calls(t)))
+                .chain(else_.iter().flat_map(|t| calls(t))),
+        ),
+        _ => Box::new(core::iter::empty()),
+    }
+}
+
+/// If the term is a scalar literal, such as `1`, `"a"`, or `true`, return its token.
+fn scalar<'s>(t: &Spanned<&'s str>) -> Option<&'s str> {
+    match &t.term {
+        Term::Num(n) => Some(n),
+        Term::Neg(t) => scalar(t),
+        Term::Str(_, _) => token(t),
+        Term::Call(name, args) if args.is_empty() && ["null", "true", "false"].contains(name) => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// Return the first token of a term, if it has one.
+fn token<'s>(t: &Spanned<&'s str>) -> Option<&'s str> {
+    match &t.term {
+        Term::Id | Term::Recurse | Term::Arr(None) => None,
+        Term::Num(s) | Term::Var(s) | Term::Break(s) | Term::Label(s, _) => Some(s),
+        Term::Call(s, _) | Term::Fold(s, ..) => Some(s),
+        Term::Str(fmt, parts) => fmt.or_else(|| {
+            parts.iter().find_map(|p| match p {
+                StrPart::Str(s) => Some(*s),
+                StrPart::Term(t) => token(t),
+                StrPart::Char(_) => None,
+            })
+        }),
+        Term::Arr(Some(t)) | Term::Neg(t) => token(t),
+        Term::Obj(kvs) => kvs
+            .iter()
+            .find_map(|(k, v)| token(k).or_else(|| v.as_ref().and_then(token))),
+        Term::Pipe(l, _, r) | Term::BinOp(l, _, r) => token(l).or_else(|| token(r)),
+        Term::TryCatch(t, c) => token(t).or_else(|| token(c.as_ref()?)),
+        Term::IfThenElse(if_thens, else_) => if_thens
+            .iter()
+            .find_map(|(i, t)| token(i).or_else(|| token(t)))
+            .or_else(|| token(else_.as_ref()?)),
+        Term::Def(defs, t) => defs.first().map(|d| d.name).or_else(|| token(t)),
+        Term::Path(t, path) => token(t).or_else(|| {
+            path.0.iter().find_map(|(part, _opt)| match part {
+                Part::Index(i) => token(i),
+                Part::Range(from, upto) => from.iter().chain(upto).find_map(token),
+            })
+        }),
+    }
+}
+
+/// Return true if the term can never yield an error.
+///
+/// This is a conservative approximation; in particular, calls to filters may always fail.
+fn infallible<S>(t: &Spanned<S>) -> bool {
+    let literal = |parts: &[StrPart<S, Spanned<S>>]| {
+        parts.iter().all(|p| match p {
+            StrPart::Term(t) => infallible(t),
+            StrPart::Str(_) | StrPart::Char(_) => true,
+        })
+    };
+    match &t.term {
+        Term::Id | Term::Recurse | Term::Num(_) | Term::Var(_) | Term::Break(_) => true,
+        // format filters such as `@csv` may fail
+        Term::Str(fmt, parts) => fmt.is_none() && literal(parts),
+        Term::Arr(t) => t.as_deref().map_or(true, infallible),
+        // keys have to be strings, and `{a}` fails if the input is no object
+        Term::Obj(kvs) => kvs.iter().all(|(k, v)| match (&k.term, v) {
+            (Term::Str(None, parts), Some(v)) => literal(parts) && infallible(v),
+            (Term::Var(_), None) => true,
+            _ => false,
+        }),
+        Term::Neg(t) => matches!(t.term, Term::Num(_)),
+        // destructuring fails if the value does not match the pattern
+        Term::Pipe(l, p, r) => {
+            matches!(p, None | Some(Pattern::Var(_))) && infallible(l) && infallible(r)
+        }
+        // errors on the left-hand side of `//` are ignored
+        Term::BinOp(_, BinaryOp::Alt, r) => infallible(r),
+        Term::BinOp(l, BinaryOp::Comma | BinaryOp::And | BinaryOp::Or | BinaryOp::Cmp(_), r) => {
+            infallible(l) && infallible(r)
+        }
+        Term::BinOp(..) | Term::Call(..) | Term::Fold(..) => false,
+        Term::Label(_, t) | Term::Def(_, t) => infallible(t),
+        Term::TryCatch(_, c) => c.as_deref().map_or(true, infallible),
+        Term::IfThenElse(if_thens, else_) => {
+            if_thens.iter().all(|(i, t)| infallible(i) && infallible(t))
+                && else_.as_deref().map_or(true, infallible)
+        }
+        // only optional paths, such as `.a?`, cannot fail
+        Term::Path(t, path) => {
+            let part = |part: &Part<Spanned<S>>| match part {
+                Part::Index(i) => infallible(i),
+                Part::Range(from, upto) => from.iter().chain(upto).all(infallible),
+            };
+            let mut parts = path.0.iter();
+            infallible(t) && parts.all(|(p, opt)| matches!(opt, Opt::Optional) && part(p))
+        }
+    }
+}
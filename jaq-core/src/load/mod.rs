@@ -3,6 +3,7 @@
 #[cfg(feature = "arbitrary")]
 mod arbitrary;
 pub mod lex;
+pub mod lint;
 pub mod parse;
 mod prec_climb;
 pub mod test;
@@ -14,7 +15,7 @@ use alloc::{string::String, vec::Vec};
 pub use lex::Lexer;
 use lex::Token;
 pub use parse::Parser;
-use parse::{Def, Term};
+use parse::{Def, Spanned, Term};
 #[cfg(feature = "std")]
 use std::path::{Path, PathBuf};
 
@@ -132,8 +133,8 @@ impl<S: core::ops::Deref<Target = str>, B> parse::Module<S, B> {
         let mut errs = Vec::new();
         for (path, as_, meta) in self.deps {
             match as_ {
-                Some(x) if x.starts_with('$') => vars.push((path, x, meta)),
-                as_ => match f(&path, meta) {
+                Some(x) if x.starts_with('$') => vars.push((path, x, meta.map(|m| m.term))),
+                as_ => match f(&path, meta.map(|m| m.term)) {
                     Ok(mid) => mods.push((mid, as_)),
                     Err(e) => errs.push((path, e)),
                 },
@@ -141,7 +142,7 @@ impl<S: core::ops::Deref<Target = str>, B> parse::Module<S, B> {
         }
         if errs.is_empty() {
             Ok(Module {
-                meta: self.meta,
+                meta: self.meta.map(|m| m.term),
                 mods,
                 vars,
                 body: self.body,
@@ -153,6 +154,23 @@ impl<S: core::ops::Deref<Target = str>, B> parse::Module<S, B> {
 }
 
 impl<S, B> Module<S, B> {
+    /// Return the modules that this module includes (`None`) or imports (`Some(name)`).
+    ///
+    /// Every module is given by its index in the [`Modules`] that contain this module.
+    pub fn mods(&self) -> &[(usize, Option<S>)] {
+        &self.mods
+    }
+
+    /// Return the path and the name (starting with `$`) of every imported data file.
+    pub fn vars(&self) -> impl Iterator<Item = (&S, &S)> {
+        self.vars.iter().map(|(path, x, _meta)| (path, x))
+    }
+
+    /// Return the body, such as the definitions of the module.
+    pub fn body(&self) -> &B {
+        &self.body
+    }
+
     fn map_body<B2>(self, f: impl FnOnce(B) -> B2) -> Module<S, B2> {
         Module {
             meta: self.meta,
@@ -176,8 +194,8 @@ impl<'s, P: Default> Loader<&'s str, P, ReadFn<P>> {
     /// The prelude is normally initialised with filters like `map` or `true`.
     pub fn new(prelude: impl IntoIterator<Item = Def<&'s str>>) -> Self {
         let defs = [
-            Def::new("!recurse", Vec::new(), Term::recurse("!recurse")),
-            Def::new("!empty", Vec::new(), Term::empty()),
+            Def::new("!recurse", Vec::new(), Spanned::recurse("!recurse")),
+            Def::new("!empty", Vec::new(), Spanned::empty("!empty")),
         ];
 
         let prelude = Module {
@@ -199,8 +217,8 @@ impl<S: PartialEq> Term<S> {
     fn obj_key(&self, key: S) -> Option<&Self> {
         if let Term::Obj(kvs) = self {
             kvs.iter().find_map(|(k, v)| {
-                if *k.as_str()? == key {
-                    v.as_ref()
+                if *k.term.as_str()? == key {
+                    v.as_ref().map(|v| &v.term)
                 } else {
                     None
                 }
@@ -212,7 +230,9 @@ impl<S: PartialEq> Term<S> {
 
     fn unconcat(&self) -> Box<dyn Iterator<Item = &Self> + '_> {
         match self {
-            Self::BinOp(l, parse::BinaryOp::Comma, r) => Box::new(l.unconcat().chain(r.unconcat())),
+            Self::BinOp(l, parse::BinaryOp::Comma, r) => {
+                Box::new(l.term.unconcat().chain(r.term.unconcat()))
+            }
             _ => Box::new(core::iter::once(self)),
         }
     }
@@ -232,7 +252,7 @@ impl<'a> Import<'a, &'a str, PathBuf> {
         let paths = self.meta.as_ref().and_then(|meta| {
             let v = meta.obj_key("search")?;
             let iter = if let Term::Arr(Some(a)) = v {
-                Box::new(a.unconcat().filter_map(|v| v.as_str()))
+                Box::new(a.term.unconcat().filter_map(|v| v.as_str()))
             } else if let Some(s) = v.as_str() {
                 Box::new(core::iter::once(s))
             } else {
@@ -395,7 +415,7 @@ impl<'s, P: Clone + Eq, R: FnMut(Import<&'s str, P>) -> ReadResult<P>> Loader<&'
     }
 }
 
-fn parse_main(code: &str) -> Result<parse::Module<&str, Term<&str>>, Error<&str>> {
+fn parse_main(code: &str) -> Result<parse::Module<&str, Spanned<&str>>, Error<&str>> {
     let tokens = lex::Lexer::new(code).lex().map_err(Error::Lex)?;
     let conv_err = |(expected, found)| (expected, Token::opt_as_str(found, code));
     parse::Parser::new(&tokens)
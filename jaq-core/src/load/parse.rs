@@ -90,41 +90,52 @@ pub enum Term<S> {
     ///
     /// This consists of an optional format filter starting with `@` (such as `@text`),
     /// followed by quoted string parts (such as `"Hello, \(.name)! \u263A"`).
-    Str(Option<S>, Vec<StrPart<S, Self>>),
+    Str(Option<S>, Vec<StrPart<S, Spanned<S>>>),
     /// Array, empty if `None`
-    Arr(Option<Box<Self>>),
+    Arr(Option<Box<Spanned<S>>>),
     /// Object, specifying its key-value pairs
-    Obj(Vec<(Self, Option<Self>)>),
+    Obj(Vec<(Spanned<S>, Option<Spanned<S>>)>),
 
     /// Negation
-    Neg(Box<Self>),
+    Neg(Box<Spanned<S>>),
     /// Application, i.e. `l | r` if no string is given, else `l as $x | r`
-    Pipe(Box<Self>, Option<Pattern<S>>, Box<Self>),
+    Pipe(Box<Spanned<S>>, Option<Pattern<S>>, Box<Spanned<S>>),
 
     /// Sequence of binary operations, e.g. `1 + 2 - 3 * 4`
-    BinOp(Box<Self>, BinaryOp, Box<Self>),
+    BinOp(Box<Spanned<S>>, BinaryOp, Box<Spanned<S>>),
 
     /// Control flow variable declaration, e.g. `label $x | ...`
-    Label(S, Box<Self>),
+    Label(S, Box<Spanned<S>>),
     /// Break out from control flow to location variable, e.g. `break $x`
     Break(S),
 
     /// `reduce` and `foreach`, e.g. `reduce .[] as $x (0; .+$x)`
-    Fold(S, Box<Self>, Pattern<S>, Vec<Self>),
+    Fold(S, Box<Spanned<S>>, Pattern<S>, Vec<Spanned<S>>),
     /// `try` and optional `catch`
-    TryCatch(Box<Self>, Option<Box<Self>>),
+    TryCatch(Box<Spanned<S>>, Option<Box<Spanned<S>>>),
     /// If-then-else
-    IfThenElse(Vec<(Self, Self)>, Option<Box<Self>>),
+    IfThenElse(Vec<(Spanned<S>, Spanned<S>)>, Option<Box<Spanned<S>>>),
 
     /// Local definition
-    Def(Vec<Def<S, Self>>, Box<Self>),
+    Def(Vec<Def<S>>, Box<Spanned<S>>),
     /// Call to another filter, e.g. `map(.+1)`
-    Call(S, Vec<Self>),
+    Call(S, Vec<Spanned<S>>),
     /// Variable, such as `$x` (including leading '$')
     Var(S),
 
     /// Path such as `.a`, `.[][]."b"`, `f[0]`
-    Path(Box<Self>, Path<Self>),
+    Path(Box<Spanned<S>>, Path<Spanned<S>>),
+}
+
+/// Term together with the first and the last token that it was parsed from.
+///
+/// For example, the term `.[0] + 1` spans from `.` to `1`.
+#[derive(Debug, Default)]
+pub struct Spanned<S> {
+    /// the term itself
+    pub term: Term<S>,
+    /// first and last token of the term
+    pub span: (S, S),
 }
 
 /// Variable-binding pattern, such as in `.[] as [$x, {$y, (f): $z}]`
@@ -135,11 +146,11 @@ pub enum Pattern<S> {
     /// Array
     Arr(Vec<Self>),
     /// Object
-    Obj(Vec<(Term<S>, Self)>),
+    Obj(Vec<(Spanned<S>, Self)>),
 }
 
 /// Binary operators, such as `|`, `,`, `//`, ...
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum BinaryOp {
     /// Concatenation, i.e. `l, r`
     Comma,
@@ -178,34 +189,126 @@ impl<S> Term<S> {
     pub(crate) fn from_str(s: S) -> Self {
         Self::Str(None, [StrPart::Str(s)].into())
     }
+}
+
+impl<S: Clone> Spanned<S> {
+    /// Create a term that consists of a single token.
+    pub(crate) fn token(term: Term<S>, token: S) -> Self {
+        let span = (token.clone(), token);
+        Self { term, span }
+    }
 
     /// `..`, also known as `recurse/0`, is defined as `., (.[]? | ..)`.
+    ///
+    /// All parts of the definition span the given token.
     pub(crate) fn recurse(recurse: S) -> Self {
+        let tok = |t| Self::token(t, recurse.clone());
         // `[]?`
         let path = (path::Part::Range(None, None), path::Opt::Optional);
         // `.[]?` (returns array/object elements or nothing instead)
-        let path = Term::Path(Term::Id.into(), Path(Vec::from([path])));
+        let path = tok(Term::Path(tok(Term::Id).into(), Path(Vec::from([path]))));
 
         // `..`
-        let f = Term::Call(recurse, Vec::new());
+        let f = tok(Term::Call(recurse.clone(), Vec::new()));
         // .[]? | ..
-        let pipe = Term::Pipe(path.into(), None, f.into());
+        let pipe = tok(Term::Pipe(path.into(), None, f.into()));
         // ., (.[]? | ..)
-        Term::BinOp(Term::Id.into(), BinaryOp::Comma, pipe.into())
+        tok(Term::BinOp(
+            tok(Term::Id).into(),
+            BinaryOp::Comma,
+            pipe.into(),
+        ))
     }
 
     /// `{}[]` returns zero values.
-    pub(crate) fn empty() -> Self {
+    ///
+    /// All parts of the definition span the given token.
+    pub(crate) fn empty(empty: S) -> Self {
+        let tok = |t| Self::token(t, empty.clone());
         // `[]`
         let path = (path::Part::Range(None, None), path::Opt::Essential);
         // `{}`
-        let obj = Term::Obj(Vec::new());
+        let obj = tok(Term::Obj(Vec::new()));
         // `{}[]`
-        Term::Path(obj.into(), Path(Vec::from([path])))
+        tok(Term::Path(obj.into(), Path(Vec::from([path]))))
+    }
+}
+
+impl<S> Term<S> {
+    /// Apply a function to all strings contained in the term.
+    ///
+    /// This can be used to attach additional information to strings,
+    /// such as their position in the source code.
+    pub fn map_ref<T>(&self, f: &impl Fn(&S) -> T) -> Term<T> {
+        let b = |t: &Spanned<S>| Box::new(t.map_ref(f));
+        match self {
+            Self::Id => Term::Id,
+            Self::Recurse => Term::Recurse,
+            Self::Num(n) => Term::Num(f(n)),
+            Self::Str(fmt, parts) => {
+                let parts = parts.iter().map(|part| match part {
+                    StrPart::Str(s) => StrPart::Str(f(s)),
+                    StrPart::Term(t) => StrPart::Term(t.map_ref(f)),
+                    StrPart::Char(c) => StrPart::Char(*c),
+                });
+                Term::Str(fmt.as_ref().map(f), parts.collect())
+            }
+            Self::Arr(a) => Term::Arr(a.as_deref().map(b)),
+            Self::Obj(o) => {
+                let kv = |(k, v): &(Spanned<S>, Option<Spanned<S>>)| {
+                    (k.map_ref(f), v.as_ref().map(|v| v.map_ref(f)))
+                };
+                Term::Obj(o.iter().map(kv).collect())
+            }
+            Self::Neg(t) => Term::Neg(b(t)),
+            Self::Pipe(l, pat, r) => Term::Pipe(b(l), pat.as_ref().map(|p| p.map_ref(f)), b(r)),
+            Self::BinOp(l, op, r) => Term::BinOp(b(l), op.clone(), b(r)),
+            Self::Label(x, t) => Term::Label(f(x), b(t)),
+            Self::Break(x) => Term::Break(f(x)),
+            Self::Fold(fold, xs, pat, args) => {
+                let args = args.iter().map(|a| a.map_ref(f)).collect();
+                Term::Fold(f(fold), b(xs), pat.map_ref(f), args)
+            }
+            Self::TryCatch(t, c) => Term::TryCatch(b(t), c.as_deref().map(b)),
+            Self::IfThenElse(if_thens, else_) => {
+                let if_thens = if_thens.iter().map(|(i, t)| (i.map_ref(f), t.map_ref(f)));
+                Term::IfThenElse(if_thens.collect(), else_.as_deref().map(b))
+            }
+            Self::Def(defs, t) => Term::Def(defs.iter().map(|d| d.map_ref(f)).collect(), b(t)),
+            Self::Call(name, args) => {
+                Term::Call(f(name), args.iter().map(|a| a.map_ref(f)).collect())
+            }
+            Self::Var(x) => Term::Var(f(x)),
+            Self::Path(t, path) => Term::Path(b(t), path.map_ref(|p| p.map_ref(f))),
+        }
+    }
+}
+
+impl<S> Spanned<S> {
+    /// Apply a function to all strings contained in the term and its span.
+    pub fn map_ref<T>(&self, f: &impl Fn(&S) -> T) -> Spanned<T> {
+        let span = (f(&self.span.0), f(&self.span.1));
+        Spanned {
+            term: self.term.map_ref(f),
+            span,
+        }
     }
 }
 
 impl<S> Pattern<S> {
+    /// Apply a function to all strings contained in the pattern.
+    pub fn map_ref<T>(&self, f: &impl Fn(&S) -> T) -> Pattern<T> {
+        match self {
+            Self::Var(x) => Pattern::Var(f(x)),
+            Self::Arr(a) => Pattern::Arr(a.iter().map(|p| p.map_ref(f)).collect()),
+            Self::Obj(o) => Pattern::Obj(
+                o.iter()
+                    .map(|(k, p)| (k.map_ref(f), p.map_ref(f)))
+                    .collect(),
+            ),
+        }
+    }
+
     pub(crate) fn vars(&self) -> Box<dyn Iterator<Item = &S> + '_> {
         match self {
             Pattern::Var(x) => Box::new(core::iter::once(x)),
@@ -406,6 +509,24 @@ impl<'s, 't> Parser<'s, 't> {
         })
     }
 
+    /// Return a term that spans all tokens from `tokens` that have been consumed since.
+    fn span_since(&self, tokens: &'t [Token<&'s str>], term: Term<&'s str>) -> Spanned<&'s str> {
+        let consumed = &tokens[..tokens.len() - self.i.as_slice().len()];
+        let span = consumed.first().zip(consumed.last());
+        let span = span.map_or_else(Default::default, |(first, last)| (first.0, last.0));
+        Spanned { term, span }
+    }
+
+    /// Parse a term with the given function, and record the tokens that it consumed.
+    fn spanned<F>(&mut self, f: F) -> Result<'s, 't, Spanned<&'s str>>
+    where
+        F: FnOnce(&mut Self) -> Result<'s, 't, Term<&'s str>>,
+    {
+        let tokens = self.i.as_slice();
+        let term = f(self)?;
+        Ok(self.span_since(tokens, term))
+    }
+
     fn terminated<T, F>(&mut self, f: F) -> Result<'s, 't, T>
     where
         F: FnOnce(&mut Self) -> Result<'s, 't, T>,
@@ -451,7 +572,7 @@ impl<'s, 't> Parser<'s, 't> {
     /// This matters for the parsing of object values, such as `{k1: v1, k2: v2}`:
     /// if we would permit terms of the shape `t, u` inside objects,
     /// then this would be parsed like `{k1: (v1, k2): v2}`, which is invalid.
-    fn term_with_comma(&mut self, with_comma: bool) -> Result<'s, 't, Term<&'s str>> {
+    fn term_with_comma(&mut self, with_comma: bool) -> Result<'s, 't, Spanned<&'s str>> {
         let head = self.atom()?;
         let tail = core::iter::from_fn(|| self.op(with_comma).map(|op| Ok((op, self.atom()?))))
             .collect::<Result<Vec<_>>>()?;
@@ -468,7 +589,12 @@ impl<'s, 't> Parser<'s, 't> {
         })?;
         Ok(match pipe {
             None => tm,
-            Some(x) => Term::Pipe(Box::new(tm), x, Box::new(self.term_with_comma(with_comma)?)),
+            Some(x) => {
+                let r = self.term_with_comma(with_comma)?;
+                let span = (tm.span.0, r.span.1);
+                let term = Term::Pipe(Box::new(tm), x, Box::new(r));
+                Spanned { term, span }
+            }
         })
     }
 
@@ -477,8 +603,26 @@ impl<'s, 't> Parser<'s, 't> {
     /// A term `t` is atomic if and only if `try t catch 0` is syntactically correct.
     /// For example, the term `1 + 2` is not atomic, because `try 1 + 2 catch 0` is invalid.
     /// However, the term `.[]` is atomic, because `try .[] catch 0` is valid.
-    fn atom(&mut self) -> Result<'s, 't, Term<&'s str>> {
-        let tm = match self.i.next() {
+    fn atom(&mut self) -> Result<'s, 't, Spanned<&'s str>> {
+        let tokens = self.i.as_slice();
+        let tm = self.spanned(Self::atom_core)?;
+
+        let tm = match self.opt() {
+            path::Opt::Optional => self.span_since(tokens, Term::TryCatch(Box::new(tm), None)),
+            path::Opt::Essential => tm,
+        };
+
+        let path = self.path()?;
+        Ok(if path.0.is_empty() {
+            tm
+        } else {
+            self.span_since(tokens, Term::Path(Box::new(tm), path))
+        })
+    }
+
+    /// Parse an atomic term without trailing `?` and path.
+    fn atom_core(&mut self) -> Result<'s, 't, Term<&'s str>> {
+        Ok(match self.i.next() {
             Some(Token("-", _)) => Term::Neg(Box::new(self.atom()?)),
             Some(Token("def", _)) => {
                 let head = self.def_tail()?;
@@ -544,7 +688,7 @@ impl<'s, 't> Parser<'s, 't> {
             Some(Token("..", _)) => Term::Recurse,
             Some(Token(c, Tok::Sym)) if c.starts_with('.') => {
                 let key = if c.len() > 1 {
-                    Some(Term::from_str(&c[1..]))
+                    Some(Spanned::token(Term::from_str(&c[1..]), &c[1..]))
                 } else {
                     // TODO: this returns None on things like "@json .",
                     // whereas it should return an error instead
@@ -554,7 +698,7 @@ impl<'s, 't> Parser<'s, 't> {
                 if let Some(key) = key {
                     let head = (path::Part::Index(key), self.opt());
                     let path = core::iter::once(head).chain(self.path()?.0).collect();
-                    Term::Path(Box::new(Term::Id), Path(path))
+                    Term::Path(Box::new(Spanned::token(Term::Id, *c)), Path(path))
                 } else {
                     Term::Id
                 }
@@ -565,28 +709,16 @@ impl<'s, 't> Parser<'s, 't> {
                 "{" if matches!(tokens[..], [Token("}", _)]) => Term::Obj(Vec::new()),
                 "[" => Term::Arr(Some(Box::new(self.with(tokens, "]", Self::term)))),
                 "{" => self.with(tokens, "", |p| p.obj_items(Self::obj_entry).map(Term::Obj)),
-                "(" => self.with(tokens, ")", Self::term),
+                "(" => self.with(tokens, ")", Self::term).term,
                 _ => panic!(),
             },
             Some(Token(_, Tok::Str(parts))) => Term::Str(None, self.str_parts(parts)),
             next => return Err((Expect::Term, next)),
-        };
-
-        let tm = match self.opt() {
-            path::Opt::Optional => Term::TryCatch(Box::new(tm), None),
-            path::Opt::Essential => tm,
-        };
-
-        let path = self.path()?;
-        Ok(if path.0.is_empty() {
-            tm
-        } else {
-            Term::Path(Box::new(tm), path)
         })
     }
 
     /// Parse a term such as `.[] | .+1`.
-    pub fn term(&mut self) -> Result<'s, 't, Term<&'s str>> {
+    pub fn term(&mut self) -> Result<'s, 't, Spanned<&'s str>> {
         self.term_with_comma(true)
     }
 
@@ -598,14 +730,19 @@ impl<'s, 't> Parser<'s, 't> {
     /// * `(f): pat`
     /// * ` a : pat`
     /// * `"a": pat`
-    fn pat_obj_entry(&mut self) -> Result<'s, 't, (Term<&'s str>, Pattern<&'s str>)> {
+    fn pat_obj_entry(&mut self) -> Result<'s, 't, (Spanned<&'s str>, Pattern<&'s str>)> {
         let i = self.i.clone();
         let key = match self.i.next() {
-            Some(Token(x, Tok::Var)) => return Ok((Term::from_str(&x[1..]), Pattern::Var(x))),
+            Some(Token(x, Tok::Var)) => {
+                let key = Spanned::token(Term::from_str(&x[1..]), *x);
+                return Ok((key, Pattern::Var(x)));
+            }
             Some(Token(full, Tok::Block(tokens))) if full.starts_with('(') => {
                 self.with(tokens, ")", Self::term)
             }
-            Some(Token(id, Tok::Word)) if !id.contains("::") => Term::from_str(*id),
+            Some(Token(id, Tok::Word)) if !id.contains("::") => {
+                Spanned::token(Term::from_str(*id), *id)
+            }
             _ => {
                 self.i = i;
                 self.str_key()?
@@ -621,7 +758,7 @@ impl<'s, 't> Parser<'s, 't> {
     /// An example of an object entry is `"key": value` or `(key): value`.
     /// When the key is a term surrounded by parentheses, a value is required,
     /// otherwise the value may be omitted (e.g. `"key"` or `$x`).
-    fn obj_entry(&mut self) -> Result<'s, 't, (Term<&'s str>, Option<Term<&'s str>>)> {
+    fn obj_entry(&mut self) -> Result<'s, 't, (Spanned<&'s str>, Option<Spanned<&'s str>>)> {
         let i = self.i.clone();
         let key = match self.i.next() {
             Some(Token(full, Tok::Block(tokens))) if full.starts_with('(') => {
@@ -629,8 +766,10 @@ impl<'s, 't> Parser<'s, 't> {
                 self.just(":")?;
                 return Ok((k, Some(self.term_with_comma(false)?)));
             }
-            Some(Token(id, Tok::Var)) => Term::Var(*id),
-            Some(Token(id, Tok::Word)) if !id.contains("::") => Term::from_str(*id),
+            Some(Token(id, Tok::Var)) => Spanned::token(Term::Var(*id), *id),
+            Some(Token(id, Tok::Word)) if !id.contains("::") => {
+                Spanned::token(Term::from_str(*id), *id)
+            }
             _ => {
                 self.i = i;
                 self.str_key()?
@@ -643,7 +782,7 @@ impl<'s, 't> Parser<'s, 't> {
     fn str_parts(
         &mut self,
         parts: &'t [StrPart<&'s str, Token<&'s str>>],
-    ) -> Vec<StrPart<&'s str, Term<&'s str>>> {
+    ) -> Vec<StrPart<&'s str, Spanned<&'s str>>> {
         let parts = parts.iter().map(|part| match part {
             StrPart::Str(s) => StrPart::Str(*s),
             StrPart::Term(Token(full, Tok::Block(tokens))) if full.starts_with('(') => {
@@ -655,13 +794,13 @@ impl<'s, 't> Parser<'s, 't> {
         parts.collect()
     }
 
-    fn path(&mut self) -> Result<'s, 't, Path<Term<&'s str>>> {
+    fn path(&mut self) -> Result<'s, 't, Path<Spanned<&'s str>>> {
         let mut path: Vec<_> = core::iter::from_fn(|| self.path_part_opt()).collect();
         while let Some(key) = self.dot() {
             let key = if key.is_empty() {
                 self.str_key()?
             } else {
-                Term::from_str(key)
+                Spanned::token(Term::from_str(key), key)
             };
             path.push((path::Part::Index(key), self.opt()));
             path.extend(core::iter::from_fn(|| self.path_part_opt()));
@@ -670,7 +809,7 @@ impl<'s, 't> Parser<'s, 't> {
     }
 
     /// Parse `[]`, `[t]`, `[t:]`, `[t:t]`, `[:t]` (all without brackets).
-    fn path_part(&mut self) -> Result<'s, 't, path::Part<Term<&'s str>>> {
+    fn path_part(&mut self) -> Result<'s, 't, path::Part<Spanned<&'s str>>> {
         use path::Part::{Index, Range};
         let done = |p: &Self| matches!(p.i.as_slice(), [Token("]", _)]);
         Ok(if done(self) {
@@ -691,7 +830,7 @@ impl<'s, 't> Parser<'s, 't> {
         })
     }
 
-    fn path_part_opt(&mut self) -> Option<(path::Part<Term<&'s str>>, path::Opt)> {
+    fn path_part_opt(&mut self) -> Option<(path::Part<Spanned<&'s str>>, path::Opt)> {
         let part = self.maybe(|p| match p.i.next() {
             Some(Token(full, Tok::Block(tokens))) if full.starts_with('[') => {
                 Some(p.with(tokens, "]", Self::path_part))
@@ -701,15 +840,15 @@ impl<'s, 't> Parser<'s, 't> {
         Some((part, self.opt()))
     }
 
-    fn str_key(&mut self) -> Result<'s, 't, Term<&'s str>> {
-        match self.i.next() {
-            Some(Token(id, Tok::Fmt)) => match self.i.next() {
-                Some(Token(_, Tok::Str(parts))) => Ok(Term::Str(Some(*id), self.str_parts(parts))),
+    fn str_key(&mut self) -> Result<'s, 't, Spanned<&'s str>> {
+        self.spanned(|p| match p.i.next() {
+            Some(Token(id, Tok::Fmt)) => match p.i.next() {
+                Some(Token(_, Tok::Str(parts))) => Ok(Term::Str(Some(*id), p.str_parts(parts))),
                 next => Err((Expect::Str, next)),
             },
-            Some(Token(_, Tok::Str(parts))) => Ok(Term::Str(None, self.str_parts(parts))),
+            Some(Token(_, Tok::Str(parts))) => Ok(Term::Str(None, p.str_parts(parts))),
             next => Err((Expect::Key, next)),
-        }
+        })
     }
 
     fn opt(&mut self) -> path::Opt {
@@ -727,7 +866,7 @@ impl<'s, 't> Parser<'s, 't> {
     }
 
     /// Parse `name args ":" term ";"`.
-    fn def_tail(&mut self) -> Result<'s, 't, Def<&'s str, Term<&'s str>>> {
+    fn def_tail(&mut self) -> Result<'s, 't, Def<&'s str>> {
         let name = match self.i.next() {
             Some(Token(w, Tok::Word | Tok::Fmt)) if !w.contains("::") => w,
             next => return Err((Expect::Ident, next)),
@@ -771,7 +910,10 @@ impl<'s, 't> Parser<'s, 't> {
     }
 
     /// Parse a module with a body returned by the given function.
-    pub(crate) fn module<B, F>(&mut self, f: F) -> Result<'s, 't, Module<&'s str, B>>
+    ///
+    /// For example, the body of a program is a term, whereas
+    /// the body of a module that is included or imported is a sequence of definitions.
+    pub fn module<B, F>(&mut self, f: F) -> Result<'s, 't, Module<&'s str, B>>
     where
         F: FnOnce(&mut Self) -> Result<'s, 't, B>,
     {
@@ -797,7 +939,10 @@ impl<'s, 't> Parser<'s, 't> {
     }
 }
 
-type Dep<S> = (S, Option<S>, Option<Term<S>>);
+/// Dependency of a module, consisting of a path, an optional name, and optional metadata.
+///
+/// The name is present for `import "path" as name;` and absent for `include "path";`.
+pub type Dep<S> = (S, Option<S>, Option<Spanned<S>>);
 
 /// jq module, consisting of metadata, imports/includes, and a body.
 ///
@@ -812,9 +957,12 @@ type Dep<S> = (S, Option<S>, Option<Term<S>>);
 /// def iter: .[];
 /// ~~~
 #[derive(Debug, Default)]
-pub(crate) struct Module<S, B> {
-    pub meta: Option<Term<S>>,
+pub struct Module<S, B> {
+    /// metadata, given by `module meta;`
+    pub meta: Option<Spanned<S>>,
+    /// includes and imports
     pub deps: Vec<Dep<S>>,
+    /// body, such as a term or definitions
     pub body: B,
 }
 
@@ -829,7 +977,7 @@ pub(crate) struct Module<S, B> {
 /// def recurse(f; cond): recurse(f | select(cond));
 /// ~~~
 #[derive(Debug)]
-pub struct Def<S, F = Term<S>> {
+pub struct Def<S, F = Spanned<S>> {
     /// name, e.g. `"double"` or `"map"`
     pub name: S,
     /// arguments, e.g. `["$x"]`, `["f"]`, or `["f", "cond"]`
@@ -844,8 +992,38 @@ impl<S, F> Def<S, F> {
     }
 }
 
-impl prec_climb::Op for BinaryOp {
-    fn precedence(&self) -> usize {
+impl<S> Def<S> {
+    /// Apply a function to all strings contained in the definition.
+    pub fn map_ref<T>(&self, f: &impl Fn(&S) -> T) -> Def<T> {
+        let args = self.args.iter().map(f).collect();
+        Def::new(f(&self.name), args, self.body.map_ref(f))
+    }
+}
+
+impl BinaryOp {
+    /// String representation of a binary operator, such as `,` or `+=`.
+    pub fn as_str(&self) -> &'static str {
+        use ops::Math;
+        match self {
+            Self::Comma => ",",
+            Self::Alt => "//",
+            Self::Or => "or",
+            Self::And => "and",
+            Self::Math(op) => op.as_str(),
+            Self::Cmp(op) => op.as_str(),
+            Self::Assign => "=",
+            Self::Update => "|=",
+            Self::UpdateMath(Math::Add) => "+=",
+            Self::UpdateMath(Math::Sub) => "-=",
+            Self::UpdateMath(Math::Mul) => "*=",
+            Self::UpdateMath(Math::Div) => "/=",
+            Self::UpdateMath(Math::Rem) => "%=",
+            Self::UpdateAlt => "//=",
+        }
+    }
+
+    /// Precedence of a binary operator; the higher, the tighter it binds.
+    pub fn precedence(&self) -> usize {
         use ops::{Cmp, Math};
         match self {
             Self::Comma => 1,
@@ -861,19 +1039,35 @@ impl prec_climb::Op for BinaryOp {
         }
     }
 
+    /// Return true if the operator is right-associative, such as `=`,
+    /// meaning that `a = b = c` is parsed as `a = (b = c)`.
+    pub fn is_right_assoc(&self) -> bool {
+        matches!(
+            self,
+            Self::Assign | Self::Update | Self::UpdateMath(_) | Self::UpdateAlt
+        )
+    }
+}
+
+impl prec_climb::Op for BinaryOp {
+    fn precedence(&self) -> usize {
+        BinaryOp::precedence(self)
+    }
+
     fn associativity(&self) -> prec_climb::Associativity {
         use prec_climb::Associativity;
-        match self {
-            Self::Assign | Self::Update | Self::UpdateMath(_) | Self::UpdateAlt => {
-                Associativity::Right
-            }
-            _ => Associativity::Left,
+        if self.is_right_assoc() {
+            Associativity::Right
+        } else {
+            Associativity::Left
         }
     }
 }
 
-impl<S> prec_climb::Expr<BinaryOp> for Term<S> {
+impl<S: Clone> prec_climb::Expr<BinaryOp> for Spanned<S> {
     fn from_op(lhs: Self, op: BinaryOp, rhs: Self) -> Self {
-        Self::BinOp(Box::new(lhs), op, Box::new(rhs))
+        let span = (lhs.span.0.clone(), rhs.span.1.clone());
+        let term = Term::BinOp(Box::new(lhs), op, Box::new(rhs));
+        Self { term, span }
     }
 }
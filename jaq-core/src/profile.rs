@@ -0,0 +1,90 @@
+//! Profiling of filter execution.
+//!
+//! When profiling is [enabled](enable), every call to a definition or
+//! to a native filter records how often it was called,
+//! how many outputs it yielded, and
+//! how much time was spent in it (including the time spent in its callees).
+//! The recorded statistics can be obtained via [`crate::Filter::profile`].
+//!
+//! Statistics are recorded per thread.
+
+use crate::compile::TermId;
+use crate::val::{ValX, ValXs};
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+std::thread_local! {
+    static STATS: RefCell<BTreeMap<Key, Stats>> = RefCell::new(BTreeMap::new());
+}
+
+/// Enable or disable profiling.
+pub fn enable(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed)
+}
+
+/// Profiled part of a filter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Key {
+    /// definition with the given body
+    Def(TermId),
+    /// native filter with the given index
+    Native(usize),
+}
+
+/// Execution statistics of a definition or a native filter.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// number of calls
+    pub calls: usize,
+    /// number of yielded values, excluding errors
+    pub outputs: usize,
+    /// cumulative wall time spent in the filter, including its callees
+    pub time: Duration,
+}
+
+fn record(key: Key, f: impl FnOnce(&mut Stats)) {
+    STATS.with(|stats| f(stats.borrow_mut().entry(key).or_default()))
+}
+
+/// Return and reset the statistics recorded on the current thread.
+pub(crate) fn take() -> BTreeMap<Key, Stats> {
+    STATS.with(|stats| core::mem::take(&mut *stats.borrow_mut()))
+}
+
+/// Record a call to `key`, whose outputs are yielded by `f`.
+pub(crate) fn call<'a, V: 'a>(key: Key, f: impl FnOnce() -> ValXs<'a, V>) -> ValXs<'a, V> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let start = Instant::now();
+    let ys = f();
+    record(key, |s| {
+        s.calls += 1;
+        s.time += start.elapsed();
+    });
+    Box::new(Profiled { key, ys })
+}
+
+/// Iterator that records the time spent in obtaining its outputs.
+struct Profiled<I> {
+    key: Key,
+    ys: I,
+}
+
+impl<'a, V, I: Iterator<Item = ValX<'a, V>>> Iterator for Profiled<I> {
+    type Item = ValX<'a, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let y = self.ys.next();
+        record(self.key, |s| {
+            s.time += start.elapsed();
+            s.outputs += usize::from(matches!(y, Some(Ok(_))));
+        });
+        y
+    }
+}
@@ -0,0 +1,118 @@
+//! Tracing of filter execution.
+//!
+//! When a [hook](set_hook) is installed, it is called whenever
+//! a term with a known location in the source code is run,
+//! such a term yields an output or an error, or
+//! a breakpoint (`$__break__`) is reached.
+//!
+//! Hooks are installed per thread.
+
+use crate::compile::Span;
+use crate::val::{ValX, ValXs};
+use crate::{exn, Exn};
+use alloc::boxed::Box;
+use core::fmt::Display;
+use std::cell::{Cell, RefCell};
+
+/// Traced event.
+pub enum Event<'a> {
+    /// a term is run with the given input
+    Run(&'a dyn Display),
+    /// a term yields an output
+    Output(&'a dyn Display),
+    /// a term yields an error
+    Error(&'a dyn Display),
+    /// a breakpoint is reached with the given input and the variables visible at it
+    Break(&'a dyn Display, &'a [(&'a str, &'a dyn Display)]),
+}
+
+/// Function called with the location of a term, its nesting depth, and an event.
+pub type Hook = Box<dyn FnMut(&Span, usize, Event)>;
+
+std::thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static HOOK: RefCell<Option<Hook>> = const { RefCell::new(None) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Install a hook for the current thread, returning the previously installed one.
+///
+/// Passing `None` disables tracing.
+pub fn set_hook(hook: Option<Hook>) -> Option<Hook> {
+    ENABLED.with(|e| e.set(hook.is_some()));
+    HOOK.with(|h| h.replace(hook))
+}
+
+fn emit(span: &Span, event: Event) {
+    HOOK.with(|hook| {
+        if let Some(hook) = hook.borrow_mut().as_mut() {
+            hook(span, DEPTH.with(Cell::get), event)
+        }
+    })
+}
+
+fn add_depth(f: impl FnOnce(usize) -> usize) {
+    DEPTH.with(|d| d.set(f(d.get())))
+}
+
+/// Return whether a hook is installed for the current thread.
+pub(crate) fn enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+/// Trace the run of a term at `span` with input `v`.
+///
+/// The location is only looked up if a hook is installed.
+/// If this returns `Some(span)`, then the outputs of the term
+/// have to be passed to [`leave`] together with the returned span.
+pub(crate) fn enter<'a>(
+    span: impl FnOnce() -> Option<&'a Span>,
+    v: &dyn Display,
+) -> Option<&'a Span> {
+    if !enabled() {
+        return None;
+    }
+    let span = span()?;
+    emit(span, Event::Run(v));
+    add_depth(|d| d + 1);
+    Some(span)
+}
+
+/// Trace the outputs `ys` of a term that was passed to [`enter`].
+pub(crate) fn leave<'a, V: Display + 'a>(span: Option<&'a Span>, ys: ValXs<'a, V>) -> ValXs<'a, V> {
+    match span {
+        Some(span) => {
+            add_depth(|d| d - 1);
+            Box::new(Traced { span, ys })
+        }
+        None => ys,
+    }
+}
+
+/// Trace a breakpoint at `span` with input `v` and visible variables `vars`.
+pub(crate) fn breakpoint(span: &Span, v: &dyn Display, vars: &[(&str, &dyn Display)]) {
+    emit(span, Event::Break(v, vars))
+}
+
+/// Iterator that traces the outputs of a term.
+struct Traced<'a, I> {
+    span: &'a Span,
+    ys: I,
+}
+
+impl<'a, V: Display, I: Iterator<Item = ValX<'a, V>>> Iterator for Traced<'_, I> {
+    type Item = ValX<'a, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        add_depth(|d| d + 1);
+        let y = self.ys.next();
+        add_depth(|d| d - 1);
+        match &y {
+            Some(Ok(v)) => emit(self.span, Event::Output(v)),
+            Some(Err(Exn(exn::Inner::Err(e)))) => emit(self.span, Event::Error(&e.0)),
+            // tail calls and breaks are not errors
+            Some(Err(_)) | None => (),
+        }
+        y
+    }
+}
@@ -0,0 +1,68 @@
+pub mod common;
+
+use jaq_core::{Ctx, RcIter};
+use jaq_json::Val;
+
+/// Run the given code and return its first error,
+/// together with the tokens where it occurred and of the calls that led to it.
+fn fail(code: &str, input: Val) -> (String, Option<&str>, Vec<(String, &str)>) {
+    let filter = common::compile(code);
+
+    let inputs = RcIter::new(core::iter::empty());
+    let ctx = Ctx::new([], &inputs);
+    // catching errors is not affected by backtraces
+    let outs = filter.run((ctx.clone(), input.clone()));
+    let oks = outs.take_while(Result::is_ok).count();
+    let mut outs = filter.run_with_backtrace((ctx, input));
+    let (e, bt) = outs.nth(oks).unwrap().unwrap_err();
+
+    // the main module comes after the (here empty) prelude
+    let token = |(mid, range): &(usize, core::ops::Range<usize>)| {
+        assert_eq!(*mid, 1);
+        &code[range.clone()]
+    };
+    let calls = bt.calls.iter().map(|(f, span)| (f.clone(), token(span)));
+    (e.to_string(), bt.span.as_ref().map(token), calls.collect())
+}
+
+#[test]
+fn location() {
+    let obj = Val::obj(
+        [("a".to_string().into(), Val::from(1))]
+            .into_iter()
+            .collect(),
+    );
+    let (e, span, calls) = fail(r#"def f: .a + "x"; def g: [f]; 0, g"#, obj);
+    assert_eq!(e, r#"cannot calculate 1 + "x""#);
+    assert_eq!(span, Some(r#".a + "x""#));
+    assert_eq!(calls, [("f/0".into(), "f"), ("g/0".into(), "g")]);
+
+    let (e, span, calls) = fail("[1, 2] | .[0] | -.a", Val::Null);
+    assert_eq!(e, "cannot use 1 as iterable (array or object)");
+    assert_eq!(span, Some(".a"));
+    assert!(calls.is_empty());
+
+    let (e, span, calls) = fail(r#"def f($x): $x | error; f("x")"#, Val::Null);
+    assert_eq!(e, r#""x""#);
+    assert_eq!(span, Some("error"));
+    assert_eq!(calls, [("f/1".into(), r#"f("x")"#)]);
+
+    let (e, span, _calls) = fail(r#""a" | . + 1"#, Val::Null);
+    assert_eq!(e, r#"cannot calculate "a" + 1"#);
+    assert_eq!(span, Some(". + 1"));
+
+    let (e, span, _calls) = fail(
+        ".[0] as $x | $x | .[]",
+        [Val::from(1)].into_iter().collect(),
+    );
+    assert_eq!(e, "cannot use 1 as iterable (array or object)");
+    assert_eq!(span, Some(".[]"));
+}
+
+#[test]
+fn catch() {
+    let (e, span, calls) = fail(r#"def f: "x" | error; (try f catch .), 1, f"#, Val::Null);
+    assert_eq!(e, r#""x""#);
+    assert_eq!(span, Some("error"));
+    assert_eq!(calls, [("f/0".into(), "f")]);
+}
@@ -1,9 +1,22 @@
+use jaq_core::load::{Arena, File, Loader};
+use jaq_core::{Compiler, Filter, Native};
 use jaq_json::{Error, Val, ValR};
 use serde_json::Value;
 
+/// Return a compiler that knows the native filters of `jaq-std` and `jaq-json`.
+pub fn compiler<'s>() -> Compiler<&'s str, Native<Val>> {
+    Compiler::default().with_funs(jaq_std::funs().chain(jaq_json::funs()))
+}
+
+/// Compile the given code (without definitions of `jaq-std` and `jaq-json`).
+pub fn compile(code: &str) -> Filter<Native<Val>> {
+    let arena = Arena::default();
+    let loader = Loader::new([]);
+    let modules = loader.load(&arena, File { path: (), code }).unwrap();
+    compiler().compile(modules).unwrap()
+}
+
 fn yields(x: Val, code: &str, ys: impl Iterator<Item = ValR>) {
-    use jaq_core::load::{Arena, File, Loader};
-    use jaq_core::{Compiler, Native};
     eprintln!("{}", code.replace('\n', " "));
 
     let arena = Arena::default();
@@ -0,0 +1,46 @@
+pub mod common;
+
+use jaq_core::load::{Arena, File, Loader};
+
+/// Compile the given code and return every error as `symbol: hint`.
+fn hints(code: &str) -> Vec<String> {
+    let arena = Arena::default();
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let modules = loader.load(&arena, File { path: (), code }).unwrap();
+    let compiled = common::compiler()
+        .with_global_vars(["$ENV"])
+        .compile_with_hints(modules);
+    let errs = compiled.err().unwrap().into_iter();
+    let errs = errs.flat_map(|(_file, errs)| errs);
+    let hint = |((x, _undef), hint): ((&str, _), jaq_core::compile::Hint)| {
+        let hint = [hint.arities_str(), hint.suggestion()];
+        format!("{x}: {}", hint.map(Option::unwrap_or_default).join(""))
+    };
+    errs.map(hint).collect()
+}
+
+#[test]
+fn similar() {
+    assert_eq!(hints("[1] | mpa(.)"), ["mpa: did you mean `map`?"]);
+    assert_eq!(hints("lenght"), ["lenght: did you mean `length`?"]);
+    assert_eq!(hints("1 as $foo | $fo"), ["$fo: did you mean `$foo`?"]);
+    assert_eq!(hints("$EVN"), ["$EVN: did you mean `$ENV`?"]);
+    assert_eq!(
+        hints("label $out | break $ot"),
+        ["$ot: did you mean `$out`?"]
+    );
+    // local definitions and filter arguments are suggested
+    assert_eq!(hints("def xyz: 1; xzy"), ["xzy: did you mean `xyz`?"]);
+    assert_eq!(hints("def f(xyz): xy; f(1)"), ["xy: did you mean `xyz`?"]);
+    // short names are not considered to be similar to anything
+    assert_eq!(hints("def f: 1; g"), ["g: "]);
+    // names that differ too much are not suggested
+    assert_eq!(hints("abcdef"), ["abcdef: "]);
+}
+
+#[test]
+fn arities() {
+    assert_eq!(hints("range(1; 2; 3; 4)"), ["range: 1, 2, or 3"]);
+    assert_eq!(hints("def f: 1; def f(g): g; f(1; 2)"), ["f: 0 or 1"]);
+    assert_eq!(hints("length(1)"), ["length: 0"]);
+}
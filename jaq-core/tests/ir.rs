@@ -0,0 +1,20 @@
+pub mod common;
+
+/// Compile the given code and return its intermediate representation.
+fn ir(code: &str) -> String {
+    common::compile(code).ir().to_string()
+}
+
+#[test]
+fn calls() {
+    let ir = ir("def f(g): g | f(g); f(floor)");
+    let lines: Vec<_> = ir.lines().collect();
+    let main = lines.iter().find(|l| l.contains("(main/0)")).unwrap();
+    // the main filter calls `f`, passing `floor` as argument
+    assert!(main.contains("CallDef(#9 f/1, [Fun(#10)], 0, "));
+    assert_eq!(lines[0], "main = #8");
+    assert!(lines.contains(&"#9 (f/1) = Pipe(#11, None, #13)"));
+    assert!(lines.contains(&"#10 = Native(floor/0, [])"));
+    // the recursive call to `f` is tail-recursive
+    assert!(lines.contains(&"#13 = CallDef(#9 f/1, [Fun(#12)], 1, Some(Throw))"));
+}
@@ -0,0 +1,68 @@
+use jaq_core::load::{self, lint, Arena, File, Loader};
+
+/// Lint the given code and return every warning as `code: description`.
+fn lint(code: &str) -> Vec<String> {
+    let arena = Arena::default();
+    let std = load::parse("def map(f): [.[] | f]; def null: [][0];", |p| p.defs());
+    let loader = Loader::new(std.unwrap());
+    let modules = loader.load(&arena, File { path: (), code }).unwrap();
+    let warnings = lint::lint(&modules).into_iter();
+    let warnings = warnings.flat_map(|(_file, warnings)| warnings);
+    warnings
+        .map(|(s, lint)| format!("{s}: {}", lint.as_str()))
+        .collect()
+}
+
+#[test]
+fn unused() {
+    assert_eq!(
+        lint("def f($x; g): 1; def h: h; 0"),
+        [
+            "$x: unused parameter",
+            "g: unused parameter",
+            "f: unused definition",
+            "h: unused definition",
+        ]
+    );
+    // `$x` is used via `x`, and `f` is used
+    assert!(lint("def f($x): x; f(1)").is_empty());
+}
+
+#[test]
+fn shadow() {
+    let shadow = ["$x: variable shadows outer variable"];
+    assert_eq!(lint(". as $x | . as $x | $x"), shadow);
+    assert_eq!(lint(". as $x | def f($x): $x; f(1), $x"), shadow);
+    // the initial value of a fold is not in the scope of its variable
+    assert!(lint(". as $x | reduce $x as $y ($x; $y)").is_empty());
+
+    let std = ["map: definition shadows standard filter"];
+    assert_eq!(lint("def map(f): f; map(1)"), std);
+    // definitions with a different arity do not shadow
+    assert!(lint("def map: 1; map").is_empty());
+}
+
+#[test]
+fn unreachable_catch() {
+    let unreachable = ["2: unreachable catch, because the term cannot fail"];
+    assert_eq!(lint("try [1, .a?] catch 2"), unreachable);
+    assert!(lint("try .a catch 2").is_empty());
+    assert_eq!(lint("try {a: 1} catch 2, try {a} catch 3"), unreachable);
+}
+
+#[test]
+fn scalar_iter() {
+    let msg = "optional iteration over scalar never yields values";
+    let lints = lint(r#"1[]?, "a"[]?, null[]?, [1][]?, .[]?"#);
+    assert_eq!(lints, ["1", "a", "null"].map(|s| format!("{s}: {msg}")));
+}
+
+#[test]
+fn differs() {
+    let lints = lint("first(.[]) |= 1, 1 / 0, (1, 2) * (3, 4), join(1)");
+    let tokens: Vec<_> = lints.iter().map(|l| l.split(':').next().unwrap()).collect();
+    assert_eq!(tokens, ["first", "0", "1", "join"]);
+
+    let lints = lint("reduce .[] as $x (0; ., $x)");
+    assert!(lints[0].starts_with("reduce: fold continues with all outputs"));
+}
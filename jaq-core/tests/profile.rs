@@ -0,0 +1,29 @@
+#![cfg(feature = "profile")]
+
+pub mod common;
+
+use jaq_core::{profile, Ctx, RcIter};
+use jaq_json::Val;
+
+#[test]
+fn calls_outputs() {
+    let code = "def f: if . < 3 then .+1 | f else ., . end; def g: 0 | f; [g, g, floor]";
+    let filter = common::compile(code);
+
+    let inputs = RcIter::new(core::iter::empty());
+    profile::enable(true);
+    let out: Vec<_> = filter.run((Ctx::new([], &inputs), Val::from(1))).collect();
+    profile::enable(false);
+    assert_eq!(out.len(), 1);
+
+    let stats = filter.profile();
+    let get = |prefix: &str| stats.iter().find(|(name, _)| name.starts_with(prefix));
+    let counts = |prefix| get(prefix).map(|(_, s)| (s.calls, s.outputs));
+    // `f` is called four times per call to `g`, including its tail-recursive calls
+    assert_eq!(counts("f/0 #"), Some((8, 4)));
+    assert_eq!(counts("g/0 #"), Some((2, 4)));
+    assert_eq!(counts("floor/0 (native)"), Some((1, 1)));
+
+    // statistics are reset after retrieval
+    assert!(filter.profile().is_empty());
+}
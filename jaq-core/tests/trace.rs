@@ -0,0 +1,76 @@
+#![cfg(feature = "trace")]
+
+pub mod common;
+
+use jaq_core::trace::{set_hook, Event};
+use jaq_core::{Ctx, RcIter};
+use jaq_json::Val;
+use std::{cell::RefCell, rc::Rc};
+
+/// Run the given code and return its traced events as `depth token event`.
+fn trace(code: &str) -> Vec<String> {
+    let filter = common::compile(code);
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let events_ = events.clone();
+    let code = code.to_string();
+    set_hook(Some(Box::new(move |(_mid, range), depth, event| {
+        let token = &code[range.clone()];
+        let event = match event {
+            Event::Run(v) => format!("run {v}"),
+            Event::Output(v) => format!("out {v}"),
+            Event::Error(e) => format!("err {e}"),
+            Event::Break(v, vars) => {
+                let vars: Vec<_> = vars.iter().map(|(x, v)| format!("{x}={v}")).collect();
+                format!("break {v} {}", vars.join(" "))
+            }
+        };
+        events_
+            .borrow_mut()
+            .push(format!("{depth} {token} {event}"))
+    })));
+    let inputs = RcIter::new(core::iter::empty());
+    let _ = filter.run((Ctx::new([], &inputs), Val::from(0))).count();
+    set_hook(None);
+    let events = events.borrow().clone();
+    events
+}
+
+#[test]
+fn nesting() {
+    assert_eq!(
+        trace("def f: 1, error; f"),
+        [
+            "0 f run 0",
+            "1 1, error run 0",
+            "2 1 run 0",
+            "2 1 out 1",
+            "1 1, error out 1",
+            "0 f out 1",
+            "2 error run 0",
+            "2 error err 0",
+            "1 1, error err 0",
+            "0 f err 0",
+        ]
+    );
+}
+
+#[test]
+fn breakpoint() {
+    assert_eq!(
+        trace("1 as $x | def f($y): $__break__; f(2)"),
+        [
+            "0 1 as $x | def f($y): $__break__; f(2) run 0",
+            "1 1 run 0",
+            "1 1 out 1",
+            "1 f(2) run 0",
+            "2 2 run 0",
+            "2 2 out 2",
+            "2 $__break__ run 0",
+            "3 $__break__ break 0 $x=1 $y=2",
+            "2 $__break__ out 0",
+            "1 f(2) out 0",
+            "0 1 as $x | def f($y): $__break__; f(2) out 0",
+        ]
+    );
+}
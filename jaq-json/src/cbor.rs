@@ -0,0 +1,173 @@
+//! CBOR support.
+//!
+//! Integers are converted to integer values if they fit into an `isize`,
+//! else to (arbitrary-precision) numbers.
+//! The same holds for big integers (tags 2 and 3) of any size.
+//! Conversely, integer numbers that do not fit into a CBOR integer
+//! are converted to big integers.
+//! Other tags are ignored, meaning that only the tagged value is converted.
+//! Map keys that are not strings are converted to strings.
+
+use crate::{key, Bytes, Val};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use ciborium::value::{Integer, Value};
+use core::fmt::Write;
+
+/// Parse a sequence of CBOR data items.
+pub fn parse_many(mut slice: &[u8], bytes: Bytes) -> Result<Vec<Val>, String> {
+    let mut vals = Vec::new();
+    while !slice.is_empty() {
+        let v: Value = ciborium::from_reader(&mut slice).map_err(|e| e.to_string())?;
+        vals.push(from_cbor(v, bytes)?);
+    }
+    Ok(vals)
+}
+
+/// Serialise a value as CBOR data item.
+pub fn to_vec(v: &Val) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    ciborium::into_writer(&to_cbor(v)?, &mut buf).map_err(|e| e.to_string())?;
+    Ok(buf)
+}
+
+fn int(i: i128) -> Val {
+    match isize::try_from(i) {
+        Ok(i) => Val::Int(i),
+        Err(_) => Val::Num(Rc::new(i.to_string())),
+    }
+}
+
+/// Convert a big integer given by its big-endian bytes to a value.
+///
+/// If `neg` is true, this yields `-1 - n`, where `n` is the integer given by `b`,
+/// as specified for negative bignums (tag 3).
+fn bignum(b: &[u8], neg: bool) -> Val {
+    // little-endian digits in base 10^9
+    let mut digits: Vec<u32> = Vec::new();
+    let mut mul_add = |mul: u64, add: u64| {
+        let mut carry = add;
+        for d in digits.iter_mut() {
+            let x = u64::from(*d) * mul + carry;
+            *d = (x % 1_000_000_000) as u32;
+            carry = x / 1_000_000_000;
+        }
+        while carry > 0 {
+            digits.push((carry % 1_000_000_000) as u32);
+            carry /= 1_000_000_000;
+        }
+    };
+    b.iter().for_each(|x| mul_add(256, (*x).into()));
+    if neg {
+        mul_add(1, 1)
+    }
+    let mut s = String::from(if neg { "-" } else { "" });
+    let mut rev = digits.iter().rev();
+    // writing to a string cannot fail
+    let _ = write!(s, "{}", rev.next().unwrap_or(&0));
+    for d in rev {
+        let _ = write!(s, "{d:09}");
+    }
+    match s.parse() {
+        Ok(i) => Val::Int(i),
+        Err(_) => Val::Num(Rc::new(s)),
+    }
+}
+
+/// Convert a natural number given by its decimal digits to its big-endian bytes.
+///
+/// If `neg` is true, this yields the bytes of `n - 1`, where `n` is the given number,
+/// because negative bignums (tag 3) encode `-1 - n`.
+fn bignum_bytes(digits: &str, neg: bool) -> Vec<u8> {
+    // little-endian digits in base 256
+    let mut bytes: Vec<u8> = Vec::new();
+    for d in digits.bytes() {
+        let mut carry = u16::from(d - b'0');
+        for b in bytes.iter_mut() {
+            let x = u16::from(*b) * 10 + carry;
+            *b = x as u8;
+            carry = x >> 8;
+        }
+        if carry > 0 {
+            bytes.push(carry as u8)
+        }
+    }
+    if neg {
+        // the number is not zero, so this terminates before running out of bytes
+        for b in bytes.iter_mut() {
+            let (x, borrow) = b.overflowing_sub(1);
+            *b = x;
+            if !borrow {
+                break;
+            }
+        }
+    }
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    bytes.reverse();
+    bytes
+}
+
+fn from_cbor(v: Value, bytes: Bytes) -> Result<Val, String> {
+    Ok(match v {
+        Value::Null => Val::Null,
+        Value::Bool(b) => Val::Bool(b),
+        Value::Integer(i) => int(i.into()),
+        Value::Float(f) => Val::Float(f),
+        Value::Text(s) => Val::from(s),
+        Value::Bytes(b) => bytes.convert(&b),
+        Value::Tag(tag, v) => match (tag, *v) {
+            (2 | 3, Value::Bytes(b)) => bignum(&b, tag == 3),
+            (_, v) => from_cbor(v, bytes)?,
+        },
+        Value::Array(a) => a
+            .into_iter()
+            .map(|v| from_cbor(v, bytes))
+            .collect::<Result<_, _>>()?,
+        Value::Map(m) => {
+            let kvs = m
+                .into_iter()
+                .map(|(k, v)| Ok((key(from_cbor(k, bytes)?), from_cbor(v, bytes)?)));
+            Val::obj(kvs.collect::<Result<_, String>>()?)
+        }
+        // `Value` is non-exhaustive, so we have to handle future variants
+        v => return Err(format!("unsupported CBOR value: {v:?}")),
+    })
+}
+
+fn to_cbor(v: &Val) -> Result<Value, String> {
+    Ok(match v {
+        Val::Null => Value::Null,
+        Val::Bool(b) => Value::Bool(*b),
+        Val::Int(i) => Value::Integer((*i as i64).into()),
+        Val::Float(f) => Value::Float(*f),
+        Val::Num(n) => {
+            let (neg, digits) = match n.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, &***n),
+            };
+            let int = n
+                .parse::<i128>()
+                .ok()
+                .and_then(|i| Integer::try_from(i).ok());
+            if let Some(i) = int {
+                Value::Integer(i)
+            } else if !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_digit()) {
+                let bytes = Value::Bytes(bignum_bytes(digits, neg));
+                Value::Tag(if neg { 3 } else { 2 }, Box::new(bytes))
+            } else {
+                Value::Float(n.parse().map_err(|_| format!("invalid number: {n}"))?)
+            }
+        }
+        Val::Str(s) => Value::Text(s.to_string()),
+        Val::Arr(a) => Value::Array(a.iter().map(to_cbor).collect::<Result<_, _>>()?),
+        Val::Obj(o) => {
+            let kvs = o
+                .iter()
+                .map(|(k, v)| Ok((Value::Text(k.to_string()), to_cbor(v)?)));
+            Value::Map(kvs.collect::<Result<_, String>>()?)
+        }
+    })
+}
@@ -0,0 +1,49 @@
+//! Structural differences between values.
+
+use crate::Val;
+use alloc::{rc::Rc, string::String, vec::Vec};
+
+/// Return the changes that turn `old` into `new`.
+///
+/// Every change is an object `{"path": $p, "old": $o, "new": $n}`,
+/// where `$p` is a path like yielded by `paths`, and
+/// `$o` and `$n` are the values at `$p` in `old` and `new`.
+/// If there is a value at `$p` only in `new` (or only in `old`),
+/// then `"old"` (or `"new"`) is omitted.
+///
+/// Objects are compared regardless of the order of their keys, whereas
+/// arrays are compared element-wise.
+pub fn diff(old: &Val, new: &Val) -> Vec<Val> {
+    let mut changes = Vec::new();
+    diff_at(Some(old), Some(new), &mut Vec::new(), &mut changes);
+    changes
+}
+
+fn diff_at(old: Option<&Val>, new: Option<&Val>, path: &mut Vec<Val>, changes: &mut Vec<Val>) {
+    let mut sub = |k: Val, old, new| {
+        path.push(k);
+        diff_at(old, new, path, changes);
+        path.pop();
+    };
+    match (old, new) {
+        (Some(Val::Obj(o)), Some(Val::Obj(n))) => {
+            let added = n.keys().filter(|k| !o.contains_key(*k));
+            for k in o.keys().chain(added) {
+                sub(Val::Str(Rc::clone(k)), o.get(k), n.get(k))
+            }
+        }
+        (Some(Val::Arr(o)), Some(Val::Arr(n))) => {
+            for i in 0..core::cmp::max(o.len(), n.len()) {
+                sub(Val::Int(i as isize), o.get(i), n.get(i))
+            }
+        }
+        (o, n) if o == n => (),
+        (o, n) => {
+            let key = |k: &str| Rc::new(String::from(k));
+            let path = (key("path"), path.iter().cloned().collect());
+            let o = o.map(|o| (key("old"), o.clone()));
+            let n = n.map(|n| (key("new"), n.clone()));
+            changes.push(Val::obj([path].into_iter().chain(o).chain(n).collect()))
+        }
+    }
+}
@@ -5,12 +5,23 @@
 
 extern crate alloc;
 
+#[cfg(feature = "cbor")]
+pub mod cbor;
+pub mod diff;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+pub mod stream;
+#[cfg(feature = "toml")]
+pub mod toml;
+#[cfg(feature = "yaml")]
+pub mod yaml;
+
 use alloc::string::{String, ToString};
 use alloc::{boxed::Box, rc::Rc, vec::Vec};
 use core::cmp::Ordering;
 use core::fmt::{self, Debug};
 use jaq_core::box_iter::{box_once, BoxIter};
-use jaq_core::{load, ops, path, Exn, Native, RunPtr};
+use jaq_core::{load, ops, path, Bind, Exn, FilterT, Native, RunPtr};
 use jaq_std::{run, unary, v, Filter};
 
 #[cfg(feature = "hifijson")]
@@ -90,6 +101,39 @@ pub type ValR = jaq_core::ValR<Val>;
 /// A value or an eXception.
 pub type ValX<'a> = jaq_core::ValX<'a, Val>;
 
+/// Conversion of byte strings from binary formats, such as CBOR and MessagePack.
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Bytes {
+    /// Convert byte strings to base64-encoded strings.
+    #[default]
+    Base64,
+    /// Convert byte strings to arrays of integers.
+    Array,
+}
+
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+impl Bytes {
+    fn convert(self, b: &[u8]) -> Val {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        match self {
+            Self::Base64 => Val::from(STANDARD.encode(b)),
+            Self::Array => b.iter().map(|x| Val::Int(isize::from(*x))).collect(),
+        }
+    }
+}
+
+/// Convert a value to an object key.
+///
+/// This is used for binary formats, which allow non-string keys in maps.
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+fn key(v: Val) -> Rc<String> {
+    match v {
+        Val::Str(s) => s,
+        v => Rc::new(v.to_string()),
+    }
+}
+
 // This is part of the Rust standard library since 1.76:
 // <https://doc.rust-lang.org/std/rc/struct.Rc.html#method.unwrap_or_clone>.
 // However, to keep MSRV low, we reimplement it here.
@@ -353,7 +397,12 @@ fn str_windows(line: &str, n: usize) -> impl Iterator<Item = &str> {
 /// Functions of the standard library.
 #[cfg(feature = "parse")]
 pub fn funs() -> impl Iterator<Item = Filter<Native<Val>>> {
-    base_funs().chain([run(parse_fun())])
+    let funs = base_funs().chain([run(parse_fun())]);
+    #[cfg(feature = "toml")]
+    let funs = funs.chain(toml::funs().into_vec().into_iter().map(run));
+    #[cfg(feature = "yaml")]
+    let funs = funs.chain(yaml::funs().into_vec().into_iter().map(run));
+    funs
 }
 
 /// Minimal set of filters for JSON values.
@@ -370,6 +419,18 @@ fn base() -> Box<[Filter<RunPtr<Val>>]> {
         ("tojson", v(0), |_, cv| {
             box_once(Ok(cv.1.to_string().into()))
         }),
+        ("tojson", v(1), |_, cv| {
+            unary(cv, |v, opts| {
+                let ascii = jaq_core::ValT::index(opts, &Val::from("ascii".to_string()))?;
+                let ascii = jaq_core::ValT::as_bool(&ascii);
+                let s = if ascii {
+                    alloc::format!("{v:#}")
+                } else {
+                    v.to_string()
+                };
+                Ok(s.into())
+            })
+        }),
         ("length", v(0), |_, cv| box_once_err(cv.1.length())),
         ("path_values", v(0), |_, cv| {
             let pair = |(p, v)| Ok([p, v].into_iter().collect());
@@ -378,6 +439,10 @@ fn base() -> Box<[Filter<RunPtr<Val>>]> {
         ("paths", v(0), |_, cv| {
             Box::new(cv.1.path_values(Vec::new()).skip(1).map(|(p, _v)| Ok(p)))
         }),
+        ("diff", v(1), |_, mut cv| {
+            let new = cv.0.pop_var();
+            Box::new(diff::diff(&cv.1, &new).into_iter().map(Ok))
+        }),
         ("keys_unsorted", v(0), |_, cv| {
             let keys = cv.1.key_values().map(|kvs| kvs.map(|(k, _v)| k).collect());
             let err = || Error::typ(cv.1.clone(), Type::Iter.as_str());
@@ -395,6 +460,15 @@ fn base() -> Box<[Filter<RunPtr<Val>>]> {
                 x.indices(&v).map(|idxs| idxs.map(to_int).collect())
             })
         }),
+        ("tostream", v(0), |_, cv| {
+            Box::new(cv.1.stream_events(Vec::new()).map(Ok))
+        }),
+        ("fromstream", [Bind::Fun(())].into(), |lut, mut cv| {
+            let (f, fc) = cv.0.pop_fun();
+            let mut state = stream::Unstream::default();
+            let evs = f.run(lut, (fc, cv.1));
+            Box::new(evs.filter_map(move |ev| ev.and_then(|ev| Ok(state.push(ev)?)).transpose()))
+        }),
     ])
 }
 
@@ -521,7 +595,7 @@ impl Val {
     /// Return any `key` for which `value | .[key]` is defined, as well as its output.
     ///
     /// Return `None` for values that are neither arrays nor objects.
-    fn key_values(&self) -> Option<BoxIter<(Val, &Val)>> {
+    fn key_values(&self) -> Option<BoxIter<'_, (Val, &Val)>> {
         let arr_idx = |(i, x)| (Self::Int(i as isize), x);
         Some(match self {
             Self::Arr(a) => Box::new(a.iter().enumerate().map(arr_idx)),
@@ -925,9 +999,15 @@ fn float_cmp(left: f64, right: f64) -> Ordering {
 }
 
 /// Format a string as valid JSON string, including leading and trailing quotes.
+///
+/// If the alternate flag is set (`{:#}`), then
+/// all non-ASCII characters are escaped as `\uXXXX`,
+/// using surrogate pairs for characters outside the Basic Multilingual Plane.
 pub fn fmt_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    let ascii = f.alternate();
+    let special = |c: char| c < ' ' || c == '\\' || c == '"' || (ascii && !c.is_ascii());
     write!(f, "\"")?;
-    for s in s.split_inclusive(|c| c < ' ' || c == '\\' || c == '"') {
+    for s in s.split_inclusive(special) {
         // split s into last character and everything before (init)
         let mut chars = s.chars();
         let last = chars.next_back();
@@ -938,12 +1018,22 @@ pub fn fmt_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
                 write!(f, "{init}{}", last.escape_default())
             }
             Some(last) if last < ' ' => write!(f, "{init}\\u{:04x}", last as u8),
+            Some(last) if ascii && !last.is_ascii() => {
+                write!(f, "{init}")?;
+                let mut buf = [0; 2];
+                let utf16 = last.encode_utf16(&mut buf);
+                utf16.iter().try_for_each(|u| write!(f, "\\u{u:04x}"))
+            }
             _ => write!(f, "{s}"),
         }?;
     }
     write!(f, "\"")
 }
 
+/// Format a value as JSON.
+///
+/// If the alternate flag is set (`{:#}`), then
+/// all non-ASCII characters in strings are escaped, see [`fmt_str`].
 impl fmt::Display for Val {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -958,18 +1048,29 @@ impl fmt::Display for Val {
                 write!(f, "[")?;
                 let mut iter = a.iter();
                 if let Some(first) = iter.next() {
-                    write!(f, "{first}")?;
+                    fmt::Display::fmt(first, f)?;
                 };
-                iter.try_for_each(|x| write!(f, ",{x}"))?;
+                iter.try_for_each(|x| {
+                    write!(f, ",")?;
+                    fmt::Display::fmt(x, f)
+                })?;
                 write!(f, "]")
             }
             Self::Obj(o) => {
                 write!(f, "{{")?;
-                let mut iter = o.iter().map(|(k, v)| (Val::Str(k.clone()), v));
-                if let Some((k, v)) = iter.next() {
-                    write!(f, "{k}:{v}")?;
+                let kv = |f: &mut fmt::Formatter, (k, v): (&Rc<String>, &Val)| {
+                    fmt_str(f, k)?;
+                    write!(f, ":")?;
+                    fmt::Display::fmt(v, f)
+                };
+                let mut iter = o.iter();
+                if let Some(first) = iter.next() {
+                    kv(f, first)?;
                 }
-                iter.try_for_each(|(k, v)| write!(f, ",{k}:{v}"))?;
+                iter.try_for_each(|x| {
+                    write!(f, ",")?;
+                    kv(f, x)
+                })?;
                 write!(f, "}}")
             }
         }
@@ -0,0 +1,77 @@
+//! MessagePack support.
+//!
+//! Integers are converted to integer values if they fit into an `isize`,
+//! else to (arbitrary-precision) numbers.
+//! Conversely, integer numbers that do not fit into a MessagePack integer
+//! yield an error, because MessagePack has no big integers.
+//! Extension types are converted like byte strings, ignoring their type.
+//! Map keys that are not strings are converted to strings.
+
+use crate::{key, Bytes, Val};
+use alloc::string::{String, ToString};
+use alloc::{format, rc::Rc, vec::Vec};
+use rmpv::Value;
+
+/// Parse a sequence of MessagePack values.
+pub fn parse_many(mut slice: &[u8], bytes: Bytes) -> Result<Vec<Val>, String> {
+    let mut vals = Vec::new();
+    while !slice.is_empty() {
+        let v = rmpv::decode::read_value(&mut slice).map_err(|e| e.to_string())?;
+        vals.push(from_msgpack(v, bytes));
+    }
+    Ok(vals)
+}
+
+/// Serialise a value as MessagePack.
+pub fn to_vec(v: &Val) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &to_msgpack(v)?).map_err(|e| e.to_string())?;
+    Ok(buf)
+}
+
+fn from_msgpack(v: Value, bytes: Bytes) -> Val {
+    match v {
+        Value::Nil => Val::Null,
+        Value::Boolean(b) => Val::Bool(b),
+        Value::Integer(i) => match i.as_i64().and_then(|i| isize::try_from(i).ok()) {
+            Some(i) => Val::Int(i),
+            None => Val::Num(Rc::new(i.to_string())),
+        },
+        Value::F32(f) => Val::Float(f.into()),
+        Value::F64(f) => Val::Float(f),
+        // strings that are not valid UTF-8 are converted like byte strings
+        Value::String(s) => match s.as_str() {
+            Some(s) => Val::from(s.to_string()),
+            None => bytes.convert(s.as_bytes()),
+        },
+        Value::Binary(b) | Value::Ext(_, b) => bytes.convert(&b),
+        Value::Array(a) => a.into_iter().map(|v| from_msgpack(v, bytes)).collect(),
+        Value::Map(m) => {
+            let kv = |(k, v)| (key(from_msgpack(k, bytes)), from_msgpack(v, bytes));
+            Val::obj(m.into_iter().map(kv).collect())
+        }
+    }
+}
+
+fn to_msgpack(v: &Val) -> Result<Value, String> {
+    Ok(match v {
+        Val::Null => Value::Nil,
+        Val::Bool(b) => Value::Boolean(*b),
+        Val::Int(i) => Value::from(*i as i64),
+        Val::Float(f) => Value::F64(*f),
+        Val::Num(n) => match (n.parse::<i64>(), n.parse::<u64>()) {
+            (Ok(i), _) => Value::from(i),
+            (_, Ok(u)) => Value::from(u),
+            _ if !n.contains(['.', 'e', 'E']) => Err(format!("integer too large: {n}"))?,
+            _ => Value::F64(n.parse().map_err(|_| format!("invalid number: {n}"))?),
+        },
+        Val::Str(s) => Value::from(s.to_string()),
+        Val::Arr(a) => Value::Array(a.iter().map(to_msgpack).collect::<Result<_, _>>()?),
+        Val::Obj(o) => {
+            let kvs = o
+                .iter()
+                .map(|(k, v)| Ok((Value::from(k.to_string()), to_msgpack(v)?)));
+            Value::Map(kvs.collect::<Result<_, String>>()?)
+        }
+    })
+}
@@ -0,0 +1,232 @@
+//! Streaming of JSON values.
+//!
+//! A value is streamed as a sequence of events, where each event is either
+//! `[path, leaf]` for a scalar or empty array/object `leaf` at `path`, or
+//! `[path]` when the array/object containing `path` is closed.
+//! For example, `{"a": [1, 2]}` is streamed as
+//! `[["a", 0], 1]`, `[["a", 1], 2]`, `[["a", 1]]`, `[["a"]]`.
+
+use crate::{wrap, Error, Map, Val, ValR};
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use jaq_core::box_iter::{box_once, BoxIter};
+
+impl Val {
+    /// Return the stream events for the value at `path`, as yielded by `tostream`.
+    pub(crate) fn stream_events<'a>(self, path: Vec<Val>) -> BoxIter<'a, Val> {
+        let kvs = self.key_values().into_iter().flatten();
+        let kvs: Vec<_> = kvs.map(|(k, v)| (k, v.clone())).collect();
+        let last = match kvs.last() {
+            Some((k, _v)) => k.clone(),
+            None => return box_once(event(&path, Some(self))),
+        };
+        let close = path.iter().cloned().chain([last]).collect::<Vec<_>>();
+        let f = move |k| path.iter().cloned().chain([k]).collect();
+        let children = kvs
+            .into_iter()
+            .flat_map(move |(k, v)| v.stream_events(f(k)));
+        Box::new(children.chain(core::iter::once_with(move || event(&close, None))))
+    }
+
+    /// Return `self` with the value at `path` replaced by `v`.
+    ///
+    /// Unlike updates, this creates arrays and objects where
+    /// `self` (or any of its children along `path`) is null.
+    fn set_path(self, path: &[Val], v: Val) -> ValR {
+        let (head, tail) = match path.split_first() {
+            None => return Ok(v),
+            Some(split) => split,
+        };
+        match (self, head) {
+            (Val::Null, Val::Str(_)) => Val::obj(Map::default()).set_path(path, v),
+            (Val::Null, Val::Int(_)) => Val::Arr(Rc::default()).set_path(path, v),
+            (Val::Obj(mut o), Val::Str(k)) => {
+                let x = Rc::make_mut(&mut o).entry(Rc::clone(k)).or_default();
+                *x = core::mem::take(x).set_path(tail, v)?;
+                Ok(Val::Obj(o))
+            }
+            (Val::Arr(mut a), Val::Int(i)) => {
+                let a_ = Rc::make_mut(&mut a);
+                let oob = || Error::str(format_args!("index {i} out of bounds"));
+                let i = wrap(*i, a_.len()).ok_or_else(oob)?;
+                if i >= a_.len() {
+                    a_.resize(i + 1, Val::Null);
+                }
+                a_[i] = core::mem::take(&mut a_[i]).set_path(tail, v)?;
+                Ok(Val::Arr(a))
+            }
+            (x, i) => Err(Error::index(x, i.clone())),
+        }
+    }
+}
+
+/// Construct `[path, leaf]` if a leaf is given, else `[path]`.
+fn event(path: &[Val], leaf: Option<Val>) -> Val {
+    let path = path.iter().cloned().collect();
+    core::iter::once(path).chain(leaf).collect()
+}
+
+/// Reassembly of values from stream events, as performed by `fromstream`.
+#[derive(Default)]
+pub(crate) struct Unstream(Option<Val>);
+
+impl Unstream {
+    /// Process a stream event, returning a value if it has been completed by the event.
+    pub(crate) fn push(&mut self, ev: Val) -> Result<Option<Val>, Error> {
+        match ev {
+            Val::Arr(ref a) => match &a[..] {
+                [Val::Arr(p), v] if p.is_empty() => Ok(Some(v.clone())),
+                [Val::Arr(p), v] => {
+                    let x = self.0.take().unwrap_or_default();
+                    self.0 = Some(x.set_path(p, v.clone())?);
+                    Ok(None)
+                }
+                [Val::Arr(p)] if p.len() == 1 => Ok(Some(self.0.take().unwrap_or_default())),
+                [Val::Arr(_)] => Ok(None),
+                _ => Err(Error::typ(ev, "stream event")),
+            },
+            _ => Err(Error::typ(ev, "stream event")),
+        }
+    }
+}
+
+#[cfg(feature = "hifijson")]
+mod parse {
+    use crate::Val;
+    use alloc::{rc::Rc, string::ToString, vec::Vec};
+    use hifijson::{token::Expect, Error, LexAlloc, Token};
+
+    /// Parser that yields stream events for the JSON values read by a lexer.
+    ///
+    /// Unlike [`Val::parse`], this yields events as soon as
+    /// the corresponding leaf has been read, so that
+    /// the memory consumption depends only on the depth of the input,
+    /// not on its size.
+    pub struct Parser<L> {
+        lexer: L,
+        /// keys leading to the current position
+        path: Vec<Val>,
+        /// true if we have read a value at the current position
+        read: bool,
+        /// true if an error occurred
+        failed: bool,
+    }
+
+    impl<L: LexAlloc> Parser<L> {
+        /// Create a stream event parser for the given lexer.
+        pub fn new(lexer: L) -> Self {
+            let path = Vec::new();
+            let (read, failed) = (false, false);
+            Self {
+                lexer,
+                path,
+                read,
+                failed,
+            }
+        }
+
+        /// Return the underlying lexer.
+        ///
+        /// This is useful to obtain errors stored in the lexer, such as I/O errors.
+        pub fn lexer(&mut self) -> &mut L {
+            &mut self.lexer
+        }
+
+        fn token(&mut self) -> Result<Token, Error> {
+            Ok(self.lexer.ws_token().ok_or(Expect::Value)?)
+        }
+
+        fn key(&mut self, token: Token) -> Result<Val, Error> {
+            let lexer = &mut self.lexer;
+            let key = lexer.str_colon(token, |lexer| lexer.str_string().map_err(Error::Str))?;
+            Ok(Val::Str(Rc::new(key.to_string())))
+        }
+
+        fn step(&mut self) -> Result<Option<Val>, Error> {
+            loop {
+                if !self.read {
+                    let token = if self.path.is_empty() {
+                        match self.lexer.ws_token() {
+                            Some(token) => token,
+                            None => return Ok(None),
+                        }
+                    } else {
+                        self.token()?
+                    };
+                    return self.value(token).map(Some);
+                }
+
+                let key = match self.path.last_mut() {
+                    Some(key) => key,
+                    None => {
+                        // we finished reading a value at the top level
+                        self.read = false;
+                        continue;
+                    }
+                };
+                let end = match key {
+                    Val::Int(_) => Token::RSquare,
+                    _ => Token::RCurly,
+                };
+                match self.lexer.ws_token().ok_or(Expect::CommaOrEnd)? {
+                    Token::Comma => {
+                        if let Val::Int(i) = key {
+                            *i += 1;
+                        } else {
+                            let token = self.token()?;
+                            let key = self.key(token)?;
+                            *self.path.last_mut().unwrap() = key;
+                        }
+                        self.read = false;
+                    }
+                    token if token == end => {
+                        let close = super::event(&self.path, None);
+                        self.path.pop();
+                        return Ok(Some(close));
+                    }
+                    _ => Err(Expect::CommaOrEnd)?,
+                }
+            }
+        }
+
+        /// Read a value starting with the given token and
+        /// return the event for its first leaf.
+        fn value(&mut self, token: Token) -> Result<Val, Error> {
+            let leaf = match token {
+                Token::LSquare => match self.token()? {
+                    Token::RSquare => Val::Arr(Rc::default()),
+                    token => {
+                        self.path.push(Val::Int(0));
+                        return self.value(token);
+                    }
+                },
+                Token::LCurly => match self.token()? {
+                    Token::RCurly => Val::obj(Default::default()),
+                    token => {
+                        let key = self.key(token)?;
+                        self.path.push(key);
+                        let token = self.token()?;
+                        return self.value(token);
+                    }
+                },
+                token => Val::parse(token, &mut self.lexer)?,
+            };
+            self.read = true;
+            Ok(super::event(&self.path, Some(leaf)))
+        }
+    }
+
+    impl<L: LexAlloc> Iterator for Parser<L> {
+        type Item = Result<Val, Error>;
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.failed {
+                return None;
+            }
+            let y = self.step().transpose();
+            self.failed = matches!(y, Some(Err(_)));
+            y
+        }
+    }
+}
+
+#[cfg(feature = "hifijson")]
+pub use parse::Parser;
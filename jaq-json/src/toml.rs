@@ -0,0 +1,96 @@
+//! TOML support.
+//!
+//! TOML tables are converted to objects and arrays to arrays.
+//! Date-times are converted to strings in ISO 8601 format.
+//!
+//! Only objects can be converted to TOML documents, and
+//! values must not contain null, because TOML has no equivalent for it.
+
+use crate::{Error, Val};
+use alloc::string::{String, ToString};
+use alloc::{boxed::Box, rc::Rc};
+use jaq_core::box_iter::box_once;
+use jaq_core::{Exn, RunPtr};
+use jaq_std::{v, Filter};
+use toml::{Table, Value};
+
+/// Parse a TOML document.
+pub fn parse(s: &str) -> Result<Val, String> {
+    let table: Table = s
+        .parse()
+        .map_err(|e: toml::de::Error| e.message().to_string())?;
+    Ok(from_table(table))
+}
+
+/// Serialise an object as TOML document.
+pub fn to_string(v: &Val) -> Result<String, String> {
+    match to_toml(v)? {
+        Value::Table(t) => toml::to_string(&t).map_err(|e| e.to_string()),
+        _ => Err(alloc::format!(
+            "cannot convert {v} to TOML document, which must be an object"
+        )),
+    }
+}
+
+fn from_table(t: Table) -> Val {
+    Val::obj(
+        t.into_iter()
+            .map(|(k, v)| (Rc::new(k), from_toml(v)))
+            .collect(),
+    )
+}
+
+fn from_toml(v: Value) -> Val {
+    match v {
+        Value::String(s) => Val::from(s),
+        Value::Integer(i) => match isize::try_from(i) {
+            Ok(i) => Val::Int(i),
+            Err(_) => Val::Num(Rc::new(i.to_string())),
+        },
+        Value::Float(f) => Val::Float(f),
+        Value::Boolean(b) => Val::Bool(b),
+        Value::Datetime(d) => Val::from(d.to_string()),
+        Value::Array(a) => a.into_iter().map(from_toml).collect(),
+        Value::Table(t) => from_table(t),
+    }
+}
+
+fn to_toml(v: &Val) -> Result<Value, String> {
+    Ok(match v {
+        Val::Null => Err("cannot convert null to TOML")?,
+        Val::Bool(b) => Value::Boolean(*b),
+        Val::Int(i) => Value::Integer(*i as i64),
+        Val::Float(f) => Value::Float(*f),
+        Val::Num(n) => match n.parse() {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::Float(
+                n.parse()
+                    .map_err(|_| alloc::format!("cannot convert {n} to TOML"))?,
+            ),
+        },
+        Val::Str(s) => Value::String(s.to_string()),
+        Val::Arr(a) => Value::Array(a.iter().map(to_toml).collect::<Result<_, _>>()?),
+        Val::Obj(o) => {
+            let kvs = o.iter().map(|(k, v)| Ok((k.to_string(), to_toml(v)?)));
+            Value::Table(kvs.collect::<Result<_, String>>()?)
+        }
+    })
+}
+
+pub(crate) fn funs() -> Box<[Filter<RunPtr<Val>>]> {
+    Box::new([
+        ("fromtoml", v(0), |_, cv| {
+            let parse = |s: Rc<String>| {
+                parse(&s).map_err(|e| Error::str(format_args!("cannot parse {s} as TOML: {e}")))
+            };
+            box_once(cv.1.into_str().and_then(parse).map_err(Exn::from))
+        }),
+        ("totoml", v(0), |_, cv| {
+            box_once(
+                to_string(&cv.1)
+                    .map(Val::from)
+                    .map_err(|e| Exn::from(Error::str(e))),
+            )
+        }),
+    ])
+}
@@ -0,0 +1,115 @@
+//! YAML support.
+//!
+//! YAML values are converted to JSON values as follows:
+//! Integers that do not fit into an `isize` are kept as (arbitrary-precision) numbers,
+//! and mapping keys that are scalars, such as `1` or `true`, are converted to strings.
+//! Mappings with keys that are sequences or mappings cannot be converted.
+
+use crate::{Error, Map, Val};
+use alloc::string::{String, ToString};
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::fmt;
+use jaq_core::box_iter::box_once;
+use jaq_core::{Exn, RunPtr};
+use jaq_std::{v, Filter};
+use yaml_rust2::{Yaml, YamlEmitter, YamlLoader};
+
+/// Parse a YAML stream, yielding one value per document.
+pub fn parse_many(s: &str) -> Result<Vec<Val>, String> {
+    let docs = YamlLoader::load_from_str(s).map_err(|e| e.to_string())?;
+    docs.into_iter().map(from_yaml).collect()
+}
+
+/// Display a value as YAML document, including the leading `---`.
+pub struct Document<'a>(pub &'a Val);
+
+impl fmt::Display for Document<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut emitter = YamlEmitter::new(f);
+        emitter.multiline_strings(true);
+        emitter.dump(&to_yaml(self.0)).map_err(|_| fmt::Error)
+    }
+}
+
+/// Serialise a value as YAML, without the leading `---`.
+pub fn to_string(v: &Val) -> String {
+    let mut s = Document(v).to_string();
+    if s.starts_with("---\n") {
+        s.drain(..4);
+    }
+    s
+}
+
+fn from_yaml(y: Yaml) -> Result<Val, String> {
+    Ok(match y {
+        Yaml::Null => Val::Null,
+        Yaml::Boolean(b) => Val::Bool(b),
+        Yaml::Integer(i) => match isize::try_from(i) {
+            Ok(i) => Val::Int(i),
+            Err(_) => Val::Num(Rc::new(i.to_string())),
+        },
+        Yaml::Real(_) => Val::Float(y.as_f64().unwrap_or(f64::NAN)),
+        Yaml::String(s) => Val::from(s),
+        Yaml::Array(a) => a.into_iter().map(from_yaml).collect::<Result<_, _>>()?,
+        Yaml::Hash(h) => {
+            let kvs = h.into_iter().map(|(k, v)| Ok((key(k)?, from_yaml(v)?)));
+            Val::obj(kvs.collect::<Result<Map<_, _>, String>>()?)
+        }
+        Yaml::Alias(_) | Yaml::BadValue => Err("unresolved alias")?,
+    })
+}
+
+/// Convert a YAML mapping key to a string.
+fn key(k: Yaml) -> Result<Rc<String>, String> {
+    Ok(Rc::new(match k {
+        Yaml::String(s) | Yaml::Real(s) => s,
+        Yaml::Integer(i) => i.to_string(),
+        Yaml::Boolean(b) => b.to_string(),
+        Yaml::Null => "null".to_string(),
+        k => Err(alloc::format!(
+            "cannot use {} as mapping key",
+            from_yaml(k)?
+        ))?,
+    }))
+}
+
+fn to_yaml(v: &Val) -> Yaml {
+    match v {
+        Val::Null => Yaml::Null,
+        Val::Bool(b) => Yaml::Boolean(*b),
+        Val::Int(i) => Yaml::Integer(*i as i64),
+        Val::Float(f) if f.is_nan() => Yaml::Real(".nan".to_string()),
+        Val::Float(f) if f.is_infinite() => {
+            Yaml::Real(if *f > 0.0 { ".inf" } else { "-.inf" }.to_string())
+        }
+        Val::Float(f) => Yaml::Real(alloc::format!("{f:?}")),
+        Val::Num(n) => Yaml::Real(n.to_string()),
+        Val::Str(s) => Yaml::String(s.to_string()),
+        Val::Arr(a) => Yaml::Array(a.iter().map(to_yaml).collect()),
+        Val::Obj(o) => {
+            let kvs = o
+                .iter()
+                .map(|(k, v)| (Yaml::String(k.to_string()), to_yaml(v)));
+            Yaml::Hash(kvs.collect())
+        }
+    }
+}
+
+pub(crate) fn funs() -> Box<[Filter<RunPtr<Val>>]> {
+    Box::new([
+        ("fromyaml", v(0), |_, cv| {
+            let parse = |s: Rc<String>| {
+                parse_many(&s)
+                    .map_err(|e| Error::str(format_args!("cannot parse {s} as YAML: {e}")))
+            };
+            let vals = cv.1.into_str().and_then(parse);
+            match vals {
+                Ok(vals) => Box::new(vals.into_iter().map(Ok)),
+                Err(e) => box_once(Err(Exn::from(e))),
+            }
+        }),
+        ("toyaml", v(0), |_, cv| {
+            box_once(Ok(Val::from(to_string(&cv.1))))
+        }),
+    ])
+}
@@ -0,0 +1,93 @@
+//! Tests for binary formats.
+#![cfg(all(feature = "cbor", feature = "msgpack"))]
+
+use jaq_json::{cbor, msgpack, Bytes, Val};
+use serde_json::json;
+
+fn vals() -> Vec<Val> {
+    let big = Val::Num("18446744073709551615".to_string().into());
+    let obj = json!({"a": [1, 2.5, -3, "x", null, true], "b": {}});
+    vec![obj.into(), Val::Float(1.0), big]
+}
+
+#[test]
+fn cbor_roundtrip() {
+    let bytes: Vec<u8> = vals()
+        .iter()
+        .flat_map(|v| cbor::to_vec(v).unwrap())
+        .collect();
+    assert_eq!(cbor::parse_many(&bytes, Bytes::Base64).unwrap(), vals());
+}
+
+#[test]
+fn msgpack_roundtrip() {
+    let bytes: Vec<u8> = vals()
+        .iter()
+        .flat_map(|v| msgpack::to_vec(v).unwrap())
+        .collect();
+    assert_eq!(msgpack::parse_many(&bytes, Bytes::Base64).unwrap(), vals());
+}
+
+#[test]
+fn int_float() {
+    // CBOR: 1, 1.0 (as half-precision float)
+    let vals = cbor::parse_many(b"\x01\xf9\x3c\x00", Bytes::Base64).unwrap();
+    assert!(matches!(vals[..], [Val::Int(1), Val::Float(f)] if f == 1.0));
+    // MessagePack: 1, 1.0 (as single-precision float)
+    let vals = msgpack::parse_many(b"\x01\xca\x3f\x80\x00\x00", Bytes::Base64).unwrap();
+    assert!(matches!(vals[..], [Val::Int(1), Val::Float(f)] if f == 1.0));
+}
+
+#[test]
+fn bytes() {
+    let b64 = cbor::parse_many(b"\x42hi", Bytes::Base64).unwrap();
+    assert_eq!(b64, vec![Val::from("aGk=".to_string())]);
+    let arr = msgpack::parse_many(b"\xc4\x02hi", Bytes::Array).unwrap();
+    assert_eq!(arr, vec![json!([104, 105]).into()]);
+}
+
+#[test]
+fn truncated() {
+    assert!(cbor::parse_many(b"\x82\x01", Bytes::Base64).is_err());
+    assert!(msgpack::parse_many(b"\x92\x01", Bytes::Base64).is_err());
+}
+
+#[test]
+fn bignum() {
+    // 2^64 and -1 - 5 as bignums
+    let vals = cbor::parse_many(b"\xc2\x49\x01\0\0\0\0\0\0\0\0\xc3\x41\x05", Bytes::Base64);
+    let big = Val::Num("18446744073709551616".to_string().into());
+    assert_eq!(vals.unwrap(), vec![big, Val::Int(-6)]);
+
+    // 2^160 (21 bytes) and -1 - (2^160 - 1)
+    let mut bytes = b"\xc2\x55\x01".to_vec();
+    bytes.extend([0; 20]);
+    bytes.extend(b"\xc3\x54");
+    bytes.extend([0xff; 20]);
+    let pow = "1461501637330902918203684832716283019655932542976";
+    let vals = cbor::parse_many(&bytes, Bytes::Base64).unwrap();
+    let num = |s: String| Val::Num(s.into());
+    assert_eq!(vals, vec![num(pow.to_string()), num(format!("-{pow}"))]);
+}
+
+#[test]
+fn bignum_to() {
+    let pow = "1461501637330902918203684832716283019655932542976";
+    let nums = [
+        pow.to_string(),
+        format!("-{pow}"),
+        "-18446744073709551617".into(),
+    ];
+    for n in nums {
+        let v = Val::Num(n.into());
+        let bytes = cbor::to_vec(&v).unwrap();
+        assert_eq!(
+            cbor::parse_many(&bytes, Bytes::Base64).unwrap(),
+            vec![v.clone()]
+        );
+        assert!(msgpack::to_vec(&v).is_err());
+    }
+    // -1 - 2^64 as bignum
+    let v = Val::Num("-18446744073709551617".to_string().into());
+    assert_eq!(cbor::to_vec(&v).unwrap(), b"\xc3\x49\x01\0\0\0\0\0\0\0\0");
+}
@@ -83,6 +83,12 @@ fn transpose() {
     give(json!([[1, 3], [2, 4]]), "transpose", y);
 }
 
+yields!(
+    truncate_stream,
+    "[1 | truncate_stream([[0], 1], [[1, 0], 2], [[1, 0]], [[1]])]",
+    json!([[[0], 2], [[0]]])
+);
+
 #[test]
 fn walk() {
     give(
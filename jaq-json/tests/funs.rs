@@ -5,6 +5,53 @@ pub mod common;
 use common::give;
 use serde_json::json;
 
+yields!(
+    diff,
+    r#"{a: [1, 2], b: {c: 1}, d: null} | [diff({d: null, b: {c: 2}, a: [1], e: 0})]"#,
+    json!([
+        {"path": ["a", 1], "old": 2},
+        {"path": ["b", "c"], "old": 1, "new": 2},
+        {"path": ["e"], "new": 0}
+    ])
+);
+yields!(diff_equal, "[{a: [1]} | diff({a: [1.0]})]", json!([]));
+yields!(
+    diff_root,
+    "[1 | diff(\"1\")]",
+    json!([{"path": [], "old": 1, "new": "1"}])
+);
+
+yields!(fromstream_scalar, "[fromstream([[], 1], [[], 2])]", [1, 2]);
+yields!(
+    fromstream_nested,
+    r#"fromstream({a: [1, {b: []}], c: {}} | tostream)"#,
+    json!({"a": [1, {"b": []}], "c": {}})
+);
+yields!(
+    fromstream_many,
+    "[fromstream(([0, [1]], {}, 2) | tostream)]",
+    json!([[0, [1]], {}, 2])
+);
+yields!(
+    fromstream_truncated,
+    "[fromstream(1 | truncate_stream([[0], 1], [[1, 0], 2], [[1, 0]], [[1]]))]",
+    [[2]]
+);
+
+#[cfg(feature = "toml")]
+yields!(
+    fromtoml,
+    r#""a = 1\n[b]\nc = [\"x\"]" | fromtoml"#,
+    json!({"a": 1, "b": {"c": ["x"]}})
+);
+
+#[cfg(feature = "yaml")]
+yields!(
+    fromyaml,
+    r#""a: [1, x]\n---\n2.5" | [fromyaml]"#,
+    json!([{"a": [1, "x"]}, 2.5])
+);
+
 #[test]
 fn has() {
     /* TODO: reenable these tests
@@ -78,6 +125,26 @@ fn tojson() {
     give(json!(0), "1.0 / 0.0 | tojson", json!("null"));
 }
 
+yields!(
+    tojson_ascii,
+    r#"{"ä": ["€ 𝄞"]} | [tojson({ascii: true}), tojson({})]"#,
+    [r#"{"\u00e4":["\u20ac \ud834\udd1e"]}"#, r#"{"ä":["€ 𝄞"]}"#]
+);
+
+#[cfg(feature = "toml")]
+yields!(
+    totoml,
+    r#"[{a: 1, b: {c: ["x"]}}, {a: null}, [] | try totoml catch "error"]"#,
+    ["a = 1\n\n[b]\nc = [\"x\"]\n", "error", "error"]
+);
+
+#[cfg(feature = "yaml")]
+yields!(
+    toyaml,
+    r#"{a: [1, {b: null}], c: "1"} | toyaml"#,
+    "a:\n  - 1\n  - b: ~\nc: \"1\""
+);
+
 #[test]
 fn math_rem() {
     // generated with this command with modification for errors and float rounding
@@ -150,3 +217,18 @@ yields!(
     "[{a: 1, b: [2, 3]} | path_values]",
     json!([[["a"], 1], [["b"], [2, 3]], [["b", 0], 2], [["b", 1], 3]])
 );
+
+yields!(tostream_scalar, "[1 | tostream]", json!([[[], 1]]));
+yields!(tostream_empty, "[[] | tostream]", json!([[[], []]]));
+yields!(
+    tostream_nested,
+    r#"[{a: [1, {b: 2}], c: {}} | tostream]"#,
+    json!([
+        [["a", 0], 1],
+        [["a", 1, "b"], 2],
+        [["a", 1, "b"]],
+        [["a", 1]],
+        [["c"], {}],
+        [["c"]]
+    ])
+);
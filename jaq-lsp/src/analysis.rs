@@ -0,0 +1,567 @@
+//! Analysis of a jq program or module.
+//!
+//! The analysis loads a document together with all modules that it includes or imports,
+//! compiles it to find errors, and resolves
+//! every call to a filter and every variable in the document to its definition.
+use jaq_core::load::parse::{Def, Pattern, Spanned, Term};
+use jaq_core::load::{self, Arena, File, Import, Loader, Module, Modules};
+use jaq_core::{compile, path, Bind};
+use jaq_json::Val;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Position in a file, counting lines and UTF-16 code units from zero.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    pub line: usize,
+    pub character: usize,
+}
+
+/// Range of a string in a file.
+#[derive(Clone, Debug)]
+pub struct Loc {
+    pub path: PathBuf,
+    pub range: Range<Pos>,
+}
+
+/// Filter or variable that can be referred to.
+#[derive(Debug)]
+pub struct Symbol {
+    /// name, such as `map` or `$x`
+    pub name: String,
+    /// declaration, such as `def map(f)` or `$x`
+    pub signature: String,
+    /// location of the name in its definition, `None` for builtins
+    pub loc: Option<Loc>,
+}
+
+/// Error or warning in a file.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub range: Range<Pos>,
+    pub message: String,
+    pub warning: bool,
+}
+
+/// Result of analysing a document.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    /// errors and warnings for every file that has some
+    pub diagnostics: Vec<(PathBuf, Vec<Diagnostic>)>,
+    /// all filters and variables that the document can refer to
+    pub symbols: Vec<Symbol>,
+    /// ranges in the document that refer to (or define) a symbol, given by its index
+    pub refs: Vec<(Range<Pos>, usize)>,
+    /// indices of symbols that are visible at the end of the document
+    pub scope: Vec<usize>,
+    /// indices of symbols that are visible only up to some position in the document
+    pub locals: Vec<(usize, Pos)>,
+}
+
+impl Analysis {
+    /// Return the symbol referred to at the given position of the document.
+    pub fn symbol_at(&self, pos: Pos) -> Option<&Symbol> {
+        let mut refs = self.refs.iter();
+        let (_range, i) = refs.find(|(r, _)| r.start <= pos && pos <= r.end)?;
+        Some(&self.symbols[*i])
+    }
+
+    /// Return the symbols that are visible at the given position of the document.
+    ///
+    /// These are all symbols visible at the end of the document,
+    /// plus all local symbols (such as variables) whose scope contains the position.
+    /// Because we know the end of a scope only approximately, this may miss
+    /// local symbols near the end of their scope, such as before a closing parenthesis.
+    pub fn symbols_at(&self, pos: Pos) -> impl Iterator<Item = &Symbol> + '_ {
+        let start = |i: usize| self.symbols[i].loc.as_ref().map(|l| l.range.start);
+        let local = move |i| {
+            let mut locals = self.locals.iter();
+            locals.any(|(j, end)| i == *j && start(i).map_or(false, |s| s < pos && pos <= *end))
+        };
+        let symbols = self.symbols.iter().enumerate();
+        symbols.filter_map(move |(i, s)| (self.scope.contains(&i) || local(i)).then_some(s))
+    }
+}
+
+/// Path of the document, as included by the wrapper that loads it as module.
+const DOCUMENT: &str = "jaq-lsp:document";
+
+/// Global variables provided by the `jaq` binary.
+const GLOBAL_VARS: [&str; 2] = ["$ENV", "$ARGS"];
+
+/// Names and arguments of filters implemented in Rust.
+///
+/// Besides the filters of `jaq-std` and `jaq-json`, this includes
+/// the filters for the input position that the `jaq` binary provides.
+fn natives() -> Vec<(&'static str, Box<[Bind]>)> {
+    let funs = jaq_std::funs::<Val>().chain(jaq_json::funs());
+    let funs = funs.chain(jaq_std::input::funs());
+    funs.map(|(name, args, _native)| (name, args)).collect()
+}
+
+/// Return the index of the document included by the wrapper,
+/// which is the last of the given modules.
+fn document<P>(mods: &Modules<&str, P>) -> Option<usize> {
+    let (_file, wrapper) = mods.last()?;
+    // the wrapper also includes the prelude, which has index 0
+    let mut docs = wrapper
+        .mods()
+        .iter()
+        .filter(|(mid, as_)| *mid != 0 && as_.is_none());
+    docs.next().map(|(mid, _)| *mid)
+}
+
+/// Analyse the document at `path` with contents `code`,
+/// searching included and imported modules in `paths`.
+///
+/// The document is loaded as program if possible, else as module.
+pub fn analyse(path: &Path, code: &str, paths: &[PathBuf]) -> Analysis {
+    let wrapper = format!("include \"{DOCUMENT}\"; .");
+    let arena = Arena::default();
+    let read = |import: Import<&str, PathBuf>| {
+        if *import.path == DOCUMENT {
+            let (code, path) = (code.to_string(), path.to_path_buf());
+            return Ok(File { code, path });
+        }
+        let path = import.find(paths, "jq")?;
+        let code = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        Ok(File { code, path })
+    };
+    let loader = || Loader::new(jaq_std::defs().chain(jaq_json::defs())).with_read(read);
+    let file = |code| File {
+        code,
+        path: path.to_path_buf(),
+    };
+
+    let loaded = match loader().load(&arena, file(code)) {
+        Ok(mods) => Ok((mods.len() - 1, mods)),
+        // a module consists only of definitions, so it cannot be loaded as program
+        Err(errs) => match loader().load(&arena, file(&wrapper)) {
+            Ok(mods) => match document(&mods) {
+                Some(doc) => Ok((doc, mods)),
+                None => Err(errs),
+            },
+            Err(_) => Err(errs),
+        },
+    };
+    let (doc, mods) = match loaded {
+        Ok(loaded) => loaded,
+        Err(errs) => {
+            // offer at least the builtins for completion
+            let mods = loader().load(&arena, file(".")).unwrap_or_default();
+            let symbols = mods.first().map_or_else(Vec::new, |(file, prelude)| {
+                let mut walker = Walker::new(Src::new(file));
+                walker.builtins(prelude);
+                walker.symbols
+            });
+            let diagnostics = load_errors(errs);
+            return Analysis {
+                diagnostics,
+                scope: (0..symbols.len()).collect(),
+                symbols,
+                ..Analysis::default()
+            };
+        }
+    };
+
+    let mut walker = Walker::new(Src::new(&mods[doc].0));
+    walker.module(&mods, doc);
+    let Walker {
+        symbols,
+        refs,
+        scope,
+        locals,
+        ..
+    } = walker;
+    let scope = scope.into_iter().map(|(id, _arity)| id).collect();
+
+    let natives = natives().into_iter().map(|(name, args)| (name, args, ()));
+    let compiler = compile::Compiler::default()
+        .with_funs(natives)
+        .with_global_vars(GLOBAL_VARS);
+    let warnings = lint_warnings(load::lint::lint(&mods));
+    let diagnostics = match compiler.compile(mods) {
+        Ok(_filter) => warnings,
+        Err(errs) => compile_errors(errs),
+    };
+    Analysis {
+        diagnostics,
+        symbols,
+        refs,
+        scope,
+        locals,
+    }
+}
+
+/// Contents of a file, with the offsets at which its lines start.
+struct Src<'a> {
+    code: &'a str,
+    path: &'a Path,
+    lines: Vec<usize>,
+}
+
+impl<'a> Src<'a> {
+    fn new(file: &'a File<&'a str, PathBuf>) -> Self {
+        let newlines = file.code.match_indices('\n').map(|(i, _)| i + 1);
+        Self {
+            code: file.code,
+            path: &file.path,
+            lines: core::iter::once(0).chain(newlines).collect(),
+        }
+    }
+
+    fn pos(&self, offset: usize) -> Pos {
+        let line = self.lines.partition_point(|start| *start <= offset) - 1;
+        let character = self.code[self.lines[line]..offset].encode_utf16().count();
+        Pos { line, character }
+    }
+
+    fn range(&self, span: Range<usize>) -> Range<Pos> {
+        self.pos(span.start)..self.pos(span.end)
+    }
+
+    /// Return the range of `s` if it is part of the file.
+    ///
+    /// This is not the case for strings that are generated by the parser.
+    fn find(&self, s: &str) -> Option<Range<Pos>> {
+        let (start, code) = (s.as_ptr() as usize, self.code.as_ptr() as usize);
+        let inside = code <= start && start + s.len() <= code + self.code.len();
+        inside.then(|| self.range(load::span(self.code, s)))
+    }
+
+    fn loc(&self, s: &str) -> Option<Loc> {
+        let path = self.path.to_path_buf();
+        self.find(s).map(|range| Loc { path, range })
+    }
+
+    fn diagnostic(&self, s: &str, message: String) -> Diagnostic {
+        let range = self.find(s).unwrap_or_default();
+        Diagnostic {
+            range,
+            message,
+            warning: false,
+        }
+    }
+}
+
+fn load_errors(errs: load::Errors<&str, PathBuf>) -> Vec<(PathBuf, Vec<Diagnostic>)> {
+    use load::Error;
+    let errs = errs.into_iter().map(|(file, err)| {
+        let src = Src::new(&file);
+        let expected = |expected: &str, found: &str| {
+            // point to the first character of what was found
+            let len = found.chars().next().map_or(0, char::len_utf8);
+            src.diagnostic(&found[..len], format!("expected {expected}"))
+        };
+        let diags = match err {
+            Error::Io(errs) => errs
+                .into_iter()
+                .map(|(path, e)| src.diagnostic(path, format!("could not load file {path}: {e}")))
+                .collect(),
+            Error::Lex(errs) => errs
+                .into_iter()
+                .map(|(exp, found)| expected(exp.as_str(), found))
+                .collect(),
+            Error::Parse(errs) => errs
+                .into_iter()
+                .map(|(exp, found)| expected(exp.as_str(), found))
+                .collect(),
+        };
+        (file.path.clone(), diags)
+    });
+    errs.collect()
+}
+
+fn compile_errors(errs: compile::Errors<&str, PathBuf>) -> Vec<(PathBuf, Vec<Diagnostic>)> {
+    use compile::Undefined::Filter;
+    let errs = errs.into_iter().map(|(file, errs)| {
+        let src = Src::new(&file);
+        let wnoa = |exp, got| format!("wrong number of arguments (expected {exp}, found {got})");
+        let diags = errs.into_iter().map(|(found, undefined)| {
+            let message = match (found, undefined) {
+                ("reduce", Filter(arity)) => wnoa("2", arity),
+                ("foreach", Filter(arity)) => wnoa("2 or 3", arity),
+                (_, undefined) => format!("undefined {}", undefined.as_str()),
+            };
+            src.diagnostic(found, message)
+        });
+        (file.path.clone(), diags.collect())
+    });
+    errs.collect()
+}
+
+fn lint_warnings(warnings: load::lint::Warnings<&str, PathBuf>) -> Vec<(PathBuf, Vec<Diagnostic>)> {
+    let warnings = warnings.into_iter().map(|(file, warnings)| {
+        let src = Src::new(&file);
+        let diags = warnings.into_iter().map(|(found, lint)| Diagnostic {
+            warning: true,
+            ..src.diagnostic(found, lint.as_str().to_string())
+        });
+        (file.path.clone(), diags.collect())
+    });
+    warnings.collect()
+}
+
+fn signature(name: &str, args: &[impl AsRef<str>]) -> String {
+    if args.is_empty() {
+        format!("def {name}")
+    } else {
+        let args: Vec<_> = args.iter().map(|a| a.as_ref()).collect();
+        format!("def {name}({})", args.join("; "))
+    }
+}
+
+/// Traverses definitions, keeping track of the symbols in scope.
+struct Walker<'a> {
+    /// document
+    src: Src<'a>,
+    symbols: Vec<Symbol>,
+    /// symbols in scope, with their arity if they are filters
+    scope: Vec<(usize, Option<usize>)>,
+    refs: Vec<(Range<Pos>, usize)>,
+    /// symbols that went out of scope, with the position where this happened
+    locals: Vec<(usize, Pos)>,
+    /// end of the last name, number, or string in the document that we traversed
+    end: Pos,
+}
+
+impl<'a> Walker<'a> {
+    fn new(src: Src<'a>) -> Self {
+        Self {
+            src,
+            symbols: Vec::new(),
+            scope: Vec::new(),
+            refs: Vec::new(),
+            locals: Vec::new(),
+            end: Pos::default(),
+        }
+    }
+
+    /// Record that we traversed `s`.
+    fn seen(&mut self, s: &str) {
+        if let Some(range) = self.src.find(s) {
+            self.end = self.end.max(range.end)
+        }
+    }
+
+    /// Take all symbols out of scope that were bound after the first `len` ones.
+    fn unbind(&mut self, len: usize) {
+        let end = self.end;
+        let dropped = self.scope.drain(len..).map(|(id, _arity)| (id, end));
+        self.locals.extend(dropped)
+    }
+
+    /// Bring a new symbol into scope.
+    fn bind(&mut self, name: String, signature: String, loc: Option<Loc>, arity: Option<usize>) {
+        let id = self.symbols.len();
+        let loc_range = loc.as_ref().map(|loc| loc.range.clone());
+        self.symbols.push(Symbol {
+            name,
+            signature,
+            loc,
+        });
+        self.scope.push((id, arity));
+        if let Some(range) = &loc_range {
+            self.end = self.end.max(range.end)
+        }
+        // the definition of a symbol in the document refers to the symbol itself
+        if let Some(range) = loc_range.filter(|_| self.symbols[id].loc_in(self.src.path)) {
+            self.refs.push((range, id))
+        }
+    }
+
+    /// Record a reference to the symbol in scope with given name and arity.
+    fn refer(&mut self, name: &str, arity: Option<usize>) {
+        self.seen(name);
+        let mut scope = self.scope.iter().rev();
+        let found = scope.find(|(id, a)| *a == arity && self.symbols[*id].name == name);
+        if let (Some((id, _)), Some(range)) = (found, self.src.find(name)) {
+            self.refs.push((range, *id))
+        }
+    }
+
+    /// Bring the native filters, the definitions of the prelude, and global variables into scope.
+    fn builtins(&mut self, prelude: &Module<&str>) {
+        for (name, args) in natives() {
+            let args = args.iter().enumerate().map(|(i, arg)| {
+                let c = char::from(b'a' + (i % 26) as u8);
+                match arg {
+                    Bind::Var(()) => format!("${c}"),
+                    Bind::Fun(()) => c.to_string(),
+                }
+            });
+            let args: Vec<_> = args.collect();
+            let sig = format!("{} # native", signature(name, &args));
+            self.bind(name.to_string(), sig, None, Some(args.len()))
+        }
+        let prelude = prelude.body().iter().filter(|d| !d.name.starts_with('!'));
+        for d in prelude {
+            let sig = signature(d.name, &d.args);
+            self.bind(d.name.to_string(), sig, None, Some(d.args.len()))
+        }
+        for var in GLOBAL_VARS {
+            self.bind(var.to_string(), var.to_string(), None, None)
+        }
+    }
+
+    /// Bring all symbols into scope that are visible in the document, and traverse it.
+    fn module(&mut self, mods: &Modules<&str, PathBuf>, doc: usize) {
+        self.builtins(&mods[0].1);
+        let (file, module) = &mods[doc];
+        for (mid, name) in module.mods().iter().filter(|(mid, _)| *mid != 0) {
+            let src = Src::new(&mods[*mid].0);
+            for d in mods[*mid].1.body() {
+                let name = match name {
+                    None => d.name.to_string(),
+                    Some(ns) => format!("{ns}::{}", d.name),
+                };
+                let sig = signature(&name, &d.args);
+                self.bind(name, sig, src.loc(d.name), Some(d.args.len()))
+            }
+        }
+        for (path, x) in module.vars() {
+            let name = format!("{x}::{}", &x[1..]);
+            let sig = format!("import \"{path}\" as {x}");
+            let loc = Src::new(file).loc(x);
+            self.bind(name, sig, loc, None)
+        }
+
+        for d in module.body() {
+            match (self.src.find(d.name), &d.body.term) {
+                (Some(_), _) => self.def(d),
+                // the main filter of a program is not a real definition,
+                // and its definitions remain in scope until the end of the program
+                (None, Term::Def(defs, t)) => {
+                    defs.iter().for_each(|d| self.def(d));
+                    self.term(t)
+                }
+                (None, _) => self.term(&d.body),
+            }
+        }
+    }
+
+    /// Bring a definition into scope and traverse its body.
+    fn def(&mut self, d: &Def<&str>) {
+        let sig = signature(d.name, &d.args);
+        let loc = self.src.loc(d.name);
+        self.bind(d.name.to_string(), sig.clone(), loc, Some(d.args.len()));
+
+        let len = self.scope.len();
+        for arg in &d.args {
+            let param = format!("{arg} # parameter of {sig}");
+            if let Some(name) = arg.strip_prefix('$') {
+                // a variable argument `$x` also defines a filter `x`
+                let loc = self.src.loc(arg);
+                self.bind(name.to_string(), param.clone(), loc, Some(0));
+            }
+            let arity = (!arg.starts_with('$')).then_some(0);
+            self.bind(arg.to_string(), param, self.src.loc(arg), arity)
+        }
+        self.term(&d.body);
+        self.unbind(len)
+    }
+
+    /// Bring the variables of a pattern into scope.
+    fn pattern(&mut self, p: &Pattern<&str>) {
+        match p {
+            Pattern::Var(x) => self.bind(x.to_string(), x.to_string(), self.src.loc(x), None),
+            Pattern::Arr(ps) => ps.iter().for_each(|p| self.pattern(p)),
+            Pattern::Obj(kps) => kps.iter().for_each(|(k, p)| {
+                self.term(k);
+                self.pattern(p)
+            }),
+        }
+    }
+
+    fn term(&mut self, t: &Spanned<&str>) {
+        use jaq_core::load::lex::StrPart;
+        match &t.term {
+            Term::Id | Term::Recurse | Term::Arr(None) => (),
+            Term::Num(n) => self.seen(n),
+            Term::Str(_fmt, parts) => {
+                for part in parts {
+                    match part {
+                        StrPart::Str(s) => self.seen(s),
+                        StrPart::Term(t) => self.term(t),
+                        StrPart::Char(_) => (),
+                    }
+                }
+            }
+            Term::Arr(Some(t)) | Term::Neg(t) | Term::TryCatch(t, None) => self.term(t),
+            Term::TryCatch(t, Some(c)) => {
+                self.term(t);
+                self.term(c)
+            }
+            Term::Obj(kvs) => {
+                for (k, v) in kvs {
+                    self.term(k);
+                    v.iter().for_each(|v| self.term(v))
+                }
+            }
+            Term::Pipe(l, None, r) | Term::BinOp(l, _, r) => {
+                self.term(l);
+                self.term(r)
+            }
+            Term::Pipe(l, Some(p), r) => {
+                self.term(l);
+                let len = self.scope.len();
+                self.pattern(p);
+                self.term(r);
+                self.unbind(len)
+            }
+            Term::Label(x, t) => {
+                let len = self.scope.len();
+                self.bind(x.to_string(), format!("label {x}"), self.src.loc(x), None);
+                self.term(t);
+                self.unbind(len)
+            }
+            Term::Break(x) | Term::Var(x) => self.refer(x, None),
+            Term::Fold(_, xs, p, args) => {
+                self.term(xs);
+                // the initial value is evaluated outside the scope of the pattern
+                let (init, update) = args
+                    .split_first()
+                    .map_or((None, &[][..]), |(i, u)| (Some(i), u));
+                init.into_iter().for_each(|i| self.term(i));
+                let len = self.scope.len();
+                self.pattern(p);
+                update.iter().for_each(|a| self.term(a));
+                self.unbind(len)
+            }
+            Term::IfThenElse(if_thens, else_) => {
+                for (if_, then_) in if_thens {
+                    self.term(if_);
+                    self.term(then_)
+                }
+                else_.iter().for_each(|e| self.term(e))
+            }
+            Term::Def(defs, t) => {
+                let len = self.scope.len();
+                defs.iter().for_each(|d| self.def(d));
+                self.term(t);
+                self.unbind(len)
+            }
+            Term::Call(name, args) => {
+                self.refer(name, Some(args.len()));
+                args.iter().for_each(|a| self.term(a))
+            }
+            Term::Path(t, path) => {
+                self.term(t);
+                for (part, _opt) in &path.0 {
+                    match part {
+                        path::Part::Index(i) => self.term(i),
+                        path::Part::Range(from, upto) => {
+                            from.iter().chain(upto).for_each(|i| self.term(i))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Symbol {
+    fn loc_in(&self, path: &Path) -> bool {
+        self.loc.as_ref().map_or(false, |loc| loc.path == path)
+    }
+}
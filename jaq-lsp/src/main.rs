@@ -0,0 +1,336 @@
+//! Language server for jq programs.
+//!
+//! The server communicates via standard input and output and supports
+//! diagnostics, go-to-definition, hover, and completion.
+//! Like `jaq`, it accepts search paths for modules via `-L DIR`.
+mod analysis;
+mod rpc;
+
+use analysis::{Analysis, Pos};
+use jaq_json::Val;
+use rpc::{get, get_str, get_usize, obj, str};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Error code for unknown methods.
+const METHOD_NOT_FOUND: isize = -32601;
+
+fn main() -> ExitCode {
+    let mut paths = Vec::new();
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("-L" | "--library-path") => paths.extend(args.next().map(PathBuf::from)),
+            Some("-h" | "--help") => {
+                println!("Usage: jaq-lsp [-L DIR]...");
+                return ExitCode::SUCCESS;
+            }
+            Some("-V" | "--version") => {
+                println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+                return ExitCode::SUCCESS;
+            }
+            _ => {
+                eprintln!("Error: unexpected argument {arg:?}");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    if paths.is_empty() {
+        paths = ["~/.jq", "$ORIGIN/../lib/jq", "$ORIGIN/../lib"]
+            .map(PathBuf::from)
+            .into()
+    }
+
+    let mut server = Server {
+        paths,
+        docs: BTreeMap::new(),
+        published: BTreeMap::new(),
+        shutdown: false,
+    };
+    match server.run(&mut io::stdin().lock(), &mut io::stdout().lock()) {
+        Ok(()) if server.shutdown => ExitCode::SUCCESS,
+        Ok(()) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+struct Server {
+    /// search paths for modules
+    paths: Vec<PathBuf>,
+    /// contents of open documents, by URI
+    docs: BTreeMap<String, String>,
+    /// URIs of files for which we published nonempty diagnostics, by document URI
+    published: BTreeMap<String, BTreeSet<String>>,
+    /// whether we received a shutdown request
+    shutdown: bool,
+}
+
+impl Server {
+    /// Handle messages until the client sends `exit`.
+    fn run(&mut self, r: &mut impl io::BufRead, w: &mut impl io::Write) -> io::Result<()> {
+        while let Some(msg) = rpc::read(r)? {
+            let method = get_str(&msg, &["method"]).unwrap_or_default();
+            let params = get(&msg, &["params"]).cloned().unwrap_or(Val::Null);
+            match get(&msg, &["id"]).cloned() {
+                // request
+                Some(id) => {
+                    let response = match self.request(method, &params) {
+                        Some(result) => rpc::response(id, result),
+                        None => rpc::error(id, METHOD_NOT_FOUND, "method not found"),
+                    };
+                    rpc::write(w, &response)?
+                }
+                // notification
+                None if method == "exit" => return Ok(()),
+                None => {
+                    for msg in self.notification(method, &params) {
+                        rpc::write(w, &msg)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the result of a request, or `None` if the method is unknown.
+    fn request(&mut self, method: &str, params: &Val) -> Option<Val> {
+        Some(match method {
+            "initialize" => initialize(),
+            "shutdown" => {
+                self.shutdown = true;
+                Val::Null
+            }
+            "textDocument/definition" | "textDocument/hover" | "textDocument/completion" => {
+                self.query(method, params).unwrap_or(Val::Null)
+            }
+            _ => return None,
+        })
+    }
+
+    /// Return the notifications to send in response to a notification.
+    fn notification(&mut self, method: &str, params: &Val) -> Vec<Val> {
+        let uri = get_str(params, &["textDocument", "uri"]).unwrap_or_default();
+        let uri = uri.to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = get_str(params, &["textDocument", "text"]).unwrap_or_default();
+                self.docs.insert(uri.clone(), text.to_string());
+                self.diagnostics(&uri)
+            }
+            "textDocument/didChange" => {
+                // we only support full document synchronisation,
+                // where the last change contains the whole document
+                if let Some(Val::Arr(changes)) = get(params, &["contentChanges"]) {
+                    if let Some(text) = changes.last().and_then(|c| get_str(c, &["text"])) {
+                        self.docs.insert(uri, text.to_string());
+                    }
+                }
+                Vec::new()
+            }
+            "textDocument/didSave" => {
+                if let Some(text) = get_str(params, &["text"]) {
+                    self.docs.insert(uri.clone(), text.to_string());
+                }
+                self.diagnostics(&uri)
+            }
+            "textDocument/didClose" => {
+                self.docs.remove(&uri);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn analyse(&self, uri: &str) -> Option<Analysis> {
+        let path = uri_to_path(uri)?;
+        let code = self.docs.get(uri)?;
+        Some(analysis::analyse(&path, code, &self.paths))
+    }
+
+    /// Answer a request about the symbol at a position in a document.
+    fn query(&self, method: &str, params: &Val) -> Option<Val> {
+        let uri = get_str(params, &["textDocument", "uri"])?;
+        let line = get_usize(params, &["position", "line"])?;
+        let character = get_usize(params, &["position", "character"])?;
+        let pos = Pos { line, character };
+        let analysis = self.analyse(uri)?;
+        Some(match method {
+            "textDocument/definition" => {
+                let loc = analysis.symbol_at(pos).and_then(|s| s.loc.as_ref());
+                let loc = loc.and_then(|loc| Some((file_uri(uri, &loc.path)?, &loc.range)));
+                loc.map_or(Val::Null, |(uri, r)| {
+                    obj([("uri", Val::from(uri)), ("range", range(r))])
+                })
+            }
+            "textDocument/hover" => analysis.symbol_at(pos).map_or(Val::Null, |s| {
+                let value = format!("```jq\n{}\n```", s.signature);
+                let contents = obj([("kind", str("markdown")), ("value", Val::from(value))]);
+                obj([("contents", contents)])
+            }),
+            "textDocument/completion" => completions(&analysis, pos),
+            _ => return None,
+        })
+    }
+
+    /// Publish diagnostics for the document and all files that it loads.
+    ///
+    /// This also clears diagnostics that we published before, but that are now fixed.
+    fn diagnostics(&mut self, uri: &str) -> Vec<Val> {
+        let analysis = match self.analyse(uri) {
+            Some(analysis) => analysis,
+            None => return Vec::new(),
+        };
+        let mut diags: BTreeMap<String, Vec<Val>> = self
+            .published
+            .remove(uri)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|uri| (uri, Vec::new()))
+            .collect();
+        diags.insert(uri.to_string(), Vec::new());
+        for (path, ds) in &analysis.diagnostics {
+            let ds = ds.iter().map(|d| {
+                // 1 means error, 2 means warning
+                let severity = ("severity", Val::Int(if d.warning { 2 } else { 1 }));
+                let source = ("source", str("jaq"));
+                obj([
+                    ("range", range(&d.range)),
+                    severity,
+                    source,
+                    ("message", str(&d.message)),
+                ])
+            });
+            let uri = file_uri(uri, path).unwrap_or_else(|| uri.to_string());
+            diags.entry(uri).or_default().extend(ds)
+        }
+        let published = self.published.entry(uri.to_string()).or_default();
+        let diags = diags.into_iter().map(|(uri, ds)| {
+            if !ds.is_empty() {
+                published.insert(uri.clone());
+            }
+            let params = obj([
+                ("uri", Val::from(uri)),
+                ("diagnostics", ds.into_iter().collect()),
+            ]);
+            rpc::notification("textDocument/publishDiagnostics", params)
+        });
+        diags.collect()
+    }
+}
+
+fn initialize() -> Val {
+    let sync = obj([
+        ("openClose", Val::Bool(true)),
+        // full synchronisation
+        ("change", Val::Int(1)),
+        ("save", obj([("includeText", Val::Bool(true))])),
+    ]);
+    let completion = obj([("triggerCharacters", [str("$")].into_iter().collect())]);
+    let capabilities = obj([
+        ("textDocumentSync", sync),
+        ("definitionProvider", Val::Bool(true)),
+        ("hoverProvider", Val::Bool(true)),
+        ("completionProvider", completion),
+    ]);
+    let info = obj([
+        ("name", str(env!("CARGO_PKG_NAME"))),
+        ("version", str(env!("CARGO_PKG_VERSION"))),
+    ]);
+    obj([("capabilities", capabilities), ("serverInfo", info)])
+}
+
+/// Return the filters and variables that can be referred to at a position.
+fn completions(analysis: &Analysis, pos: Pos) -> Val {
+    // symbols defined later shadow those defined earlier
+    let mut symbols = BTreeMap::new();
+    for s in analysis.symbols_at(pos) {
+        symbols.insert((&s.name, s.signature.starts_with("def ")), s);
+    }
+    let items = symbols.into_values().map(|s| {
+        // kind 3 is function, kind 6 is variable
+        let kind = if s.name.starts_with('$') { 6 } else { 3 };
+        let label = ("label", str(&s.name));
+        obj([
+            label,
+            ("kind", Val::Int(kind)),
+            ("detail", str(&s.signature)),
+        ])
+    });
+    items.collect()
+}
+
+fn position(pos: &Pos) -> Val {
+    let line = ("line", Val::Int(pos.line as isize));
+    obj([line, ("character", Val::Int(pos.character as isize))])
+}
+
+fn range(range: &core::ops::Range<Pos>) -> Val {
+    obj([
+        ("start", position(&range.start)),
+        ("end", position(&range.end)),
+    ])
+}
+
+/// Return the URI of a file that was loaded when analysing the document at `doc`.
+///
+/// This preserves the URI of the document as sent by the client,
+/// which might encode its path differently than we do.
+fn file_uri(doc: &str, path: &Path) -> Option<String> {
+    if uri_to_path(doc).as_deref() == Some(path) {
+        Some(doc.to_string())
+    } else {
+        path_to_uri(path)
+    }
+}
+
+/// Convert a `file://` URI to a path, decoding percent-encoded bytes.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((b, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok());
+        match hex
+            .filter(|_| *b == b'%')
+            .map(|h| u8::from_str_radix(h, 16))
+        {
+            Some(Ok(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..]
+            }
+            _ => {
+                bytes.push(*b);
+                rest = tail
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // on Windows, paths look like `/C:/dir/file.jq`
+    #[cfg(target_os = "windows")]
+    let path = path.strip_prefix('/').unwrap_or(&path).to_string();
+    Some(PathBuf::from(path))
+}
+
+/// Convert a path to a `file://` URI, percent-encoding bytes where necessary.
+fn path_to_uri(path: &Path) -> Option<String> {
+    let path = path.to_str()?.replace('\\', "/");
+    let mut uri = String::from(if path.starts_with('/') {
+        "file://"
+    } else {
+        "file:///"
+    });
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(char::from(b))
+            }
+            _ => uri.push_str(&format!("%{b:02X}")),
+        }
+    }
+    Some(uri)
+}
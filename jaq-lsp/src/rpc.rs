@@ -0,0 +1,107 @@
+//! JSON-RPC messages as exchanged by the language server protocol.
+//!
+//! Every message consists of a header with its length, followed by a JSON value.
+use jaq_json::Val;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+/// Read a message, returning `None` at the end of input.
+pub fn read(r: &mut impl BufRead) -> io::Result<Option<Val>> {
+    let mut len = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        // we ignore all other headers, such as `Content-Type`
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let len = len.ok_or_else(|| invalid("missing Content-Length header"))?;
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf)?;
+
+    use hifijson::token::Lex;
+    let mut lexer = hifijson::SliceLexer::new(&buf);
+    let v = lexer
+        .exactly_one(Val::parse)
+        .map_err(|e| invalid(e.to_string()))?;
+    Ok(Some(v))
+}
+
+/// Write a message.
+pub fn write(w: &mut impl Write, v: &Val) -> io::Result<()> {
+    let s = v.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{s}", s.len())?;
+    w.flush()
+}
+
+/// Response with given result to a request with given ID.
+pub fn response(id: Val, result: Val) -> Val {
+    obj([("jsonrpc", str("2.0")), ("id", id), ("result", result)])
+}
+
+/// Error response to a request with given ID.
+pub fn error(id: Val, code: isize, message: &str) -> Val {
+    let error = obj([("code", Val::Int(code)), ("message", str(message))]);
+    obj([("jsonrpc", str("2.0")), ("id", id), ("error", error)])
+}
+
+/// Notification with given method and parameters.
+pub fn notification(method: &str, params: Val) -> Val {
+    obj([
+        ("jsonrpc", str("2.0")),
+        ("method", str(method)),
+        ("params", params),
+    ])
+}
+
+/// Create an object from fields.
+pub fn obj<'a>(fields: impl IntoIterator<Item = (&'a str, Val)>) -> Val {
+    let fields = fields.into_iter().map(|(k, v)| (Rc::new(k.to_string()), v));
+    Val::obj(fields.collect())
+}
+
+/// Create a string.
+pub fn str(s: &str) -> Val {
+    Val::from(s.to_string())
+}
+
+/// Return the value at the given path of object keys.
+pub fn get<'a>(mut v: &'a Val, path: &[&str]) -> Option<&'a Val> {
+    for key in path {
+        match v {
+            Val::Obj(o) => v = o.get(&Rc::new(key.to_string()))?,
+            _ => return None,
+        }
+    }
+    Some(v)
+}
+
+/// Return the string at the given path of object keys.
+pub fn get_str<'a>(v: &'a Val, path: &[&str]) -> Option<&'a str> {
+    match get(v, path)? {
+        Val::Str(s) => Some(s),
+        _ => None,
+    }
+}
+
+/// Return the non-negative integer at the given path of object keys.
+pub fn get_usize(v: &Val, path: &[&str]) -> Option<usize> {
+    match get(v, path)? {
+        Val::Int(i) => usize::try_from(*i).ok(),
+        _ => None,
+    }
+}
+
+fn invalid(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,142 @@
+use std::io::{self, Write};
+use std::{fs, path, process};
+
+fn message(s: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{s}", s.len())
+}
+
+/// Send messages to the server and return its responses.
+fn lsp(msgs: &[String], dir: &path::Path) -> io::Result<Vec<String>> {
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_jaq-lsp"))
+        .args(["-L", "."])
+        .current_dir(dir)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(msgs.concat().as_bytes())?;
+    let output = child.wait_with_output()?;
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).expect("invalid UTF-8 in output");
+    let mut rest = &*stdout;
+    let mut responses = Vec::new();
+    while let Some((header, body)) = rest.split_once("\r\n\r\n") {
+        let len = header.strip_prefix("Content-Length: ").unwrap();
+        let (response, tail) = body.split_at(len.parse().unwrap());
+        responses.push(response.to_string());
+        rest = tail;
+    }
+    Ok(responses)
+}
+
+#[test]
+fn requests() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let dir = dir.path().canonicalize()?;
+    fs::write(dir.join("lib.jq"), "def f: 1;\n")?;
+    let uri = format!("file://{}", dir.join("main.jq").display());
+    let lib = format!("file://{}", dir.join("lib.jq").display());
+
+    let code = r#"include \"lib\";\ndef g($x): f | $x;\ng(1) | $y"#;
+    let doc = format!(r#"{{"uri":"{uri}","languageId":"jq","version":1,"text":"{code}"}}"#);
+    let at = |id, method, line, character| {
+        let pos = format!(r#"{{"line":{line},"character":{character}}}"#);
+        let params = format!(r#"{{"textDocument":{{"uri":"{uri}"}},"position":{pos}}}"#);
+        message(&format!(
+            r#"{{"jsonrpc":"2.0","id":{id},"method":"{method}","params":{params}}}"#
+        ))
+    };
+    let msgs = [
+        message(r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{}}"#),
+        message(&format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{doc}}}}}"#
+        )),
+        at(1, "textDocument/definition", 1, 11),
+        at(2, "textDocument/hover", 2, 0),
+        at(3, "textDocument/hover", 1, 16),
+        at(4, "textDocument/completion", 2, 10),
+        at(5, "textDocument/completion", 1, 15),
+        message(r#"{"jsonrpc":"2.0","id":6,"method":"unknown"}"#),
+        message(r#"{"jsonrpc":"2.0","id":7,"method":"shutdown"}"#),
+        message(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+    ];
+    let responses = lsp(&msgs, &dir)?;
+    assert_eq!(responses.len(), 9);
+
+    assert!(responses[0].contains(r#""definitionProvider":true"#));
+
+    let diags = &responses[1];
+    assert!(diags.contains("textDocument/publishDiagnostics"));
+    assert!(diags.contains(r#""message":"undefined variable""#));
+    let range = r#""range":{"start":{"line":2,"character":7},"end":{"line":2,"character":9}}"#;
+    assert!(diags.contains(range));
+
+    let def = &responses[2];
+    assert!(def.contains(&format!(r#""uri":"{lib}""#)));
+    let range = r#""range":{"start":{"line":0,"character":4},"end":{"line":0,"character":5}}"#;
+    assert!(def.contains(range));
+
+    assert!(responses[3].contains(r#"def g($x)"#));
+    assert!(responses[4].contains(r#"$x # parameter of def g($x)"#));
+
+    let completion = &responses[5];
+    for label in ["g", "f", "map", "$ENV", "input_filename"] {
+        assert!(completion.contains(&format!(r#""label":"{label}""#)));
+    }
+    assert!(!completion.contains(r#""label":"$x""#));
+
+    // parameters are visible only in the body of their definition
+    assert!(responses[6].contains(r#""label":"$x""#));
+
+    assert!(responses[7].contains("-32601"));
+    assert!(responses[8].contains(r#""result":null"#));
+    Ok(())
+}
+
+#[test]
+fn fold_init() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let dir = dir.path().canonicalize()?;
+    let uri = format!("file://{}", dir.join("main.jq").display());
+
+    let code = r#"def f($x): reduce 1 as $x ($x; . + $x); f(0)"#;
+    let doc = format!(r#"{{"uri":"{uri}","languageId":"jq","version":1,"text":"{code}"}}"#);
+    let hover = |id, character| {
+        let pos = format!(r#"{{"line":0,"character":{character}}}"#);
+        let params = format!(r#"{{"textDocument":{{"uri":"{uri}"}},"position":{pos}}}"#);
+        message(&format!(
+            r#"{{"jsonrpc":"2.0","id":{id},"method":"textDocument/hover","params":{params}}}"#
+        ))
+    };
+    let msgs = [
+        message(r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{}}"#),
+        message(&format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{doc}}}}}"#
+        )),
+        hover(1, 28),
+        hover(2, 36),
+        message(r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#),
+        message(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+    ];
+    let responses = lsp(&msgs, &dir)?;
+    // lint warnings are published with severity 2
+    let diags = &responses[1];
+    assert!(diags.contains(r#""severity":2"#), "{diags}");
+    assert!(diags.contains("variable shadows outer variable"), "{diags}");
+    // the initial value refers to the parameter, the update to the pattern variable
+    assert!(
+        responses[2].contains("parameter of def f($x)"),
+        "{}",
+        responses[2]
+    );
+    assert!(
+        !responses[3].contains("parameter of def f($x)"),
+        "{}",
+        responses[3]
+    );
+    Ok(())
+}
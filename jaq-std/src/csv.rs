@@ -0,0 +1,155 @@
+//! CSV and TSV parsing and escaping.
+//!
+//! This is the inverse of the formatting performed by `@csv` and `@tsv`.
+//! Empty lines are skipped.
+
+use crate::ValT;
+use alloc::string::{String, ToString};
+use alloc::{format, vec::Vec};
+
+/// Escape a TSV field like `@tsv`.
+///
+/// This replaces line feeds, carriage returns, tabs, backslashes, and NULs
+/// by their escape sequences.
+pub fn escape_tsv(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            '\0' => out.push_str("\\0"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A record, consisting of fields.
+pub type Record = Vec<String>;
+
+/// Parse CSV as specified in RFC 4180.
+///
+/// Fields may be enclosed in double quotes, in which case
+/// they may contain commas, line breaks, and double quotes, where
+/// the latter have to be escaped by another double quote.
+/// Records may be terminated by LF, CRLF, or CR.
+pub fn parse_csv(s: &str) -> Result<Vec<Record>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut chars = s.chars().peekable();
+    let mut line = 1;
+    loop {
+        let mut field = String::new();
+        let quoted = chars.next_if_eq(&'"').is_some();
+        if quoted {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => {
+                        line += usize::from(c == '\n');
+                        field.push(c)
+                    }
+                    None => return Err(format!("unterminated quoted field in line {line}")),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !matches!(c, ',' | '\n' | '\r')) {
+                field.push(c)
+            }
+        }
+        let blank = !quoted && field.is_empty() && record.is_empty();
+        record.push(field);
+        match chars.next() {
+            Some(',') => continue,
+            Some(c @ ('\n' | '\r')) => {
+                if c == '\r' {
+                    chars.next_if_eq(&'\n');
+                }
+                line += 1;
+                let record = core::mem::take(&mut record);
+                if !blank {
+                    records.push(record)
+                }
+            }
+            None => {
+                if !blank {
+                    records.push(record)
+                }
+                return Ok(records);
+            }
+            Some(c) => {
+                return Err(format!(
+                    "unexpected `{c}` after quoted field in line {line}"
+                ))
+            }
+        }
+    }
+}
+
+/// Parse TSV, where fields are separated by tabs and records by line breaks.
+///
+/// The escape sequences `\n`, `\r`, `\t`, `\\`, and `\0` in fields are
+/// replaced by the characters that they stand for.
+pub fn parse_tsv(s: &str) -> Vec<Record> {
+    let lines = s.lines().filter(|l| !l.is_empty());
+    lines
+        .map(|l| l.split('\t').map(unescape_tsv).collect())
+        .collect()
+}
+
+fn unescape_tsv(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('\\') => out.push('\\'),
+            Some('0') => out.push('\0'),
+            c => out.extend(core::iter::once('\\').chain(c)),
+        }
+    }
+    out
+}
+
+/// Convert records to values.
+///
+/// If `header` is true, then the first record is taken as header, and
+/// every following record is converted to an object whose keys are
+/// the fields of the header.
+/// Fields missing in a record are omitted from its object,
+/// whereas it is an error for a record to have more fields than the header.
+/// Otherwise, every record is converted to an array of strings.
+pub fn records<V: ValT>(records: Vec<Record>, header: bool) -> Result<Vec<V>, String> {
+    let str = |s: String| V::from(s);
+    let arr = |r: Record| r.into_iter().map(str).collect();
+    let mut records = records.into_iter();
+    let keys: Vec<V> = match (header, records.next()) {
+        (false, first) => return Ok(first.into_iter().chain(records).map(arr).collect()),
+        (true, None) => return Ok(Vec::new()),
+        (true, Some(keys)) => keys.into_iter().map(str).collect(),
+    };
+    let obj = |(i, r): (usize, Record)| {
+        if r.len() > keys.len() {
+            let (n, m) = (r.len(), keys.len());
+            return Err(format!(
+                "record {i} has {n} fields, but header has only {m}"
+            ));
+        }
+        V::from_map(keys.iter().cloned().zip(r.into_iter().map(str))).map_err(|e| e.to_string())
+    };
+    // the header is record 1, so the first record after the header is record 2
+    records
+        .enumerate()
+        .map(|(i, r)| (i + 2, r))
+        .map(obj)
+        .collect()
+}
@@ -0,0 +1,50 @@
+//! Position of the input value that is currently processed.
+//!
+//! Programs that read input values record the file and the line of every value
+//! by [`set_filename`] and [`set_line`], and the filters `input_filename` and
+//! `input_line_number` yield the position of the value recorded last, or null.
+//! The position is stored per thread.
+use crate::{run, v, Filter};
+use alloc::string::String;
+use core::cell::{Cell, RefCell};
+use jaq_core::box_iter::box_once;
+use jaq_core::{Native, RunPtr, ValT};
+
+std::thread_local! {
+    /// file name of the current input, or `None` for standard input
+    static FILENAME: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// number of the line where the current input value starts, if known
+    static LINE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Set the file name of the current input, or `None` for standard input.
+pub fn set_filename(name: Option<String>) {
+    FILENAME.with(|f| f.replace(name));
+}
+
+/// Return the file name of the current input.
+pub fn filename() -> Option<String> {
+    FILENAME.with(|f| f.borrow().clone())
+}
+
+/// Set the line where the current input value starts, if known.
+pub fn set_line(line: Option<usize>) {
+    LINE.with(|l| l.set(line));
+}
+
+/// Filters that yield the position of the current input.
+///
+/// The value type's default value is yielded for an unknown position.
+pub fn funs<V: ValT + Default>() -> impl Iterator<Item = Filter<Native<V>>> {
+    let filename: RunPtr<V> = |_, _| box_once(Ok(filename().map_or_else(V::default, V::from)));
+    let line: RunPtr<V> = |_, _| {
+        let line = LINE.with(Cell::get);
+        box_once(Ok(line.map_or_else(V::default, |l| V::from(l as isize))))
+    };
+    [
+        ("input_filename", v(0), filename),
+        ("input_line_number", v(0), line),
+    ]
+    .map(run)
+    .into_iter()
+}
@@ -17,6 +17,9 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+pub mod csv;
+#[cfg(feature = "std")]
+pub mod input;
 #[cfg(feature = "math")]
 mod math;
 #[cfg(feature = "regex")]
@@ -463,6 +466,30 @@ fn replace(s: &str, patterns: &[&str], replacements: &[&str]) -> String {
     ac.replace_all(s, replacements)
 }
 
+/// Parse a string as CSV or TSV and yield its records.
+///
+/// If the options object `opts` has a true `header` field,
+/// then every record after the first one is yielded as object.
+#[cfg(feature = "format")]
+fn from_records<'a, V: ValT + 'a>(
+    v: V,
+    opts: Option<V>,
+    fmt: &str,
+    parse: fn(&str) -> Result<Vec<csv::Record>, String>,
+) -> ValXs<'a, V> {
+    let header = opts.map_or(Ok(false), |o| {
+        Ok(o.index(&V::from("header".to_string()))?.as_bool())
+    });
+    let vals = header.and_then(|header| {
+        let s = v.try_as_str()?;
+        let err = |e| Error::str(format_args!("cannot parse {s} as {fmt}: {e}"));
+        parse(s).and_then(|r| csv::records(r, header)).map_err(err)
+    });
+    then(vals.map_err(Exn::from), |vals| {
+        Box::new(vals.into_iter().map(Ok))
+    })
+}
+
 #[cfg(feature = "format")]
 fn format<V: ValT>() -> Box<[Filter<RunPtr<V>>]> {
     Box::new([
@@ -472,9 +499,21 @@ fn format<V: ValT>() -> Box<[Filter<RunPtr<V>>]> {
             bome(cv.1.try_as_str().map(|s| replace(s, &pats, &reps).into()))
         }),
         ("escape_tsv", v(0), |_, cv| {
-            let pats = ["\n", "\r", "\t", "\\", "\0"];
-            let reps = ["\\n", "\\r", "\\t", "\\\\", "\\0"];
-            bome(cv.1.try_as_str().map(|s| replace(s, &pats, &reps).into()))
+            bome(cv.1.try_as_str().map(|s| csv::escape_tsv(s).into()))
+        }),
+        ("fromcsv", v(0), |_, cv| {
+            from_records(cv.1, None, "CSV", csv::parse_csv)
+        }),
+        ("fromcsv", v(1), |_, mut cv| {
+            let opts = cv.0.pop_var();
+            from_records(cv.1, Some(opts), "CSV", csv::parse_csv)
+        }),
+        ("fromtsv", v(0), |_, cv| {
+            from_records(cv.1, None, "TSV", |s| Ok(csv::parse_tsv(s)))
+        }),
+        ("fromtsv", v(1), |_, mut cv| {
+            let opts = cv.0.pop_var();
+            from_records(cv.1, Some(opts), "TSV", |s| Ok(csv::parse_tsv(s)))
         }),
         ("encode_uri", v(0), |_, cv| {
             use urlencoding::encode;
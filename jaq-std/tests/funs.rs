@@ -118,6 +118,27 @@ yields!(
     "hello cruel world"
 );
 
+yields!(
+    fromcsv,
+    r#"[" a ,\"b, \"\"c\"\"\"\r\n\n\"d\ne\"" | fromcsv]"#,
+    json!([[" a ", "b, \"c\""], ["d\ne"]])
+);
+yields!(
+    fromcsv_header,
+    r#"["a,b\n1,2\n3" | fromcsv({header: true})]"#,
+    json!([{"a": "1", "b": "2"}, {"a": "3"}])
+);
+yields!(
+    fromcsv_roundtrip,
+    r#"[1, "x,\"y\"\n"] | @csv | fromcsv"#,
+    json!(["1", "x,\"y\"\n"])
+);
+yields!(
+    fromtsv_roundtrip,
+    r#"["a\tb", "\\n", null] | @tsv | fromtsv"#,
+    json!(["a\tb", "\\n", ""])
+);
+
 yields!(
     escape_html,
     r#""<p style='visibility: hidden'>sneaky</p>" | escape_html"#,
@@ -207,7 +228,7 @@ fn regex() {
           "string": s
         })
     };
-    let d1 = json!([c(00, "2012-03-14"), c(00, "2012"), c(05, "03"), c(08, "14")]);
+    let d1 = json!([c(00, "2012-03-14"), c(00, "2012"), c(5, "03"), c(8, "14")]);
     let d2 = json!([c(12, "2013-01-01"), c(12, "2013"), c(17, "01"), c(20, "01")]);
     let d3 = json!([c(27, "2014-07-05"), c(27, "2014"), c(32, "07"), c(35, "05")]);
 
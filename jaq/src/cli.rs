@@ -1,5 +1,6 @@
 //! Command-line argument parsing
 use core::fmt;
+use jaq_json::Bytes;
 use std::env::ArgsOs;
 use std::ffi::OsString;
 use std::path::PathBuf;
@@ -11,27 +12,83 @@ pub struct Cli {
     /// When the option `--slurp` is used additionally,
     /// then the whole input is read into a single string.
     pub raw_input: bool,
+    /// This flag enables `--raw-input`, but
+    /// splits the input at NUL characters instead of newlines.
+    pub raw_input0: bool,
     /// When input is read from files,
     /// jaq yields an array for each file, whereas
     /// jq produces only a single array.
     pub slurp: bool,
+    /// Read input values as stream of `[path, leaf]` and `[path]` events.
+    pub stream: bool,
+    /// Format of input values.
+    /// This is ignored if `--raw-input` is given.
+    pub from: InFormat,
+    /// Read the first record of CSV/TSV input as header,
+    /// yielding every further record as object instead of array.
+    pub header: bool,
+    /// Conversion of byte strings in binary input formats.
+    pub bytes: Bytes,
+    /// When a JSON input value cannot be parsed,
+    /// report it and continue parsing at the next line.
+    pub continue_on_error: bool,
 
     // Output options
     pub compact_output: bool,
     pub raw_output: bool,
     /// This flag enables `--raw-output`.
     pub join_output: bool,
+    /// Escape all non-ASCII characters in strings.
+    /// Like in jq, this flag overrides `--raw-output` for strings
+    /// that contain non-ASCII characters.
+    pub ascii_output: bool,
+    /// This flag enables `--raw-output`, but
+    /// terminates every output with NUL instead of a newline.
+    /// It is an error to output a string that contains NUL.
+    pub raw_output0: bool,
     pub in_place: bool,
+    /// Before overwriting a file in place, copy it to a file with this suffix.
+    /// This flag enables `--in-place`.
+    pub backup: Option<String>,
+    /// Instead of overwriting files in place, print the paths of files that would change.
+    /// This flag enables `--in-place`.
+    pub dry_run: bool,
+    /// Instead of overwriting files in place, print a unified diff of the changes.
+    /// This flag enables `--in-place`.
+    pub diff: bool,
     pub sort_keys: bool,
     pub color_output: bool,
     pub monochrome_output: bool,
     pub tab: bool,
     pub indent: usize,
+    /// Prefix every output value with the ASCII record separator (RS), and
+    /// read input as JSON text sequence (RFC 7464), skipping invalid records.
+    pub seq: bool,
+    /// Format of output values.
+    /// This is ignored for strings if `--raw-output` is given.
+    pub to: OutFormat,
+
+    // Evaluation options
+    /// Evaluate the filter on inputs in parallel using this many threads.
+    /// Values of 0 and 1 disable parallel evaluation.
+    pub threads: usize,
+    /// Print execution statistics of definitions after running the filter.
+    pub profile: bool,
+    /// Print every evaluated term with its input and outputs.
+    pub trace: bool,
 
     // Compilation options
     pub from_file: bool,
     /// If this option is given multiple times, all given directories are searched.
     pub library_path: Vec<PathBuf>,
+    /// Format in which errors are reported.
+    pub error_format: ErrorFormat,
+    /// Report warnings about the filter instead of running it.
+    pub lint: bool,
+    /// Print the parsed filter instead of running it.
+    pub dump_ast: bool,
+    /// Print the compiled filter instead of running it.
+    pub dump_ir: bool,
 
     // Key-value options
     pub arg: Vec<(String, String)>,
@@ -43,8 +100,8 @@ pub struct Cli {
     /// If this argument is not given, it is assumed to be `.`, the identity filter.
     pub filter: Option<Filter>,
     pub files: Vec<PathBuf>,
-    pub args: Vec<String>,
-    //pub jsonargs: Vec<String>,
+    /// Positional arguments given after `--args` or `--jsonargs`, in their original order.
+    pub args: Vec<Arg>,
     pub run_tests: Option<Vec<PathBuf>>,
     /// If there is some last output value `v`,
     /// then the exit status code is
@@ -54,10 +111,111 @@ pub struct Cli {
     ///
     /// If any error occurs, then this option has no effect.
     pub exit_status: bool,
+    /// Read filters interactively; the first positional argument is an input file.
+    pub repl: bool,
+    /// Print the structural differences between the values in the two files
+    /// given as positional arguments.
+    pub compare: bool,
+    /// Format the programs in the files given as positional arguments.
+    pub fmt: bool,
+    /// With `--fmt`, print the paths of files that are not formatted and
+    /// return exit status code 1 if there are any.
+    pub check: bool,
     pub version: bool,
     pub help: bool,
 }
 
+/// Positional argument that is collected into `$ARGS.positional`.
+#[derive(Debug)]
+pub enum Arg {
+    /// argument given after `--args`, used as string
+    Str(String),
+    /// argument given after `--jsonargs`, parsed as JSON value
+    Json(String),
+}
+
+/// Data format of input values.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum InFormat {
+    #[default]
+    Json,
+    /// YAML, where every document is a value
+    Yaml,
+    /// TOML, where every document is an object
+    Toml,
+    /// CBOR, where every data item is a value
+    Cbor,
+    /// MessagePack
+    Msgpack,
+    /// CSV (RFC 4180), where every record is a value
+    Csv,
+    /// TSV, like CSV
+    Tsv,
+}
+
+const IN_FORMATS: &[(&str, InFormat)] = &[
+    ("json", InFormat::Json),
+    ("yaml", InFormat::Yaml),
+    ("toml", InFormat::Toml),
+    ("cbor", InFormat::Cbor),
+    ("msgpack", InFormat::Msgpack),
+    ("csv", InFormat::Csv),
+    ("tsv", InFormat::Tsv),
+];
+
+/// Data format of output values.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutFormat {
+    #[default]
+    Json,
+    Yaml,
+    Toml,
+    Cbor,
+    Msgpack,
+    /// CSV, where every array of objects is a table
+    Csv,
+    /// TSV, like CSV
+    Tsv,
+    /// Markdown table, like CSV
+    Markdown,
+    /// table with aligned columns for terminals, like CSV
+    Table,
+}
+
+impl OutFormat {
+    /// Return true if every output value is written as table.
+    pub fn is_table(self) -> bool {
+        matches!(self, Self::Csv | Self::Tsv | Self::Markdown | Self::Table)
+    }
+}
+
+const OUT_FORMATS: &[(&str, OutFormat)] = &[
+    ("json", OutFormat::Json),
+    ("yaml", OutFormat::Yaml),
+    ("toml", OutFormat::Toml),
+    ("cbor", OutFormat::Cbor),
+    ("msgpack", OutFormat::Msgpack),
+    ("csv", OutFormat::Csv),
+    ("tsv", OutFormat::Tsv),
+    ("markdown", OutFormat::Markdown),
+    ("table", OutFormat::Table),
+];
+
+/// Format of error messages.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// human-readable text, showing erroneous code
+    #[default]
+    Text,
+    /// one JSON object per line for every error
+    Json,
+}
+
+const ERROR_FORMATS: &[(&str, ErrorFormat)] =
+    &[("text", ErrorFormat::Text), ("json", ErrorFormat::Json)];
+
+const BYTES: &[(&str, Bytes)] = &[("base64", Bytes::Base64), ("array", Bytes::Array)];
+
 #[derive(Debug)]
 pub enum Filter {
     Inline(String),
@@ -75,8 +233,8 @@ impl Cli {
         } else {
             match mode {
                 Mode::Files => self.files.push(arg.into()),
-                Mode::Args => self.args.push(arg.into_string()?),
-                //Mode::JsonArgs => self.jsonargs.push(arg.into_string()?),
+                Mode::Args => self.args.push(Arg::Str(arg.into_string()?)),
+                Mode::JsonArgs => self.args.push(Arg::Json(arg.into_string()?)),
             }
         }
         Ok(())
@@ -90,19 +248,45 @@ impl Cli {
 
             "null-input" => self.short('n', args)?,
             "raw-input" => self.short('R', args)?,
+            "raw-input0" => {
+                self.raw_input = true;
+                self.raw_input0 = true;
+            }
             "slurp" => self.short('s', args)?,
+            "stream" => self.stream = true,
+            "from" => self.from = parse_choice("--from", IN_FORMATS, args)?,
+            "header" => self.header = true,
+            "bytes" => self.bytes = parse_choice("--bytes", BYTES, args)?,
+            "continue-on-error" => self.continue_on_error = true,
 
             "compact-output" => self.short('c', args)?,
             "raw-output" => self.short('r', args)?,
             "join-output" => self.short('j', args)?,
+            "raw-output0" => self.raw_output0 = true,
+            "ascii-output" => self.short('a', args)?,
             "in-place" => self.short('i', args)?,
+            "backup" => self.backup = Some("~".to_string()),
+            "dry-run" => self.dry_run = true,
+            "diff" => self.diff = true,
             "sort-keys" => self.short('S', args)?,
             "color-output" => self.short('C', args)?,
             "monochrome-output" => self.short('M', args)?,
             "tab" => self.tab = true,
             "indent" => self.indent = args.next().and_then(int).ok_or(Error::Int("--indent"))?,
+            "seq" => self.seq = true,
+            "to" => self.to = parse_choice("--to", OUT_FORMATS, args)?,
+            "threads" => self.threads = args.next().and_then(int).ok_or(Error::Int("--threads"))?,
+            "profile" if cfg!(feature = "profile") => self.profile = true,
+            "profile" => Err(Error::Feature("--profile", "profile"))?,
+            "trace" => self.trace = true,
             "from-file" => self.short('f', args)?,
             "library-path" => self.short('L', args)?,
+            "lint" => self.lint = true,
+            "dump-ast" => self.dump_ast = true,
+            "dump-ir" => self.dump_ir = true,
+            "error-format" => {
+                self.error_format = parse_choice("--error-format", ERROR_FORMATS, args)?
+            }
             "arg" => {
                 let (name, value) = parse_key_val("--arg", args)?;
                 self.arg.push((name, value.into_string()?));
@@ -115,13 +299,26 @@ impl Cli {
             "rawfile" => self.rawfile.push(parse_key_val("--rawfile", args)?),
 
             "args" => *mode = Mode::Args,
-            //"jsonargs" => *mode = Mode::JsonArgs,
+            "jsonargs" => *mode = Mode::JsonArgs,
             "run-tests" => self.run_tests = Some(args.map(PathBuf::from).collect()),
             "exit-status" => self.short('e', args)?,
+            "repl" => self.repl = true,
+            "compare" => self.compare = true,
+            "fmt" => self.fmt = true,
+            "check" => self.check = true,
             "version" => self.short('V', args)?,
             "help" => self.short('h', args)?,
 
-            arg => Err(Error::Flag(format!("--{arg}")))?,
+            arg => match arg.split_once('=') {
+                Some(("backup", suffix)) if !suffix.is_empty() => {
+                    self.backup = Some(suffix.to_string())
+                }
+                Some(("error-format", format)) => {
+                    let format = core::iter::once(format.into());
+                    self.error_format = parse_choice("--error-format", ERROR_FORMATS, format)?
+                }
+                _ => Err(Error::Flag(format!("--{arg}")))?,
+            },
         }
         Ok(())
     }
@@ -135,6 +332,7 @@ impl Cli {
             'c' => self.compact_output = true,
             'r' => self.raw_output = true,
             'j' => self.join_output = true,
+            'a' => self.ascii_output = true,
             'i' => self.in_place = true,
             'S' => self.sort_keys = true,
             'C' => self.color_output = true,
@@ -176,9 +374,68 @@ impl Cli {
                 None => cli.positional(&mode, arg)?,
             }
         }
+        if cli.fmt {
+            // `jaq --fmt a.jq` formats files, so there is no filter
+            cli.no_filter();
+            if cli.check && !cli.diff {
+                cli.dry_run = true;
+            }
+        }
+        if cli.backup.is_some() || cli.dry_run || cli.diff {
+            cli.in_place = true;
+        }
+        if cli.repl {
+            cli.no_filter();
+        }
+        if cli.compare {
+            // `jaq --compare a b` compares two files, so there is no filter
+            cli.no_filter();
+            if cli.files.len() != 2 {
+                Err(Error::Paths("--compare", 2))?
+            }
+        }
+        if cli.continue_on_error {
+            // only plain JSON input can be resumed at the next line
+            let conflicts = [
+                (cli.seq, "--seq"),
+                (cli.stream, "--stream"),
+                (cli.from != InFormat::Json, "--from"),
+            ];
+            if let Some((_, o)) = conflicts.into_iter().find(|(c, _)| *c) {
+                Err(Error::Conflict("--continue-on-error", o))?
+            }
+        }
+        if cli.from != InFormat::Json {
+            // only JSON input can be split into records or streamed
+            let conflicts = [(cli.seq, "--seq"), (cli.stream, "--stream")];
+            if let Some((_, o)) = conflicts.into_iter().find(|(c, _)| *c) {
+                Err(Error::Conflict(o, "--from"))?
+            }
+        }
+        if cli.threads > 1 {
+            // parallel evaluation requires inputs that can be split at line ends
+            let conflicts = [
+                (cli.slurp, "--slurp"),
+                (cli.stream, "--stream"),
+                (cli.seq, "--seq"),
+                (cli.from != InFormat::Json, "--from"),
+            ];
+            if let Some((_, o)) = conflicts.into_iter().find(|(c, _)| *c) {
+                Err(Error::Conflict("--threads", o))?
+            }
+        }
         Ok(cli)
     }
 
+    /// Treat the filter argument as first input file.
+    fn no_filter(&mut self) {
+        match self.filter.take() {
+            Some(Filter::Inline(path)) => self.files.insert(0, path.into()),
+            Some(Filter::FromFile(path)) => self.files.insert(0, path),
+            None => (),
+        }
+    }
+
     pub fn color_if(&self, f: impl Fn() -> bool) -> bool {
         if self.monochrome_output {
             false
@@ -197,6 +454,10 @@ pub enum Error {
     KeyValue(&'static str),
     Int(&'static str),
     Path(&'static str),
+    Choice(&'static str, Vec<&'static str>),
+    Conflict(&'static str, &'static str),
+    Paths(&'static str, usize),
+    Feature(&'static str, &'static str),
 }
 
 impl fmt::Display for Error {
@@ -207,6 +468,12 @@ impl fmt::Display for Error {
             Self::KeyValue(o) => write!(f, "{o} expects a key and a value"),
             Self::Int(o) => write!(f, "{o} expects an integer"),
             Self::Path(o) => write!(f, "{o} expects a path"),
+            Self::Choice(o, choices) => write!(f, "{o} expects one of {}", choices.join(", ")),
+            Self::Conflict(o1, o2) => write!(f, "{o1} cannot be used with {o2}"),
+            Self::Paths(o, n) => write!(f, "{o} expects {n} paths"),
+            Self::Feature(o, feat) => {
+                write!(f, "{o} requires jaq to be built with feature `{feat}`")
+            }
         }
     }
 }
@@ -225,9 +492,21 @@ fn parse_key_val(arg: &'static str, args: &mut ArgsOs) -> Result<(String, OsStri
     Ok((key, val))
 }
 
+/// Parse an argument that must be one of the given names.
+fn parse_choice<T: Copy>(
+    arg: &'static str,
+    choices: &[(&'static str, T)],
+    mut args: impl Iterator<Item = OsString>,
+) -> Result<T, Error> {
+    let name = args.next().and_then(|s| s.into_string().ok());
+    let found = name.and_then(|name| choices.iter().find(|(n, _)| *n == name));
+    let err = || Error::Choice(arg, choices.iter().map(|(n, _)| *n).collect());
+    found.map(|(_, x)| *x).ok_or_else(err)
+}
+
 /// Interpretation of positional arguments.
 enum Mode {
     Args,
-    //JsonArgs,
+    JsonArgs,
     Files,
 }
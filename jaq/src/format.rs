@@ -0,0 +1,899 @@
+//! Formatting of jq programs.
+//!
+//! The formatter parses a program and prints it with canonical layout:
+//! every construct is printed on a single line if it fits into [`WIDTH`] characters,
+//! else its parts are broken into several lines and indented.
+//! For example, a long pipeline is printed with every filter on its own line,
+//! starting with `|`, and `if`, `elif`, `else`, and `end` are aligned.
+//!
+//! Comments are retained by the lexer, and the printer places every comment
+//! before the first token that follows it in the original program.
+//! Comments at the end of a line remain at the end of a line.
+use jaq_core::load::lex::{Lexer, StrPart, Tok, Token};
+use jaq_core::load::parse::{self, BinaryOp, Def, Parser, Pattern, Spanned, Term};
+use jaq_core::load::{self, span};
+use jaq_core::path::{Opt, Part};
+
+/// Number of characters per line that the formatter aims not to exceed.
+const WIDTH: usize = 80;
+
+/// Format a program or module, using `indent` for every level of indentation.
+pub fn format<'a>(code: &'a str, indent: &str) -> Result<String, load::Error<&'a str>> {
+    let (tokens, comments) = Lexer::new(code)
+        .lex_with_comments()
+        .map_err(load::Error::Lex)?;
+
+    let mut flat = Vec::new();
+    flatten(&tokens, &mut flat);
+    let mut p = Printer {
+        code,
+        tokens: flat,
+        comments: comments.into_iter().peekable(),
+        docs: Vec::new(),
+        last: 0,
+        end: code.len(),
+        printed: false,
+    };
+
+    // a module consists only of definitions, so it cannot be parsed as program
+    match Parser::new(&tokens).parse(|p| p.module(|p| p.term())) {
+        Ok(m) => p.module(&m, Printer::main),
+        Err(e) => match Parser::new(&tokens).parse(|p| p.module(|p| p.defs())) {
+            Ok(m) => p.module(&m, |p, defs| {
+                for d in defs {
+                    p.item(Some(p.offset(d.name)), |p| p.def(d))
+                }
+            }),
+            Err(_) => {
+                let conv_err = |(expected, found)| (expected, Token::opt_as_str(found, code));
+                return Err(load::Error::Parse(e.into_iter().map(conv_err).collect()));
+            }
+        },
+    }
+    p.comments_before(code.len());
+    Ok(render(&p.docs, indent))
+}
+
+/// Collect all tokens, including those inside of blocks and strings,
+/// together with whether they are leaves, such as words, variables, or `.`,
+/// which are not delimiters or operators.
+fn flatten<'a>(tokens: &[Token<&'a str>], acc: &mut Vec<(&'a str, bool)>) {
+    for Token(s, tok) in tokens {
+        let leaf = match tok {
+            Tok::Sym => s.starts_with('.'),
+            // `[]` and `{}` are printed via [`Printer::sym`]
+            Tok::Block(tokens) => tokens.len() == 1,
+            Tok::Word | Tok::Var | Tok::Fmt | Tok::Num | Tok::Str(_) => true,
+        };
+        acc.push((*s, leaf));
+        match tok {
+            Tok::Block(tokens) => flatten(tokens, acc),
+            Tok::Str(parts) => parts.iter().for_each(|part| {
+                if let StrPart::Term(t) = part {
+                    flatten(core::slice::from_ref(t), acc)
+                }
+            }),
+            _ => (),
+        }
+    }
+}
+
+/// Element of a document to be rendered.
+enum Doc {
+    Text(String),
+    /// line break, or the given string if the surrounding group fits on a line
+    Line(&'static str),
+    HardLine,
+    /// comment on its own line
+    Comment(String),
+    /// comment at the end of the current line
+    TrailingComment(String),
+    /// start of a group, whose lines are either all broken or none
+    Begin,
+    End,
+    Indent,
+    Dedent,
+}
+
+/// Render a document, breaking the lines of groups that do not fit on a line.
+fn render(docs: &[Doc], indent: &str) -> String {
+    // width of every group if printed on a single line, indexed by its start
+    let mut widths = vec![None; docs.len()];
+    let mut stack: Vec<(usize, Option<usize>)> = Vec::new();
+    for (i, doc) in docs.iter().enumerate() {
+        let width = match doc {
+            Doc::Begin => {
+                stack.push((i, Some(0)));
+                continue;
+            }
+            Doc::End => match stack.pop() {
+                Some((start, width)) => {
+                    widths[start] = width;
+                    width
+                }
+                // an end without a beginning does not affect any group
+                None => continue,
+            },
+            Doc::Text(s) if s.contains('\n') => None,
+            Doc::Text(s) => Some(s.chars().count()),
+            Doc::Line(s) => Some(s.len()),
+            Doc::HardLine | Doc::Comment(_) | Doc::TrailingComment(_) => None,
+            Doc::Indent | Doc::Dedent => Some(0),
+        };
+        if let Some((_, w)) = stack.last_mut() {
+            *w = w.zip(width).map(|(w, width)| w + width)
+        }
+    }
+
+    let mut r = Renderer {
+        out: String::new(),
+        indent,
+        level: 0,
+        column: 0,
+        suffix: None,
+        pending: false,
+    };
+    // for every group that we are in, whether it is printed on a single line
+    let mut flat: Vec<bool> = Vec::new();
+    for (doc, width) in docs.iter().zip(widths) {
+        match doc {
+            Doc::Begin => {
+                let parent = flat.last().copied().unwrap_or(false);
+                let fits = width.map_or(false, |w| r.column + w <= WIDTH);
+                flat.push(parent || fits)
+            }
+            Doc::End => {
+                flat.pop();
+            }
+            Doc::Text(s) => r.text(s),
+            Doc::Line(s) if flat.last().copied().unwrap_or(false) => r.text(s),
+            Doc::Line(_) | Doc::HardLine => r.newline(),
+            Doc::Comment(c) => {
+                if r.column > r.level * indent.chars().count() {
+                    r.newline()
+                }
+                r.text(c);
+                r.pending = true;
+            }
+            Doc::TrailingComment(c) => match &mut r.suffix {
+                // several comments that end up on the same line are joined
+                Some(suffix) => {
+                    suffix.push(' ');
+                    suffix.push_str(c)
+                }
+                None => r.suffix = Some(c.clone()),
+            },
+            Doc::Indent => r.level += 1,
+            Doc::Dedent => r.level -= 1,
+        }
+    }
+    r.level = 0;
+    r.newline();
+    let out = r.out.trim_matches('\n');
+    if out.is_empty() {
+        String::new()
+    } else {
+        format!("{out}\n")
+    }
+}
+
+struct Renderer<'i> {
+    out: String,
+    indent: &'i str,
+    level: usize,
+    column: usize,
+    /// trailing comment that is printed before the next line break
+    suffix: Option<String>,
+    /// whether a line break has to precede the next text, such as after a comment
+    pending: bool,
+}
+
+impl Renderer<'_> {
+    fn text(&mut self, s: &str) {
+        if self.pending {
+            self.newline();
+            if s.trim().is_empty() {
+                return;
+            }
+        }
+        self.out.push_str(s);
+        self.column = match s.rfind('\n') {
+            Some(i) => s[i + 1..].chars().count(),
+            None => self.column + s.chars().count(),
+        };
+    }
+
+    fn newline(&mut self) {
+        self.out
+            .truncate(self.out.trim_end_matches([' ', '\t']).len());
+        if let Some(c) = self.suffix.take() {
+            // on an empty line, a trailing comment is indented like the next line
+            if self.out.is_empty() || self.out.ends_with('\n') {
+                self.out.push_str(&self.indent.repeat(self.level))
+            } else {
+                self.out.push(' ')
+            }
+            self.out.push_str(&c)
+        }
+        self.pending = false;
+        self.out.push('\n');
+        self.out.push_str(&self.indent.repeat(self.level));
+        self.column = self.level * self.indent.chars().count();
+    }
+}
+
+/// Context in which a term is printed, which determines whether it needs parentheses.
+#[derive(Copy, Clone)]
+enum Ctx {
+    /// any term, containing `,` only if true
+    Term(bool),
+    /// operand of binary operators, such as `1 + 2`, having at least the given precedence
+    Op(usize),
+    /// term that may be followed by a binary operator or `catch`, such as `try 1` or `-1`
+    Atom,
+    /// term that may be followed by a path or `?`, such as `f` in `f[0]`
+    Suffix,
+}
+
+/// Conversion of a syntax tree to a document, which also places comments.
+struct Printer<'a> {
+    code: &'a str,
+    /// all tokens of the code in order, and whether they are leaves
+    tokens: Vec<(&'a str, bool)>,
+    comments: core::iter::Peekable<std::vec::IntoIter<&'a str>>,
+    docs: Vec<Doc>,
+    /// end of the last token from the code that we printed
+    last: usize,
+    /// offset of the closing delimiter of the block that we are printing
+    end: usize,
+    /// whether we printed any token from the code
+    printed: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn offset(&self, s: &str) -> usize {
+        span(self.code, s).start
+    }
+
+    /// Print all comments that start before the given offset.
+    fn comments_before(&mut self, offset: usize) {
+        let code = self.code;
+        while let Some(c) = self.comments.next_if(|c| span(code, c).start < offset) {
+            let start = self.offset(c);
+            if self.is_trailing(start) {
+                // attach the comment to the line before any line break that we just printed
+                let docs = self.docs.iter().rev();
+                let n = docs
+                    .take_while(|d| {
+                        matches!(d, Doc::Line(_) | Doc::Begin | Doc::Indent | Doc::Dedent)
+                    })
+                    .count();
+                let i = self.docs.len() - n;
+                self.docs.insert(i, Doc::TrailingComment(c.to_string()))
+            } else {
+                self.docs.push(Doc::Comment(c.to_string()))
+            }
+        }
+    }
+
+    /// Return true if a comment starting at the given offset follows a token on the same line.
+    ///
+    /// Such a comment stays at the end of a line.
+    fn is_trailing(&self, start: usize) -> bool {
+        let i = self.tokens.partition_point(|(s, _)| self.offset(s) < start);
+        let ends = self.tokens[..i].iter().rev();
+        let mut ends = ends.map(|(s, _)| self.offset(s) + s.len());
+        // skip blocks that contain the comment
+        let end = ends.find(|end| *end <= start);
+        end.map_or(false, |end| !self.code[end..start].contains('\n'))
+    }
+
+    /// Return the tokens that have not been printed yet.
+    fn rest(&self) -> impl Iterator<Item = (usize, &'a str, bool)> + '_ {
+        let i = (self.tokens).partition_point(|(s, _)| self.offset(s) < self.last);
+        let tokens = self.tokens[i..].iter();
+        tokens.map(|(s, leaf)| (self.offset(s), *s, *leaf))
+    }
+
+    /// Return the offset of the next leaf that has not been printed yet.
+    ///
+    /// Comments before this offset are printed before the next line break.
+    fn next_leaf(&self) -> usize {
+        let mut rest = self.rest();
+        let next = rest.find(|(_, _, leaf)| *leaf);
+        next.map_or(self.end, |(start, ..)| start.min(self.end))
+    }
+
+    /// Return the next token that has not been printed yet and starts with `c`.
+    fn next_token_with(&self, c: char) -> Option<(usize, &'a str)> {
+        let mut rest = self.rest();
+        rest.find(|(_, s, _)| s.starts_with(c))
+            .map(|(start, s, _)| (start, s))
+    }
+
+    /// Return the offset of the first token of a term.
+    fn start(&self, t: &Spanned<&'a str>) -> Option<usize> {
+        let next = |c| self.next_token_with(c).map(|(start, _)| start);
+        match &t.term {
+            Term::Id | Term::Recurse => next('.'),
+            Term::Arr(_) => next('['),
+            Term::Obj(_) => next('{'),
+            Term::Str(None, _) => next('"'),
+            Term::Neg(_) => next('-'),
+            Term::Pipe(l, ..) | Term::BinOp(l, ..) | Term::TryCatch(l, _) | Term::Path(l, _) => {
+                self.start(l)
+            }
+            Term::Num(s) | Term::Var(s) | Term::Break(s) | Term::Label(s, _) => {
+                Some(self.offset(s))
+            }
+            Term::Str(Some(s), _) | Term::Fold(s, ..) | Term::Call(s, _) => Some(self.offset(s)),
+            Term::IfThenElse(if_thens, _) => self.start(&if_thens[0].0),
+            Term::Def(defs, _) => Some(self.offset(defs[0].name)),
+        }
+    }
+
+    fn text(&mut self, s: &str) {
+        self.docs.push(Doc::Text(s.to_string()))
+    }
+
+    /// Print a token from the code, preceded by the comments before it.
+    fn token(&mut self, s: &'a str) {
+        let start = self.offset(s);
+        self.comments_before(start);
+        self.text(s);
+        self.last = self.last.max(start + s.len());
+        self.printed = true;
+    }
+
+    /// Print `s`, which corresponds to the next token in the code that starts like `s`,
+    /// such as `.` or `[]`.
+    fn sym(&mut self, s: &str) {
+        if let Some((start, token)) = s.chars().next().and_then(|c| self.next_token_with(c)) {
+            self.comments_before(start);
+            self.last = start + token.len();
+        }
+        self.text(s);
+        self.printed = true;
+    }
+
+    fn line(&mut self) {
+        self.comments_before(self.next_leaf());
+        self.docs.push(Doc::Line(" "))
+    }
+
+    fn softline(&mut self) {
+        self.comments_before(self.next_leaf());
+        self.docs.push(Doc::Line(""))
+    }
+
+    fn group(&mut self, f: impl FnOnce(&mut Self)) {
+        self.docs.push(Doc::Begin);
+        f(self);
+        self.docs.push(Doc::End);
+    }
+
+    fn indent(&mut self, f: impl FnOnce(&mut Self)) {
+        self.docs.push(Doc::Indent);
+        f(self);
+        self.docs.push(Doc::Dedent);
+    }
+
+    /// Print `open`, then the output of `f` indented, then `close`.
+    fn delimit(&mut self, open: &str, close: &str, f: impl FnOnce(&mut Self)) {
+        // offset of the closing delimiter in the code
+        let outer = self.end;
+        let next = open.chars().next().and_then(|c| self.next_token_with(c));
+        if let Some((start, token)) = next {
+            self.comments_before(start);
+            self.last = start + open.len();
+            self.end = start + token.len() - close.len();
+        }
+        self.group(|p| {
+            p.text(open);
+            p.indent(|p| {
+                p.softline();
+                f(p)
+            });
+            // comments after the closing delimiter are printed after it
+            p.comments_before(p.end);
+            p.docs.push(Doc::Line(""));
+            p.text(close)
+        });
+        self.end = outer;
+    }
+
+    /// Print elements separated by `sep` and a line.
+    fn sep<T>(&mut self, xs: &[T], sep: &str, mut f: impl FnMut(&mut Self, &T)) {
+        for (i, x) in xs.iter().enumerate() {
+            if i > 0 {
+                self.text(sep);
+                self.line()
+            }
+            f(self, x)
+        }
+    }
+
+    /// Print a top-level element, such as a definition, starting on a new line.
+    ///
+    /// If there is an empty line before the element in the code, then we keep it.
+    fn item(&mut self, start: Option<usize>, f: impl FnOnce(&mut Self)) {
+        let start = start.unwrap_or(self.code.len());
+        if self.printed {
+            let code = self.code;
+            // keep a comment at the end of the last line of the previous element
+            if let Some(c) = self.comments.peek().copied() {
+                let c = self.offset(c);
+                if c < start && self.is_trailing(c) {
+                    self.comments_before(c + 1)
+                }
+            }
+            let next = self.comments.peek().copied();
+            let next = next.map_or(start, |c| self.offset(c));
+            let next = next.min(start);
+            // lines between the previous element and this one, excluding the first and last
+            let lines: Vec<_> = code[self.last.min(next)..next].split('\n').collect();
+            let inner = lines.get(1..lines.len() - 1).unwrap_or_default();
+            self.docs.push(Doc::HardLine);
+            if inner.iter().any(|l| l.trim().is_empty()) {
+                self.docs.push(Doc::HardLine);
+            }
+        }
+        self.comments_before(start);
+        self.group(f)
+    }
+
+    fn module<B>(&mut self, m: &parse::Module<&'a str, B>, body: impl FnOnce(&mut Self, &B)) {
+        if let Some(meta) = &m.meta {
+            self.item(self.start(meta), |p| {
+                p.text("module ");
+                p.term(meta, Ctx::Term(true));
+                p.text(";")
+            })
+        }
+        for (path, name, meta) in &m.deps {
+            self.item(Some(self.offset(path)), |p| {
+                p.text(if name.is_some() {
+                    "import "
+                } else {
+                    "include "
+                });
+                p.text("\"");
+                p.token(path);
+                p.text("\"");
+                if let Some(name) = name {
+                    p.text(" as ");
+                    p.token(name)
+                }
+                if let Some(meta) = meta {
+                    p.text(" ");
+                    p.term(meta, Ctx::Term(true))
+                }
+                p.text(";")
+            })
+        }
+        body(self, &m.body)
+    }
+
+    /// Print the main filter of a program, printing its definitions on separate lines.
+    fn main(&mut self, t: &Spanned<&'a str>) {
+        match &t.term {
+            Term::Def(defs, t) => {
+                for d in defs {
+                    self.item(Some(self.offset(d.name)), |p| p.def(d))
+                }
+                self.main(t)
+            }
+            _ => self.item(self.start(t), |p| p.term(t, Ctx::Term(true))),
+        }
+    }
+
+    fn def(&mut self, d: &Def<&'a str>) {
+        self.comments_before(self.offset(d.name));
+        self.group(|p| {
+            p.text("def ");
+            p.token(d.name);
+            if !d.args.is_empty() {
+                p.text("(");
+                for (i, arg) in d.args.iter().enumerate() {
+                    if i > 0 {
+                        p.text("; ")
+                    }
+                    p.token(arg)
+                }
+                p.text(")");
+            }
+            p.text(":");
+            p.indent(|p| {
+                p.line();
+                p.term(&d.body, Ctx::Term(true))
+            });
+            p.text(";")
+        })
+    }
+
+    fn term(&mut self, t: &Spanned<&'a str>, ctx: Ctx) {
+        if let Some(start) = self.start(t) {
+            self.comments_before(start)
+        }
+        if needs_parens(t, ctx) {
+            self.delimit("(", ")", |p| p.seq(t))
+        } else {
+            self.term_inner(t, ctx)
+        }
+    }
+
+    /// Print a term that is surrounded by delimiters,
+    /// printing the elements of `a, b, c` on separate lines if they do not fit.
+    fn seq(&mut self, t: &Spanned<&'a str>) {
+        match &t.term {
+            Term::BinOp(l, op @ BinaryOp::Comma, r) => self.binop(l, op, r),
+            _ => self.term(t, Ctx::Term(true)),
+        }
+    }
+
+    fn term_inner(&mut self, t: &Spanned<&'a str>, ctx: Ctx) {
+        match &t.term {
+            Term::Id => self.sym("."),
+            Term::Recurse => self.sym(".."),
+            Term::Num(n) | Term::Var(n) => self.token(n),
+            Term::Str(fmt, parts) => {
+                if let Some(fmt) = fmt {
+                    self.token(fmt);
+                    self.text(" ")
+                }
+                self.str(parts)
+            }
+            Term::Arr(None) => self.sym("[]"),
+            Term::Arr(Some(t)) => self.delimit("[", "]", |p| p.seq(t)),
+            Term::Obj(kvs) if kvs.is_empty() => self.sym("{}"),
+            Term::Obj(kvs) => self.delimit("{", "}", |p| p.sep(kvs, ",", Self::obj_entry)),
+            Term::Neg(t) => {
+                self.text("-");
+                match t.term {
+                    Term::Neg(_) => self.delimit("(", ")", |p| p.term(t, Ctx::Term(true))),
+                    _ => self.term(t, Ctx::Atom),
+                }
+            }
+            Term::Pipe(..) | Term::Label(..) => {
+                let comma = matches!(ctx, Ctx::Term(true));
+                self.group(|p| p.pipe(t, comma))
+            }
+            Term::BinOp(l, op, r) => self.group(|p| p.binop(l, op, r)),
+            Term::Break(x) => {
+                self.text("break ");
+                self.token(x)
+            }
+            Term::Fold(fold, xs, x, args) => {
+                self.token(fold);
+                self.text(" ");
+                self.term(xs, Ctx::Atom);
+                self.text(" as ");
+                self.pattern(x);
+                if !args.is_empty() {
+                    self.text(" ");
+                    self.args(args)
+                }
+            }
+            Term::TryCatch(t, None) if is_opt(t) => {
+                // unlike in `(..).a`, we do not need parentheses in `..?`
+                match t.term {
+                    Term::Recurse => self.sym(".."),
+                    _ => self.term(t, Ctx::Suffix),
+                }
+                self.text("?")
+            }
+            Term::TryCatch(t, catch) => {
+                self.text("try ");
+                self.term(t, Ctx::Atom);
+                if let Some(catch) = catch {
+                    self.text(" catch ");
+                    self.term(catch, Ctx::Atom)
+                }
+            }
+            Term::IfThenElse(if_thens, else_) => self.if_then_else(if_thens, else_.as_deref()),
+            Term::Def(defs, t) => self.group(|p| {
+                for d in defs {
+                    p.def(d);
+                    p.line()
+                }
+                p.term(t, Ctx::Term(true))
+            }),
+            Term::Call(name, args) => {
+                self.token(name);
+                if !args.is_empty() {
+                    self.args(args)
+                }
+            }
+            Term::Path(head, path) => {
+                let id = matches!(head.term, Term::Id);
+                match &head.term {
+                    Term::Id => (),
+                    // `f?[0]` does not need parentheses
+                    Term::TryCatch(t, None) if is_opt(t) => self.term_inner(head, Ctx::Suffix),
+                    _ => self.term(head, Ctx::Suffix),
+                }
+                for (i, (part, opt)) in path.0.iter().enumerate() {
+                    self.path_part(part, id && i == 0);
+                    if let Opt::Optional = opt {
+                        self.text("?")
+                    }
+                }
+            }
+        }
+    }
+
+    /// Print a sequence of terms separated by `|`, starting every term on a new line
+    /// if they do not all fit on a single line.
+    fn pipe(&mut self, mut t: &Spanned<&'a str>, comma: bool) {
+        // operands of `|` may only be of the shape `a, b` if `comma` is true
+        let lhs = Ctx::Op(if comma { 1 } else { 2 });
+        let mut first = true;
+        loop {
+            if !first {
+                self.line();
+                self.text("| ")
+            }
+            first = false;
+            match &t.term {
+                Term::Pipe(l, x, r) => {
+                    self.term(l, lhs);
+                    if let Some(x) = x {
+                        self.text(" as ");
+                        self.pattern(x)
+                    }
+                    t = r
+                }
+                Term::Label(x, r) => {
+                    self.comments_before(self.offset(x));
+                    self.text("label ");
+                    self.token(x);
+                    t = r
+                }
+                _ => return self.term(t, Ctx::Term(comma)),
+            }
+        }
+    }
+
+    /// Print a sequence of binary operations with the same precedence,
+    /// such as `1 + 2 - 3` or `a, b, c`, where `op` is the last operator.
+    fn binop<'t>(&mut self, l: &'t Spanned<&'a str>, op: &'t BinaryOp, r: &'t Spanned<&'a str>) {
+        let prec = op.precedence();
+        let right_assoc = op.is_right_assoc();
+        let comma = matches!(op, BinaryOp::Comma);
+        // collect operators and their right operands, such as `[(+, 2), (-, 3)]` for `1 + 2 - 3`
+        let mut ops = Vec::from([(op, r)]);
+        let mut head = l;
+        while let Term::BinOp(l, op, r) = &head.term {
+            if op.precedence() != prec || op.is_right_assoc() {
+                break;
+            }
+            ops.push((op, &**r));
+            head = l;
+        }
+        ops.reverse();
+
+        self.term(head, Ctx::Op(prec + usize::from(right_assoc)));
+        let rest = |p: &mut Self| {
+            for (op, t) in ops {
+                if !comma {
+                    p.text(" ")
+                }
+                p.text(op.as_str());
+                p.line();
+                p.term(t, Ctx::Op(prec + usize::from(!right_assoc)))
+            }
+        };
+        // elements of `a, b, c` are aligned, whereas operands of other operators are indented
+        if comma {
+            rest(self)
+        } else {
+            self.indent(rest)
+        }
+    }
+
+    fn if_then_else(
+        &mut self,
+        if_thens: &[(Spanned<&'a str>, Spanned<&'a str>)],
+        else_: Option<&Spanned<&'a str>>,
+    ) {
+        self.group(|p| {
+            for (i, (if_, then_)) in if_thens.iter().enumerate() {
+                p.text(if i == 0 { "if " } else { "elif " });
+                p.term(if_, Ctx::Term(true));
+                p.text(" then");
+                p.indent(|p| {
+                    p.line();
+                    p.term(then_, Ctx::Term(true))
+                });
+                p.line()
+            }
+            if let Some(else_) = else_ {
+                p.text("else");
+                p.indent(|p| {
+                    p.line();
+                    p.term(else_, Ctx::Term(true))
+                });
+                p.line()
+            }
+            p.text("end")
+        })
+    }
+
+    /// Print arguments of a filter, such as `(a; b)`.
+    fn args(&mut self, args: &[Spanned<&'a str>]) {
+        self.delimit("(", ")", |p| {
+            p.sep(args, ";", |p, a| p.term(a, Ctx::Term(true)))
+        })
+    }
+
+    fn path_part(&mut self, part: &Part<Spanned<&'a str>>, after_id: bool) {
+        match part {
+            Part::Index(k) => match key(k) {
+                Some(k) => {
+                    self.comments_before(self.offset(k));
+                    self.text(".");
+                    self.token(k)
+                }
+                None if matches!(k.term, Term::Str(..)) => {
+                    if after_id {
+                        self.sym(".")
+                    } else {
+                        self.text(".")
+                    }
+                    self.term(k, Ctx::Term(true))
+                }
+                None => {
+                    if after_id {
+                        self.sym(".")
+                    }
+                    self.delimit("[", "]", |p| p.term(k, Ctx::Term(true)))
+                }
+            },
+            Part::Range(from, upto) => {
+                if after_id {
+                    self.sym(".")
+                }
+                self.delimit("[", "]", |p| {
+                    if let Some(from) = from {
+                        p.term(from, Ctx::Term(true))
+                    }
+                    if from.is_some() || upto.is_some() {
+                        p.text(":")
+                    }
+                    if let Some(upto) = upto {
+                        p.term(upto, Ctx::Term(true))
+                    }
+                })
+            }
+        }
+    }
+
+    fn str(&mut self, parts: &[StrPart<&'a str, Spanned<&'a str>>]) {
+        // comments inside interpolated terms are printed before the string
+        if let Some((start, token)) = self.next_token_with('"') {
+            self.comments_before(start + token.len())
+        }
+        self.text("\"");
+        for part in parts {
+            match part {
+                StrPart::Str(s) => self.text(s),
+                StrPart::Char(c) => self.text(&escape(*c)),
+                StrPart::Term(t) => {
+                    self.text("\\(");
+                    self.seq(t);
+                    self.text(")")
+                }
+            }
+        }
+        self.text("\"")
+    }
+
+    fn obj_entry(&mut self, (k, v): &(Spanned<&'a str>, Option<Spanned<&'a str>>)) {
+        self.key(k);
+        if let Some(v) = v {
+            self.text(": ");
+            self.term(v, Ctx::Term(false))
+        }
+    }
+
+    /// Print the key of an object (pattern) entry.
+    fn key(&mut self, k: &Spanned<&'a str>) {
+        match (&k.term, key(k)) {
+            (Term::Var(x), _) => self.token(x),
+            (_, Some(k)) => self.token(k),
+            (Term::Str(..), None) => self.term(k, Ctx::Term(true)),
+            _ => self.delimit("(", ")", |p| p.seq(k)),
+        }
+    }
+
+    fn pattern(&mut self, p: &Pattern<&'a str>) {
+        match p {
+            Pattern::Var(x) => self.token(x),
+            Pattern::Arr(ps) => self.delimit("[", "]", |p| p.sep(ps, ",", Self::pattern)),
+            Pattern::Obj(kps) => self.delimit("{", "}", |p| {
+                p.sep(kps, ",", |p, (k, v)| match (key(k), v) {
+                    // `{$x}` is short for `{x: $x}`
+                    (Some(k), Pattern::Var(x)) if x[1..] == *k => p.token(x),
+                    _ => {
+                        p.key(k);
+                        p.text(": ");
+                        p.pattern(v)
+                    }
+                })
+            }),
+        }
+    }
+}
+
+/// Return true if a term has to be surrounded by parentheses in the given context.
+fn needs_parens<S>(t: &Spanned<S>, ctx: Ctx) -> bool {
+    match (ctx, &t.term) {
+        // definitions and labels extend as far to the right as possible,
+        // so they may only occur where the surrounding term ends anyway
+        (Ctx::Term(comma), Term::Def(..) | Term::Label(..)) => !comma,
+        (Ctx::Term(comma), Term::BinOp(_, BinaryOp::Comma, _)) => !comma,
+        (Ctx::Term(_), _) => false,
+        (_, Term::Pipe(..) | Term::Def(..) | Term::Label(..)) => true,
+        (Ctx::Op(prec), Term::BinOp(_, op, _)) => op.precedence() < prec,
+        (Ctx::Op(_), _) => false,
+        (Ctx::Atom, Term::BinOp(..)) => true,
+        // `try (try a) catch b` and `try (try a catch b)`
+        (Ctx::Atom, Term::TryCatch(t, catch)) => catch.is_some() || !is_opt(t),
+        (Ctx::Atom, _) => false,
+        (Ctx::Suffix, t) => !matches!(
+            t,
+            Term::Id
+                | Term::Str(..)
+                | Term::Arr(_)
+                | Term::Obj(_)
+                | Term::Call(..)
+                | Term::Var(_)
+                | Term::IfThenElse(..)
+                | Term::Fold(..)
+        ),
+    }
+}
+
+/// Return true if `try t` can be written as `t?`.
+///
+/// This is not the case for paths, because `.a?` is parsed as path with an optional part.
+fn is_opt<S>(t: &Spanned<S>) -> bool {
+    let suffix = matches!(t.term, Term::Recurse) || !needs_parens(t, Ctx::Suffix);
+    suffix && !matches!(t.term, Term::Path(..))
+}
+
+/// If the term is a string that can be written as identifier, such as `a` in `.a`, return it.
+fn key<'a>(t: &Spanned<&'a str>) -> Option<&'a str> {
+    match &t.term {
+        Term::Str(None, parts) => match parts[..] {
+            [StrPart::Str(s)] if is_ident(s) => Some(s),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    let start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    chars.next().map_or(false, start) && chars.all(|c| start(c) || c.is_ascii_digit())
+}
+
+fn escape(c: char) -> String {
+    match c {
+        '"' => "\\\"".into(),
+        '\\' => "\\\\".into(),
+        '/' => "\\/".into(),
+        '\x08' => "\\b".into(),
+        '\x0C' => "\\f".into(),
+        '\n' => "\\n".into(),
+        '\r' => "\\r".into(),
+        '\t' => "\\t".into(),
+        // characters outside the basic multilingual plane are written as surrogate pairs
+        c => c
+            .encode_utf16(&mut [0; 2])
+            .iter()
+            .map(|u| format!("\\u{u:04x}"))
+            .collect(),
+    }
+}
@@ -0,0 +1,245 @@
+//! Position of the input value that is currently processed.
+//!
+//! The readers in `main` record the file and the line of every value
+//! when they yield it, and the filters `input_filename` and
+//! `input_line_number` from [`jaq_std::input`] query the position of the value read last.
+//! The position is stored per thread, because
+//! every worker thread for `--threads` reads its own inputs.
+//!
+//! The line of a value is the line where it starts, counting from 1.
+//! This differs from jq, which yields the number of line breaks consumed by its parser.
+//! The line is unknown (null) for values read with
+//! `--stream`, `--seq`, or a non-JSON `--from` format.
+//!
+//! The position is also used to show where parsing an input value failed.
+use core::fmt::{self, Display, Formatter};
+use jaq_std::input::{filename, set_filename};
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+thread_local! {
+    /// number of the first line of the text that lines are counted from
+    static FIRST_LINE: Cell<usize> = const { Cell::new(1) };
+    /// byte offset of the text that bytes are counted from
+    static FIRST_BYTE: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Start reading inputs from the given file, or standard input if `None`.
+pub fn set_file(path: Option<&Path>) {
+    set_filename(path.map(|p| p.display().to_string()));
+    set_start(1, 0);
+}
+
+/// Count lines starting from `line` and bytes starting from `byte`.
+///
+/// This is used when reading a part of a file that does not start at its beginning.
+pub fn set_start(line: usize, byte: usize) {
+    FIRST_LINE.with(|l| l.set(line));
+    FIRST_BYTE.with(|b| b.set(byte));
+    jaq_std::input::set_line(None);
+}
+
+/// Set the line of the current input value,
+/// counted from zero at the start of the text read.
+pub fn set_line(line: Option<usize>) {
+    let first = FIRST_LINE.with(Cell::get);
+    jaq_std::input::set_line(line.map(|line| first + line));
+}
+
+/// Error that occurred when parsing an input value.
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    message: String,
+    /// name of the file, or `<stdin>`
+    file: String,
+    /// number of the line that contains the error
+    line: usize,
+    /// contents of the line that contains the error
+    code: String,
+    /// byte offset of the error in `code`
+    offset: usize,
+    /// byte offset of the error in the file
+    pos: usize,
+}
+
+impl ParseError {
+    /// Create an error at `offset` in `code`, which is the contents of the given line,
+    /// where `line` and `pos` (the offset of the error in the text read)
+    /// are counted from zero at the start of the text read.
+    pub fn new(message: impl Display, line: usize, code: &[u8], offset: usize, pos: usize) -> Self {
+        let code = code.strip_suffix(b"\r").unwrap_or(code);
+        let (before, after) = code.split_at(offset.min(code.len()));
+        let before = String::from_utf8_lossy(before);
+        let file = filename().unwrap_or_else(|| "<stdin>".to_string());
+        Self {
+            message: message.to_string(),
+            file,
+            line: FIRST_LINE.with(Cell::get) + line,
+            pos: FIRST_BYTE.with(Cell::get) + pos,
+            offset: before.len(),
+            code: before.into_owned() + &String::from_utf8_lossy(after),
+        }
+    }
+
+    /// Create an error at `offset` in `text`, where `line` is the line of `offset`.
+    pub fn from_slice(message: impl Display, text: &[u8], line: usize, offset: usize) -> Self {
+        let (before, after) = text.split_at(offset);
+        let start = before
+            .iter()
+            .rposition(|c| *c == b'\n')
+            .map_or(0, |i| i + 1);
+        let end = after
+            .iter()
+            .position(|c| *c == b'\n')
+            .map_or(text.len(), |i| offset + i);
+        Self::new(message, line, &text[start..end], offset - start, offset)
+    }
+
+    /// Return the message of the error without the erroneous code.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Return the message together with the location of the error on a single line.
+    ///
+    /// This is used for errors that can be caught by filters, such as `input`.
+    pub fn brief(&self) -> String {
+        let (file, line, column) = (&self.file, self.line, self.column());
+        format!("{} at {file}:{line}:{column}", self.message)
+    }
+
+    /// Return the file name, the byte span in the file, the line, and the column of the error.
+    ///
+    /// Lines and columns start at 1, where columns count characters.
+    pub fn location(&self) -> (&str, core::ops::Range<usize>, usize, usize) {
+        let span = self.pos..self.pos + self.len();
+        (&self.file, span, self.line, self.column())
+    }
+
+    fn column(&self) -> usize {
+        self.code[..self.offset].chars().count() + 1
+    }
+
+    /// Length of the erroneous character in bytes, or 0 at the end of the line.
+    fn len(&self) -> usize {
+        self.code[self.offset..]
+            .chars()
+            .next()
+            .map_or(0, char::len_utf8)
+    }
+}
+
+impl Display for ParseError {
+    /// Show the message and the line of the error, underlining the erroneous character.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let column = self.column();
+        writeln!(f, "{}", self.message)?;
+
+        let label = (
+            self.offset..self.offset + self.len(),
+            [(self.message.clone(), None)].into(),
+        );
+        let report = crate::Report {
+            message: self.message.clone(),
+            expected: None,
+            labels: Vec::from([(label.0, label.1, crate::Color::Red)]),
+        };
+        let idx = codesnake::LineIndex::new(&self.code);
+        let block = report.into_block(&idx);
+
+        // the block numbers the line as 1, so we replace this by the actual line number
+        let line = self.line.to_string();
+        let pad = " ".repeat(line.len() - 1);
+        let file = format!("{}:{}:{column}", self.file, self.line);
+        writeln!(f, "{pad}{}[{file}]", block.prologue())?;
+        for l in block.to_string().lines() {
+            match l.strip_prefix("1 ") {
+                Some(rest) => writeln!(f, "{line} {rest}")?,
+                None => writeln!(f, "{pad}{l}")?,
+            }
+        }
+        write!(f, "{}{}", "─".repeat(line.len() - 1), block.epilogue())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Bytes from a reader that keep track of the line read last.
+///
+/// This is shared between a lexer and its user, so that the user can
+/// locate an error of the lexer and skip the remainder of its line.
+pub struct LineBytes<I>(Rc<RefCell<Lines<I>>>);
+
+struct Lines<I> {
+    bytes: I,
+    /// number of line feeds read
+    newlines: usize,
+    /// number of bytes read
+    read: usize,
+    /// bytes of the current line read so far, including a final line feed
+    code: Vec<u8>,
+}
+
+impl<I> Clone for LineBytes<I> {
+    fn clone(&self) -> Self {
+        Self(Rc::clone(&self.0))
+    }
+}
+
+impl<I: Iterator<Item = io::Result<u8>>> Iterator for LineBytes<I> {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lines = &mut *self.0.borrow_mut();
+        let b = lines.bytes.next()?;
+        if let Ok(b) = b {
+            if lines.code.last() == Some(&b'\n') {
+                lines.code.clear()
+            }
+            lines.code.push(b);
+            lines.newlines += usize::from(b == b'\n');
+            lines.read += 1;
+        }
+        Some(b)
+    }
+}
+
+impl<I: Iterator<Item = io::Result<u8>>> LineBytes<I> {
+    pub fn new(bytes: I) -> Self {
+        let code = Vec::new();
+        Self(Rc::new(RefCell::new(Lines {
+            bytes,
+            newlines: 0,
+            read: 0,
+            code,
+        })))
+    }
+
+    /// Return the line of the byte read last, counted from zero.
+    pub fn line(&self) -> usize {
+        let lines = self.0.borrow();
+        lines.newlines - usize::from(lines.code.last() == Some(&b'\n'))
+    }
+
+    /// Create an error at the byte read last.
+    pub fn error(&self, message: impl Display) -> ParseError {
+        let lines = self.0.borrow();
+        let code = lines.code.strip_suffix(b"\n").unwrap_or(&lines.code);
+        let offset = lines.code.len().saturating_sub(1);
+        let pos = lines.read.saturating_sub(1);
+        ParseError::new(message, self.line(), code, offset, pos)
+    }
+
+    /// Read until the end of the line of the byte read last.
+    pub fn skip_line(&mut self) -> io::Result<()> {
+        while self.0.borrow().code.last() != Some(&b'\n') {
+            match self.next() {
+                None => break,
+                Some(b) => b.map(|_| ())?,
+            }
+        }
+        Ok(())
+    }
+}
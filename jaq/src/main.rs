@@ -1,13 +1,20 @@
 mod cli;
+mod format;
+mod input;
+mod repl;
+mod table;
+mod threads;
 
-use cli::Cli;
+use cli::{Cli, ErrorFormat, InFormat, OutFormat};
 use core::fmt::{self, Display, Formatter};
 use is_terminal::IsTerminal;
 use jaq_core::{compile, load, Ctx, Native, RcIter, ValT};
 use jaq_json::Val;
-use std::io::{self, BufRead, Write};
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::process::{ExitCode, Termination};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 type Filter = jaq_core::Filter<Native<Val>>;
 
@@ -52,9 +59,11 @@ fn main() -> ExitCode {
     };
 
     set_color(!cli.in_place && cli.color_if(|| std::io::stdout().is_terminal() && !no_color));
+    JSON_ERRORS.store(cli.error_format == ErrorFormat::Json, Ordering::Relaxed);
 
     match real_main(&cli) {
         Ok(exit) => exit,
+        Err(e) if cli.error_format == ErrorFormat::Json => e.report_json(),
         Err(e) => {
             set_color(cli.color_if(|| std::io::stderr().is_terminal() && !no_color));
             e.report()
@@ -70,51 +79,63 @@ fn real_main(cli: &Cli) -> Result<ExitCode, Error> {
         });
     }
 
-    let (vars, mut ctx): (Vec<String>, Vec<Val>) = binds(cli)?.into_iter().unzip();
+    if cli.fmt {
+        return format_files(cli);
+    } else if cli.compare {
+        return diff_files(cli);
+    } else if cli.repl {
+        return repl::repl(cli);
+    } else if cli.lint {
+        return lint_filter(cli);
+    } else if cli.dump_ast || cli.dump_ir {
+        return dump_filter(cli);
+    }
 
-    let (vals, filter) = match &cli.filter {
-        None => (Vec::new(), Filter::default()),
-        Some(filter) => {
-            let (path, code) = match filter {
-                cli::Filter::FromFile(path) => (path.into(), std::fs::read_to_string(path)?),
-                cli::Filter::Inline(filter) => ("<inline>".into(), filter.clone()),
-            };
-            parse(&path, &code, &vars, &cli.library_path).map_err(Error::Report)?
-        }
-    };
-    ctx.extend(vals);
+    let (ctx, filter, files) = cli_filter(cli)?;
     //println!("Filter: {:?}", filter);
 
+    // print the profile also when the filter fails
+    #[cfg(feature = "profile")]
+    let _profile = cli.profile.then(|| Profile::new(&filter));
+    if cli.trace {
+        trace(files.clone());
+    }
+
+    // evaluate inputs in parallel unless there are no inputs
+    // (or we profile or trace, because this is done per thread)
+    let threads = cli.threads > 1 && !cli.null_input && !cli.profile && !cli.trace;
     let last = if cli.files.is_empty() {
-        let inputs = read_buffered(cli, io::stdin().lock());
-        with_stdout(|out| run(cli, &filter, ctx, inputs, |v| print(out, cli, &v)))?
+        let stdin = io::stdin().lock();
+        with_stdout(|out| {
+            if threads {
+                threads::run(cli, (&ctx, &filter), None, stdin, out)
+            } else {
+                let inputs = read_buffered(cli, stdin);
+                run(cli, &filter, ctx, inputs, |v| print(out, cli, &v))
+            }
+        })
+        .map_err(|e| e.locate(&files))?
     } else {
         let mut last = None;
         for file in &cli.files {
             let path = Path::new(file);
             let file =
                 load_file(path).map_err(|e| Error::Io(Some(path.display().to_string()), e))?;
+            input::set_file(Some(path));
             let inputs = read_slice(cli, &file);
-            if cli.in_place {
-                // create a temporary file where output is written to
-                let location = path.parent().unwrap();
-                let mut tmp = tempfile::Builder::new()
-                    .prefix("jaq")
-                    .tempfile_in(location)?;
-
-                last = run(cli, &filter, ctx.clone(), inputs, |output| {
-                    print(tmp.as_file_mut(), cli, &output)
-                })?;
-
-                // replace the input file with the temporary file
-                let perms = std::fs::metadata(path)?.permissions();
-                tmp.persist(path).map_err(Error::Persist)?;
-                std::fs::set_permissions(path, perms)?;
-            } else {
-                last = with_stdout(|out| {
+            let process = |out: &mut dyn Write| {
+                if threads {
+                    threads::run(cli, (&ctx, &filter), Some(path), &**file, out)
+                } else {
                     run(cli, &filter, ctx.clone(), inputs, |v| print(out, cli, &v))
-                })?;
+                }
+            };
+            last = if cli.in_place {
+                in_place(cli, path, &file, process)
+            } else {
+                with_stdout(process)
             }
+            .map_err(|e| e.locate(&files))?;
         }
         last
     };
@@ -129,27 +150,392 @@ fn real_main(cli: &Cli) -> Result<ExitCode, Error> {
     }
 }
 
+/// Format the programs in the given files, or in standard input if no files are given.
+///
+/// With `--check`, return exit status code 1 if any program is not formatted.
+fn format_files(cli: &Cli) -> Result<ExitCode, Error> {
+    let indent = if cli.tab {
+        "\t".to_string()
+    } else {
+        " ".repeat(cli.indent)
+    };
+    let format = |path: &Path, code: &str| {
+        format::format(code, &indent).map_err(|e| {
+            let file = load::File {
+                code,
+                path: path.to_path_buf(),
+            };
+            Error::Report(load_errors(Vec::from([(file, e)])))
+        })
+    };
+
+    let mut stdout = io::stdout().lock();
+    let mut changed = false;
+    if cli.files.is_empty() {
+        let mut code = String::new();
+        io::stdin().read_to_string(&mut code)?;
+        let path = Path::new("<stdin>");
+        let new = format(path, &code)?;
+        changed = new != code;
+        // standard input cannot be overwritten, so we print its formatted version
+        if !(cli.diff || cli.dry_run) {
+            stdout.write_all(new.as_bytes())?
+        } else if changed && cli.diff {
+            print_diff(&mut stdout, path, code.as_bytes(), new.as_bytes())?
+        } else if changed {
+            writeln!(stdout, "{}", path.display())?
+        }
+    }
+    for path in &cli.files {
+        let code = std::fs::read_to_string(path)
+            .map_err(|e| Error::Io(Some(path.display().to_string()), e))?;
+        let new = format(path, &code)?;
+        changed |= new != code;
+        if !cli.in_place {
+            stdout.write_all(new.as_bytes())?
+        } else {
+            in_place(cli, path, code.as_bytes(), |w| {
+                Ok(w.write_all(new.as_bytes())?)
+            })?
+        }
+    }
+    Ok(ExitCode::from(u8::from(cli.check && changed)))
+}
+
+/// Format a change yielded by `diff` as `path: old -> new`,
+/// where `old` is omitted for additions, and `new` for removals.
+fn fmt_change(f: &mut Formatter, change: &Val) -> fmt::Result {
+    use yansi::Paint;
+    let get = |k: &str| match change {
+        Val::Obj(o) => o.get(&std::rc::Rc::new(k.to_string())),
+        _ => None,
+    };
+    match get("path") {
+        Some(Val::Arr(path)) => {
+            if !matches!(path.first(), Some(Val::Str(k)) if is_ident(k)) {
+                write!(f, ".")?
+            }
+            path.iter().try_for_each(|k| match k {
+                Val::Str(k) if is_ident(k) => write!(f, ".{k}"),
+                _ => write!(f, "[{k}]"),
+            })?
+        }
+        _ => write!(f, "{change}")?,
+    }
+    match (get("old"), get("new")) {
+        (Some(o), Some(n)) => write!(f, ": {} -> {}", o.red(), n.green()),
+        (Some(o), None) => write!(f, ": {} (removed)", o.red()),
+        (None, Some(n)) => write!(f, ": {} (added)", n.green()),
+        (None, None) => Ok(()),
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    let start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    chars.next().map_or(false, start) && chars.all(|c| start(c) || c.is_ascii_digit())
+}
+
+/// Replace the file at `path`, whose contents are `old`,
+/// by the output that `f` writes to a temporary file in the same directory,
+/// or print what would change if `--dry-run` or `--diff` is given.
+///
+/// The file is left untouched if its contents would not change.
+fn in_place<T>(
+    cli: &Cli,
+    path: &Path,
+    old: &[u8],
+    f: impl FnOnce(&mut dyn Write) -> Result<T, Error>,
+) -> Result<T, Error> {
+    // create a temporary file where output is written to
+    let location = path.parent().unwrap();
+    let mut tmp = tempfile::Builder::new()
+        .prefix("jaq")
+        .tempfile_in(location)?;
+    let mut w = Compare {
+        w: io::BufWriter::new(&mut tmp),
+        old,
+        same: true,
+    };
+    let y = f(&mut w)?;
+    w.flush()?;
+    if w.same && w.old.is_empty() {
+        return Ok(y);
+    }
+    drop(w);
+
+    if cli.diff {
+        let new = std::fs::read(tmp.path())?;
+        print_diff(&mut io::stdout().lock(), path, old, &new)?;
+        return Ok(y);
+    } else if cli.dry_run {
+        writeln!(io::stdout().lock(), "{}", path.display())?;
+        return Ok(y);
+    }
+
+    if let Some(suffix) = &cli.backup {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(suffix);
+        std::fs::copy(path, backup)?;
+    }
+
+    // replace the input file with the temporary file
+    let perms = std::fs::metadata(path)?.permissions();
+    tmp.persist(path).map_err(Error::Persist)?;
+    std::fs::set_permissions(path, perms)?;
+    Ok(y)
+}
+
+/// Write the changes from `old` to `new` in the file at `path` as unified diff.
+fn print_diff(w: &mut dyn Write, path: &Path, old: &[u8], new: &[u8]) -> io::Result<()> {
+    let (old, new) = (String::from_utf8_lossy(old), String::from_utf8_lossy(new));
+    let diff = similar::TextDiff::from_lines(&old, &new);
+    let path = path.display().to_string();
+    write!(w, "{}", diff.unified_diff().header(&path, &path))
+}
+
+/// Writer that determines whether the written bytes equal `old`.
+struct Compare<'a, W> {
+    w: W,
+    /// remaining bytes that have not been compared yet
+    old: &'a [u8],
+    /// true if all written bytes were equal to the compared ones
+    same: bool,
+}
+
+impl<W: Write> Write for Compare<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.w.write(buf)?;
+        self.same = self.same && self.old.starts_with(&buf[..n]);
+        if self.same {
+            self.old = &self.old[n..];
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+/// Compile the filter given on the command line,
+/// returning it together with the values of its global variables and its modules.
+fn cli_filter(cli: &Cli) -> Result<(Vec<Val>, Filter, Files), Error> {
+    let (vars, mut ctx): (Vec<String>, Vec<Val>) = binds(cli)?.into_iter().unzip();
+
+    let (vals, filter, files) = match &cli.filter {
+        None => (Vec::new(), Filter::default(), Vec::new()),
+        Some(filter) => {
+            let (path, code) = filter_code(filter)?;
+            parse(&path, &code, &vars, &cli.library_path).map_err(Error::Report)?
+        }
+    };
+    ctx.extend(vals);
+    Ok((ctx, filter, files))
+}
+
+/// Return the path and the code of the filter given on the command line.
+fn filter_code(filter: &cli::Filter) -> io::Result<(PathBuf, String)> {
+    Ok(match filter {
+        cli::Filter::FromFile(path) => (path.into(), std::fs::read_to_string(path)?),
+        cli::Filter::Inline(filter) => ("<inline>".into(), filter.clone()),
+    })
+}
+
+/// Print warnings about the filter given on the command line.
+///
+/// Like `--fmt --check`, return exit status code 1 if there are warnings, else 0.
+fn lint_filter(cli: &Cli) -> Result<ExitCode, Error> {
+    let Some(filter) = &cli.filter else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    let (path, code) = filter_code(filter)?;
+    let vars: Vec<_> = binds(cli)?
+        .into_iter()
+        .map(|(k, _)| format!("${k}"))
+        .collect();
+
+    let arena = load::Arena::default();
+    let modules = load_modules(&arena, &path, &code, &cli.library_path).map_err(Error::Report)?;
+    let warnings = lint_warnings(load::lint::lint(&modules));
+    // report undefined symbols, which are errors
+    compiler(&vars)
+        .compile_with_hints(modules)
+        .map_err(|e| Error::Report(compile_errors(e)))?;
+
+    let empty = warnings.is_empty();
+    if cli.error_format == ErrorFormat::Json {
+        let warnings = Error::Report(warnings).to_json("warning");
+        warnings.iter().for_each(|v| eprintln!("{v}"))
+    } else {
+        eprint_reports(warnings, "Warning")
+    }
+    Ok(ExitCode::from(u8::from(!empty)))
+}
+
+/// Print the syntax tree and/or the intermediate representation of the filter.
+///
+/// The syntax tree contains the definitions of all loaded modules except for the standard library,
+/// where every string is annotated with its byte range in its module.
+fn dump_filter(cli: &Cli) -> Result<ExitCode, Error> {
+    let mut stdout = io::stdout().lock();
+    if let (true, Some(filter)) = (cli.dump_ast, &cli.filter) {
+        let (path, code) = filter_code(filter)?;
+        let arena = load::Arena::default();
+        let modules = load_modules(&arena, &path, &code, &cli.library_path);
+        let modules = modules.map_err(Error::Report)?;
+        // the first module is the standard library, and
+        // the last module is the main module, containing only a definition of `main`
+        if let [_std, mods @ .., (file, main)] = &modules[..] {
+            for (file, module) in mods {
+                writeln!(stdout, "# {}", file.path.display())?;
+                let spanned = Spanned::in_(file.code);
+                for def in module.body() {
+                    writeln!(stdout, "{:#?}", def.map_ref(&spanned))?
+                }
+            }
+            writeln!(stdout, "# {}", file.path.display())?;
+            let spanned = Spanned::in_(file.code);
+            for def in main.body() {
+                writeln!(stdout, "{:#?}", def.body.map_ref(&spanned))?
+            }
+        }
+    }
+    if cli.dump_ir {
+        let (_ctx, filter, _files) = cli_filter(cli)?;
+        write!(stdout, "{}", filter.ir())?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// String together with its byte range in the source code.
+struct Spanned<'a>(&'a str, Range<usize>);
+
+impl<'a> Spanned<'a> {
+    /// Return a function that annotates a part of `code` with its byte range.
+    fn in_(code: &'a str) -> impl Fn(&&'a str) -> Self {
+        |s| Spanned(s, load::span(code, s))
+    }
+}
+
+impl fmt::Debug for Spanned<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:?} @ {:?}", self.0, self.1)
+    }
+}
+
+/// Profiler that prints the execution statistics of a filter when dropped.
+#[cfg(feature = "profile")]
+struct Profile<'a>(&'a Filter);
+
+#[cfg(feature = "profile")]
+impl<'a> Profile<'a> {
+    fn new(filter: &'a Filter) -> Self {
+        jaq_core::profile::enable(true);
+        Self(filter)
+    }
+}
+
+#[cfg(feature = "profile")]
+impl Drop for Profile<'_> {
+    fn drop(&mut self) {
+        jaq_core::profile::enable(false);
+        let stats = self.0.profile();
+        eprintln!(
+            "{:>12} {:>10} {:>10}  filter",
+            "time (ms)", "calls", "outputs"
+        );
+        for (name, s) in stats {
+            let ms = s.time.as_secs_f64() * 1000.0;
+            eprintln!("{ms:>12.3} {:>10} {:>10}  {name}", s.calls, s.outputs);
+        }
+    }
+}
+
+/// Print every evaluated term of the filter, its input and its outputs to stderr.
+///
+/// Terms of the standard library are not printed.
+fn trace(files: Files) {
+    use jaq_core::trace::{set_hook, Event};
+    set_hook(Some(Box::new(move |(mid, range), depth, event| {
+        // the first module is the standard library
+        let Some(file) = files.get(*mid).filter(|_| *mid != 0) else {
+            return;
+        };
+        let indent = "  ".repeat(depth);
+        match event {
+            Event::Run(v) => {
+                let (line, column) = line_column(&file.code, range.start);
+                let (path, token) = (file.path.display(), &file.code[range.clone()]);
+                eprintln!("{indent}{path}:{line}:{column}: {token} <- {v}")
+            }
+            Event::Output(v) => eprintln!("{indent}  -> {v}"),
+            Event::Error(e) => eprintln!("{indent}  error: {e}"),
+            // `$ENV` is omitted, because it is usually large
+            Event::Break(_v, vars) => vars
+                .iter()
+                .filter(|(x, _)| *x != "$ENV")
+                .for_each(|(x, v)| eprintln!("{indent}{x} = {v}")),
+        }
+    })));
+}
+
+/// Print the structural differences between the values in the two files given.
+///
+/// Like `diff`, return exit status code 1 if there are differences, else 0.
+fn diff_files(cli: &Cli) -> Result<ExitCode, Error> {
+    let value = |path: &PathBuf| {
+        let file = load_file(path).map_err(|e| Error::Io(Some(path.display().to_string()), e))?;
+        let vals = read_slice(cli, &file).collect::<Result<Vec<_>, _>>();
+        match vals.map_err(Error::input)?.as_slice() {
+            [v] => Ok(v.clone()),
+            _ => Err(Error::Parse(format!(
+                "{}: expected exactly one value",
+                path.display()
+            ))),
+        }
+    };
+    let (old, new) = (value(&cli.files[0])?, value(&cli.files[1])?);
+    let changes = jaq_json::diff::diff(&old, &new);
+    with_stdout(|out| {
+        changes.iter().try_for_each(|c| {
+            if cli.raw_output {
+                writeln!(out, "{}", FormatterFn(|f: &mut Formatter| fmt_change(f, c)))
+            } else {
+                print(out, cli, c)
+            }
+        })
+    })?;
+    Ok(ExitCode::from(u8::from(!changes.is_empty())))
+}
+
 fn binds(cli: &Cli) -> Result<Vec<(String, Val)>, Error> {
     let arg = cli.arg.iter().map(|(k, s)| {
         let s = s.to_owned();
         Ok((k.to_owned(), Val::Str(s.into())))
     });
     let argjson = cli.argjson.iter().map(|(k, s)| {
-        use hifijson::token::Lex;
-        let mut lexer = hifijson::SliceLexer::new(s.as_bytes());
         let err = |e| Error::Parse(format!("{e} (for value passed to `--argjson {k}`)"));
-        Ok((k.to_owned(), lexer.exactly_one(Val::parse).map_err(err)?))
+        Ok((k.to_owned(), json_str(s).map_err(err)?))
     });
     let rawfile = cli.rawfile.iter().map(|(k, path)| {
-        let s = std::fs::read_to_string(path).map_err(|e| Error::Io(Some(format!("{path:?}")), e));
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| Error::Io(Some(Path::new(path).display().to_string()), e));
         Ok((k.to_owned(), Val::Str(s?.into())))
     });
     let slurpfile = cli.slurpfile.iter().map(|(k, path)| {
-        let a = json_array(path).map_err(|e| Error::Io(Some(format!("{path:?}")), e));
+        let a =
+            json_array(path).map_err(|e| Error::Io(Some(Path::new(path).display().to_string()), e));
         Ok((k.to_owned(), a?))
     });
 
-    let positional = cli.args.iter().cloned().map(|s| Ok(Val::from(s)));
+    let positional = cli.args.iter().map(|arg| match arg {
+        cli::Arg::Str(s) => Ok(Val::from(s.clone())),
+        cli::Arg::Json(s) => {
+            let err = |e| Error::Parse(format!("{e} (for `--jsonargs` argument `{s}`)"));
+            json_str(s).map_err(err)
+        }
+    });
     let positional = positional.collect::<Result<Vec<_>, Error>>()?;
 
     let var_val = arg.chain(rawfile).chain(slurpfile).chain(argjson);
@@ -162,6 +548,12 @@ fn binds(cli: &Cli) -> Result<Vec<(String, Val)>, Error> {
     Ok(var_val)
 }
 
+/// Parse a string as exactly one JSON value.
+fn json_str(s: &str) -> Result<Val, hifijson::Error> {
+    use hifijson::token::Lex;
+    hifijson::SliceLexer::new(s.as_bytes()).exactly_one(Val::parse)
+}
+
 fn args(positional: &[Val], named: &[(String, Val)]) -> Val {
     let key = |k: &str| k.to_string().into();
     let positional = positional.iter().cloned();
@@ -174,39 +566,67 @@ fn args(positional: &[Val], named: &[(String, Val)]) -> Val {
 }
 
 fn parse(
-    path: &PathBuf,
+    path: &Path,
     code: &str,
     vars: &[String],
     paths: &[PathBuf],
-) -> Result<(Vec<Val>, Filter), Vec<FileReports>> {
-    use compile::Compiler;
-    use load::{import, Arena, File, Loader};
-
-    let default = ["~/.jq", "$ORIGIN/../lib/jq", "$ORIGIN/../lib"].map(|x| x.into());
-    let paths = if paths.is_empty() { &default } else { paths };
-
+) -> Result<(Vec<Val>, Filter, Files), Vec<FileReports>> {
     let vars: Vec<_> = vars.iter().map(|v| format!("${v}")).collect();
-    let arena = Arena::default();
-    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs())).with_std_read(paths);
-    //let loader = Loader::new([]).with_std_read(paths);
-    let path = path.into();
-    let modules = loader
-        .load(&arena, File { path, code })
-        .map_err(load_errors)?;
+    let paths = lib_paths(paths);
+    let arena = load::Arena::default();
+    let modules = load_modules(&arena, path, code, &paths)?;
 
     let mut vals = Vec::new();
-    import(&modules, |p| {
-        let path = p.find(paths, "json")?;
+    load::import(&modules, |p| {
+        let path = p.find(&paths, "json")?;
         vals.push(json_array(path).map_err(|e| e.to_string())?);
         Ok(())
     })
     .map_err(load_errors)?;
 
-    let compiler = Compiler::default()
-        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
-        .with_global_vars(vars.iter().map(|v| &**v));
-    let filter = compiler.compile(modules).map_err(compile_errors)?;
-    Ok((vals, filter))
+    let files = modules
+        .iter()
+        .map(|(file, _)| file.clone().map_code(|s| s.into()));
+    let files = files.collect();
+    let filter = compiler(&vars)
+        .compile_with_hints(modules)
+        .map_err(compile_errors)?;
+    Ok((vals, filter, files))
+}
+
+/// Return the directories to search for modules and data,
+/// which are the given ones, or default ones if none are given.
+fn lib_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let default = ["~/.jq", "$ORIGIN/../lib/jq", "$ORIGIN/../lib"].map(|x| x.into());
+    if paths.is_empty() {
+        default.into()
+    } else {
+        paths.to_vec()
+    }
+}
+
+/// Load the program at `path` with contents `code` and all modules that it uses.
+fn load_modules<'a>(
+    arena: &'a load::Arena,
+    path: &Path,
+    code: &'a str,
+    paths: &[PathBuf],
+) -> Result<load::Modules<&'a str, PathBuf>, Vec<FileReports>> {
+    use load::{File, Loader};
+
+    let paths = lib_paths(paths);
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs())).with_std_read(&paths);
+    //let loader = Loader::new([]).with_std_read(paths);
+    let path = path.into();
+    loader.load(arena, File { path, code }).map_err(load_errors)
+}
+
+/// Return a compiler with all native filters and the given global variables.
+fn compiler(vars: &[String]) -> compile::Compiler<&str, Native<Val>> {
+    let funs = jaq_std::funs().chain(jaq_json::funs());
+    compile::Compiler::default()
+        .with_funs(funs.chain(jaq_std::input::funs()))
+        .with_global_vars(vars.iter().map(|v| &**v))
 }
 
 fn load_errors(errs: load::Errors<&str, PathBuf>) -> Vec<FileReports> {
@@ -224,7 +644,16 @@ fn load_errors(errs: load::Errors<&str, PathBuf>) -> Vec<FileReports> {
     errs.collect()
 }
 
-fn compile_errors(errs: compile::Errors<&str, PathBuf>) -> Vec<FileReports> {
+fn lint_warnings(warnings: load::lint::Warnings<&str, PathBuf>) -> Vec<FileReports> {
+    let warnings = warnings.into_iter().map(|(file, warnings)| {
+        let code = file.code;
+        let warnings = warnings.into_iter().map(|w| report_lint(code, w)).collect();
+        (file.map_code(|s| s.into()), warnings)
+    });
+    warnings.collect()
+}
+
+fn compile_errors(errs: compile::Errors<&str, PathBuf, compile::Hinted<&str>>) -> Vec<FileReports> {
     let errs = errs.into_iter().map(|(file, errs)| {
         let code = file.code;
         let errs = errs.into_iter().map(|e| report_compile(code, e)).collect();
@@ -242,7 +671,7 @@ fn load_file(path: impl AsRef<Path>) -> io::Result<Box<dyn core::ops::Deref<Targ
     }
 }
 
-fn invalid_data(e: impl std::error::Error + Send + Sync + 'static) -> std::io::Error {
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> std::io::Error {
     io::Error::new(io::ErrorKind::InvalidData, e)
 }
 
@@ -254,52 +683,237 @@ fn json_slice(slice: &[u8]) -> impl Iterator<Item = io::Result<Val>> + '_ {
     })
 }
 
-fn json_read<'a>(read: impl BufRead + 'a) -> impl Iterator<Item = io::Result<Val>> + 'a {
-    let mut lexer = hifijson::IterLexer::new(read.bytes());
-    core::iter::from_fn(move || {
+/// Like `json_slice`, but record the line of every value for `input_line_number`.
+///
+/// If `skip_errors` is true, then report values that cannot be parsed and
+/// continue parsing at the next line.
+fn json_slice_lines(slice: &[u8], skip_errors: bool) -> impl Iterator<Item = io::Result<Val>> + '_ {
+    let mut lexer = hifijson::SliceLexer::new(slice);
+    let (mut pos, mut line) = (0, 0);
+    core::iter::from_fn(move || loop {
+        use hifijson::token::Lex;
+        let token = lexer.ws_token()?;
+        // return the line of the character at `offset`
+        let mut goto = |offset| {
+            line += newlines(&slice[pos..offset]);
+            pos = offset;
+            line
+        };
+        // the lexer has read at most the first character of the value
+        input::set_line(Some(goto(slice.len() - lexer.as_slice().len())));
+        let e = match Val::parse(token, &mut lexer) {
+            Ok(v) => return Some(Ok(v)),
+            Err(e) => e,
+        };
+        let offset = (slice.len() - lexer.as_slice().len()).saturating_sub(1);
+        let e = input::ParseError::from_slice(e, slice, goto(offset), offset);
+        if !skip_errors {
+            return Some(Err(invalid_data(e)));
+        }
+        warn_skip("invalid value", Error::Input(e));
+        let next = slice[offset..].iter().position(|c| *c == b'\n');
+        lexer = hifijson::SliceLexer::new(next.map_or(&[], |i| &slice[offset + i + 1..]));
+    })
+}
+
+/// Read JSON values and record the line of every value for `input_line_number`.
+///
+/// If `skip_errors` is true, then report values that cannot be parsed and
+/// continue parsing at the next line.
+fn json_read<'a>(
+    read: impl BufRead + 'a,
+    skip_errors: bool,
+) -> impl Iterator<Item = io::Result<Val>> + 'a {
+    let mut bytes = input::LineBytes::new(read.bytes());
+    let mut lexer = hifijson::IterLexer::new(bytes.clone());
+    core::iter::from_fn(move || loop {
         use hifijson::token::Lex;
-        let v = Val::parse(lexer.ws_token()?, &mut lexer);
-        Some(v.map_err(|e| core::mem::take(&mut lexer.error).unwrap_or_else(|| invalid_data(e))))
+        let token = lexer.ws_token()?;
+        // the lexer has read only the first character of the value yet
+        input::set_line(Some(bytes.line()));
+        let e = match Val::parse(token, &mut lexer) {
+            Ok(v) => return Some(Ok(v)),
+            Err(e) => e,
+        };
+        if let Some(e) = lexer.error.take() {
+            return Some(Err(e));
+        }
+        let e = bytes.error(e);
+        if !skip_errors {
+            return Some(Err(invalid_data(e)));
+        }
+        warn_skip("invalid value", Error::Input(e));
+        if let Err(e) = bytes.skip_line() {
+            return Some(Err(e));
+        }
+        lexer = hifijson::IterLexer::new(bytes.clone());
+    })
+}
+
+fn stream_slice(slice: &[u8]) -> impl Iterator<Item = io::Result<Val>> + '_ {
+    let lexer = hifijson::SliceLexer::new(slice);
+    jaq_json::stream::Parser::new(lexer).map(|r| r.map_err(invalid_data))
+}
+
+fn stream_read<'a>(read: impl BufRead + 'a) -> impl Iterator<Item = io::Result<Val>> + 'a {
+    let mut parser = jaq_json::stream::Parser::new(hifijson::IterLexer::new(read.bytes()));
+    core::iter::from_fn(move || {
+        let v = parser.next()?;
+        let error = &mut parser.lexer().error;
+        Some(v.map_err(|e| core::mem::take(error).unwrap_or_else(|| invalid_data(e))))
     })
 }
 
+/// ASCII record separator, which starts every record in a JSON text sequence.
+const RS: u8 = 0x1e;
+
+/// Read values from the records of a JSON text sequence (RFC 7464).
+///
+/// If a record cannot be parsed, for example because it was truncated,
+/// print a warning and resume at the next record.
+fn seq_values<'a, R: AsRef<[u8]>>(
+    stream: bool,
+    records: impl Iterator<Item = io::Result<R>> + 'a,
+) -> impl Iterator<Item = io::Result<Val>> + 'a {
+    records.flat_map(move |record| {
+        let vals = record.and_then(|record| {
+            let record = record.as_ref();
+            if stream {
+                stream_slice(record).collect::<Result<Vec<_>, _>>()
+            } else {
+                json_slice(record).collect()
+            }
+        });
+        match vals {
+            Ok(vals) => vals.into_iter().map(Ok).collect(),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                warn_skip("invalid or truncated record", Error::input(e));
+                Vec::new()
+            }
+            Err(e) => Vec::from([Err(e)]),
+        }
+    })
+}
+
+/// Parse all values in a slice that is in a format other than JSON.
+fn doc_values(cli: &Cli, slice: &[u8]) -> io::Result<Vec<Val>> {
+    use jaq_json::{cbor, msgpack, toml, yaml};
+    use jaq_std::csv;
+    let s = || std::str::from_utf8(slice).map_err(invalid_data);
+    let records = |r| csv::records(r, cli.header).map_err(invalid_data);
+    match cli.from {
+        InFormat::Json => json_slice(slice).collect(),
+        InFormat::Yaml => yaml::parse_many(s()?).map_err(invalid_data),
+        InFormat::Toml => Ok(Vec::from([toml::parse(s()?).map_err(invalid_data)?])),
+        InFormat::Cbor => cbor::parse_many(slice, cli.bytes).map_err(invalid_data),
+        InFormat::Msgpack => msgpack::parse_many(slice, cli.bytes).map_err(invalid_data),
+        InFormat::Csv => records(csv::parse_csv(s()?).map_err(invalid_data)?),
+        InFormat::Tsv => records(csv::parse_tsv(s()?)),
+    }
+}
+
+/// Yield all values if there is no error, else yield only the error.
+fn flatten_ok<T, E>(r: Result<Vec<T>, E>) -> impl Iterator<Item = Result<T, E>> {
+    let (oks, err) = match r {
+        Ok(oks) => (oks, None),
+        Err(e) => (Vec::new(), Some(Err(e))),
+    };
+    oks.into_iter().map(Ok).chain(err)
+}
+
+/// Report errors and warnings as JSON, as chosen by `--error-format`.
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Print a warning that an invalid input (described by `what`) has been skipped.
+fn warn_skip(what: &str, e: Error) {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        e.to_json("warning").iter().for_each(|v| eprintln!("{v}"))
+    } else {
+        let message = match e {
+            Error::Input(e) => e.to_string(),
+            Error::Parse(e) => e,
+            e => e.message().unwrap_or_default(),
+        };
+        eprintln!("Warning: skipping {what}: {message}")
+    }
+}
+
 fn json_array(path: impl AsRef<Path>) -> io::Result<Val> {
     json_slice(&load_file(path.as_ref())?).collect()
 }
 
-fn read_buffered<'a, R>(cli: &Cli, read: R) -> Box<dyn Iterator<Item = io::Result<Val>> + 'a>
+fn read_buffered<'a, R>(cli: &Cli, mut read: R) -> Box<dyn Iterator<Item = io::Result<Val>> + 'a>
 where
     R: BufRead + 'a,
 {
     if cli.raw_input {
-        Box::new(raw_input(cli.slurp, read).map(|r| r.map(Val::from)))
+        Box::new(raw_input(cli, read).map(|r| r.map(Val::from)))
+    } else if cli.from != InFormat::Json {
+        let mut buf = Vec::new();
+        let vals = read
+            .read_to_end(&mut buf)
+            .and_then(|_| doc_values(cli, &buf));
+        Box::new(collect_if(cli.slurp, flatten_ok(vals)))
+    } else if cli.seq {
+        let records = read.split(RS);
+        Box::new(collect_if(cli.slurp, seq_values(cli.stream, records)))
+    } else if cli.stream {
+        Box::new(collect_if(cli.slurp, stream_read(read)))
     } else {
-        Box::new(collect_if(cli.slurp, json_read(read)))
+        Box::new(collect_if(
+            cli.slurp,
+            json_read(read, cli.continue_on_error),
+        ))
     }
 }
 
 fn read_slice<'a>(cli: &Cli, slice: &'a [u8]) -> Box<dyn Iterator<Item = io::Result<Val>> + 'a> {
     if cli.raw_input {
         let read = io::BufReader::new(slice);
-        Box::new(raw_input(cli.slurp, read).map(|r| r.map(Val::from)))
+        Box::new(raw_input(cli, read).map(|r| r.map(Val::from)))
+    } else if cli.from != InFormat::Json {
+        Box::new(collect_if(cli.slurp, flatten_ok(doc_values(cli, slice))))
+    } else if cli.seq {
+        let records = slice.split(|c| *c == RS).map(Ok);
+        Box::new(collect_if(cli.slurp, seq_values(cli.stream, records)))
+    } else if cli.stream {
+        Box::new(collect_if(cli.slurp, stream_slice(slice)))
     } else {
-        Box::new(collect_if(cli.slurp, json_slice(slice)))
+        let vals = json_slice_lines(slice, cli.continue_on_error);
+        Box::new(collect_if(cli.slurp, vals))
     }
 }
 
-fn raw_input<'a, R>(slurp: bool, mut read: R) -> impl Iterator<Item = io::Result<String>> + 'a
+fn raw_input<'a, R>(cli: &Cli, mut read: R) -> impl Iterator<Item = io::Result<String>> + 'a
 where
     R: BufRead + 'a,
 {
-    if slurp {
+    if cli.slurp {
         let mut buf = String::new();
         let s = read.read_to_string(&mut buf).map(|_| buf);
         Box::new(std::iter::once(s))
+    } else if cli.raw_input0 {
+        let mut line = 0;
+        Box::new(read.split(b'\0').map(move |s| {
+            input::set_line(Some(line));
+            let s = s?;
+            line += newlines(&s);
+            String::from_utf8(s).map_err(invalid_data)
+        }))
     } else {
-        Box::new(read.lines()) as Box<dyn Iterator<Item = _>>
+        let lines = read.lines().enumerate().map(|(i, s)| {
+            input::set_line(Some(i));
+            s
+        });
+        Box::new(lines) as Box<dyn Iterator<Item = _>>
     }
 }
 
+/// Count the line feeds in a slice.
+fn newlines(slice: &[u8]) -> usize {
+    slice.iter().filter(|c| **c == b'\n').count()
+}
+
 fn collect_if<'a, T: FromIterator<T> + 'a, E: 'a>(
     slurp: bool,
     iter: impl Iterator<Item = Result<T, E>> + 'a,
@@ -313,56 +927,194 @@ fn collect_if<'a, T: FromIterator<T> + 'a, E: 'a>(
 
 type FileReports = (load::File<String, PathBuf>, Vec<Report>);
 
+/// Paths and code of all modules of a filter, indexed like in [`compile::Span`].
+type Files = Vec<load::File<String, PathBuf>>;
+
 #[derive(Debug)]
 enum Error {
     Io(Option<String>, io::Error),
     Report(Vec<FileReports>),
     Parse(String),
-    Jaq(jaq_core::Error<Val>),
+    /// error when parsing an input value, with its location
+    Input(input::ParseError),
+    /// runtime error, with the location where it occurred
+    Jaq(jaq_core::Error<Val>, jaq_core::Backtrace),
+    /// runtime error, with reports of where it occurred and of the calls that led to it
+    Located(jaq_core::Error<Val>, Option<FileReports>, Vec<FileReports>),
     Persist(tempfile::PersistError),
     FalseOrNull,
     NoOutput,
 }
 
-impl Termination for Error {
-    fn report(self) -> ExitCode {
-        let exit = match self {
+impl Error {
+    fn exit_code(&self) -> u8 {
+        match self {
             Self::FalseOrNull => 1,
-            Self::Io(prefix, e) => {
-                eprint!("Error: ");
-                if let Some(p) = prefix {
-                    eprint!("{p}: ");
-                }
-                eprintln!("{e}");
-                2
-            }
-            Self::Persist(e) => {
-                eprintln!("Error: {e}");
-                2
-            }
-            Self::Report(file_reports) => {
-                for (file, reports) in file_reports {
-                    let idx = codesnake::LineIndex::new(&file.code);
-                    for e in reports {
-                        eprintln!("Error: {}", e.message);
-                        let block = e.into_block(&idx);
-                        eprintln!("{}[{}]", block.prologue(), file.path.display());
-                        eprintln!("{}{}", block, block.epilogue())
-                    }
-                }
-                3
-            }
+            Self::Io(..) | Self::Persist(_) => 2,
+            Self::Report(_) => 3,
             Self::NoOutput => 4,
-            Self::Parse(e) => {
-                eprintln!("Error: failed to parse: {e}");
-                5
+            Self::Parse(_) | Self::Input(_) | Self::Jaq(..) | Self::Located(..) => 5,
+        }
+    }
+
+    /// Convert an error that occurred when reading input values.
+    fn input(e: io::Error) -> Self {
+        match e
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<input::ParseError>())
+        {
+            Some(e) => Self::Input(e.clone()),
+            None if e.kind() == io::ErrorKind::InvalidData => Self::Parse(e.to_string()),
+            None => Self::Io(None, e),
+        }
+    }
+
+    /// Message of errors other than reports, if there is any.
+    fn message(&self) -> Option<String> {
+        match self {
+            Self::Io(Some(prefix), e) => Some(format!("{prefix}: {e}")),
+            Self::Io(None, e) => Some(e.to_string()),
+            Self::Persist(e) => Some(e.to_string()),
+            Self::Parse(e) => Some(format!("failed to parse: {e}")),
+            Self::Input(e) => Some(format!("failed to parse: {e}")),
+            Self::Jaq(e, _) | Self::Located(e, None, _) => Some(e.to_string()),
+            Self::Located(_, Some(_), _) => None,
+            Self::Report(_) | Self::FalseOrNull | Self::NoOutput => None,
+        }
+    }
+
+    /// Locate a runtime error and the calls that led to it in the given modules.
+    ///
+    /// Locations in the standard library are omitted.
+    fn locate(self, files: &Files) -> Self {
+        let Self::Jaq(e, bt) = self else { return self };
+        let report = |(mid, range): &compile::Span, message, label: &str, color| {
+            let file = files.get(*mid).filter(|_| *mid != 0)?;
+            let text = [(label.to_string(), None)].into();
+            let labels = Vec::from([(range.clone(), text, color)]);
+            let report = Report {
+                message,
+                expected: None,
+                labels,
+            };
+            Some((file.clone(), Vec::from([report])))
+        };
+        let here = "error occurred here";
+        let here = bt
+            .span
+            .and_then(|s| report(&s, e.to_string(), here, Color::Red));
+        let calls = bt.calls.iter().filter_map(|(name, span)| {
+            report(
+                span,
+                format!("in call to {name}"),
+                "called here",
+                Color::Yellow,
+            )
+        });
+        Self::Located(e, here, calls.collect())
+    }
+
+    /// Return the error as JSON diagnostics with the given severity.
+    fn to_json(&self, severity: &str) -> Vec<Val> {
+        let diag = |file, pos, message: &str| json_obj(diagnostic(file, pos, severity, message));
+        match self {
+            Self::Report(file_reports) => file_reports
+                .iter()
+                .flat_map(|(file, reports)| {
+                    reports
+                        .iter()
+                        .map(|e| e.to_json(&file.path, &file.code, severity))
+                })
+                .collect(),
+            Self::Located(e, here, calls) => {
+                let json = |(file, reports): &FileReports, severity| {
+                    let json = |r: &Report| r.to_json(&file.path, &file.code, severity);
+                    reports.iter().map(json).collect::<Vec<_>>()
+                };
+                let here = match here {
+                    Some(here) => json(here, severity),
+                    None => Vec::from([diag(None, None, &e.to_string())]),
+                };
+                let calls = calls.iter().flat_map(|c| json(c, "note"));
+                here.into_iter().chain(calls).collect()
             }
-            Self::Jaq(e) => {
-                eprintln!("Error: {e}");
-                5
+            Self::Input(e) => {
+                let (file, span, line, column) = e.location();
+                Vec::from([diag(Some(file), Some((span, line, column)), e.message())])
             }
-        };
-        ExitCode::from(exit)
+            Self::Io(file, e) => Vec::from([diag(file.as_deref(), None, &e.to_string())]),
+            Self::Parse(e) => Vec::from([diag(None, None, e)]),
+            e => Vec::from_iter(e.message().map(|m| diag(None, None, &m))),
+        }
+    }
+
+    /// Print the error as JSON diagnostics, one object per line.
+    fn report_json(self) -> ExitCode {
+        self.to_json("error").iter().for_each(|v| eprintln!("{v}"));
+        ExitCode::from(self.exit_code())
+    }
+}
+
+/// Return the fields of a JSON diagnostic, where
+/// the file and the position (byte span, line, and column) are null if unknown.
+fn diagnostic(
+    file: Option<&str>,
+    pos: Option<(Range<usize>, usize, usize)>,
+    severity: &str,
+    message: &str,
+) -> Vec<(&'static str, Val)> {
+    let str = |s: &str| Val::from(s.to_string());
+    let int = |i: usize| Val::Int(i as isize);
+    let (span, line, column) = match pos {
+        Some((span, line, column)) => {
+            let span = [span.start, span.end].map(int).into_iter().collect();
+            (span, int(line), int(column))
+        }
+        None => (Val::Null, Val::Null, Val::Null),
+    };
+    Vec::from([
+        ("file", file.map_or(Val::Null, str)),
+        ("span", span),
+        ("line", line),
+        ("column", column),
+        ("severity", str(severity)),
+        ("message", str(message)),
+    ])
+}
+
+fn json_obj(fields: Vec<(&str, Val)>) -> Val {
+    let fields = fields.into_iter().map(|(k, v)| (k.to_string().into(), v));
+    Val::obj(fields.collect())
+}
+
+impl Termination for Error {
+    fn report(self) -> ExitCode {
+        let exit = ExitCode::from(self.exit_code());
+        if let Some(message) = self.message() {
+            eprintln!("Error: {message}")
+        }
+        match self {
+            Self::Report(file_reports) => eprint_reports(file_reports, "Error"),
+            Self::Located(_, here, calls) => {
+                eprint_reports(here.into_iter().collect(), "Error");
+                eprint_reports(calls, "Note")
+            }
+            _ => (),
+        }
+        exit
+    }
+}
+
+/// Print reports with code snippets, where every message starts with `kind`.
+fn eprint_reports(file_reports: Vec<FileReports>, kind: &str) {
+    for (file, reports) in file_reports {
+        let idx = codesnake::LineIndex::new(&file.code);
+        for e in reports {
+            eprintln!("{kind}: {}", e.message);
+            let block = e.into_block(&idx);
+            eprintln!("{}[{}]", block.prologue(), file.path.display());
+            eprintln!("{}{}", block, block.epilogue())
+        }
     }
 }
 
@@ -384,7 +1136,21 @@ fn run(
     mut f: impl FnMut(Val) -> io::Result<()>,
 ) -> Result<Option<bool>, Error> {
     let mut last = None;
-    let iter = iter.map(|r| r.map_err(|e| e.to_string()));
+    // `Ctx` stores input errors only as strings, so we keep the original error
+    let error = core::cell::RefCell::new(None);
+    let iter = iter.map(|r| {
+        r.map_err(|e| {
+            let s = match e
+                .get_ref()
+                .and_then(|e| e.downcast_ref::<input::ParseError>())
+            {
+                Some(e) => e.brief(),
+                None => e.to_string(),
+            };
+            error.replace(Some(e));
+            s
+        })
+    });
 
     let iter = Box::new(iter) as Box<dyn Iterator<Item = _>>;
     let null = Box::new(core::iter::once(Ok(Val::Null))) as Box<dyn Iterator<Item = _>>;
@@ -395,10 +1161,10 @@ fn run(
     let ctx = Ctx::new(vars, &iter);
 
     for item in if cli.null_input { &null } else { &iter } {
-        let input = item.map_err(Error::Parse)?;
+        let input = item.map_err(|e| error.take().map_or(Error::Parse(e), Error::input))?;
         //println!("Got {:?}", input);
-        for output in filter.run((ctx.clone(), input)) {
-            let output = output.map_err(Error::Jaq)?;
+        for output in filter.run_with_backtrace((ctx.clone(), input)) {
+            let output = output.map_err(|(e, bt)| Error::Jaq(e, bt))?;
             last = Some(output.as_bool());
             f(output)?;
         }
@@ -418,6 +1184,7 @@ struct PpOpts {
     compact: bool,
     indent: String,
     sort_keys: bool,
+    ascii: bool,
 }
 
 impl PpOpts {
@@ -458,6 +1225,7 @@ fn fmt_val(f: &mut Formatter, opts: &PpOpts, level: usize, v: &Val) -> fmt::Resu
     use yansi::Paint;
     match v {
         Val::Null | Val::Bool(_) | Val::Int(_) | Val::Float(_) | Val::Num(_) => v.fmt(f),
+        Val::Str(_) if opts.ascii => write!(f, "{:#}", v.green()),
         Val::Str(_) => write!(f, "{}", v.green()),
         Val::Arr(a) => {
             '['.bold().fmt(f)?;
@@ -469,7 +1237,12 @@ fn fmt_val(f: &mut Formatter, opts: &PpOpts, level: usize, v: &Val) -> fmt::Resu
         Val::Obj(o) => {
             '{'.bold().fmt(f)?;
             let kv = |f: &mut Formatter, (k, val): (&std::rc::Rc<String>, &Val)| {
-                write!(f, "{}:", Val::Str(k.clone()).bold())?;
+                let k = Val::Str(k.clone());
+                if opts.ascii {
+                    write!(f, "{:#}:", k.bold())?;
+                } else {
+                    write!(f, "{}:", k.bold())?;
+                }
                 if !opts.compact {
                     write!(f, " ")?;
                 }
@@ -499,16 +1272,70 @@ fn print(w: &mut (impl Write + ?Sized), cli: &Cli, val: &Val) -> io::Result<()>
                 " ".repeat(cli.indent)
             },
             sort_keys: cli.sort_keys,
+            ascii: cli.ascii_output,
         };
         fmt_val(f, &opts, 0, val)
     };
 
-    match val {
-        Val::Str(s) if cli.raw_output || cli.join_output => write!(w, "{s}")?,
-        _ => write!(w, "{}", FormatterFn(f))?,
+    // check this before writing anything, such as the record separator for `--seq`
+    if let Val::Str(s) = val {
+        if cli.raw_output0 && s.contains('\0') {
+            let msg = "cannot print string containing NUL with --raw-output0";
+            Err(io::Error::new(io::ErrorKind::InvalidData, msg))?
+        }
+    }
+
+    let raw = cli.raw_output || cli.join_output || cli.raw_output0;
+    let raw_str = match val {
+        // like jq, escape non-ASCII strings even if raw output is requested
+        Val::Str(s) if raw && (s.is_ascii() || !cli.ascii_output) => Some(s),
+        _ => None,
+    };
+    let table = match raw_str {
+        None if cli.to.is_table() => {
+            Some(table::Table::new(val, cli.sort_keys).map_err(invalid_data)?)
+        }
+        _ => None,
+    };
+    // an empty table is printed as nothing, not even as empty line
+    if table.as_ref().map_or(false, |t| t.is_empty()) {
+        return Ok(());
+    }
+
+    let binary = match cli.to {
+        OutFormat::Cbor => Some(jaq_json::cbor::to_vec(val)),
+        OutFormat::Msgpack => Some(jaq_json::msgpack::to_vec(val)),
+        _ => None,
     };
+    if let Some(bytes) = binary {
+        return w.write_all(&bytes.map_err(invalid_data)?);
+    }
 
-    if cli.join_output {
+    if cli.seq {
+        w.write_all(&[RS])?;
+    }
+
+    match (raw_str, table) {
+        (Some(s), _) => write!(w, "{s}")?,
+        (None, Some(table)) => match cli.to {
+            OutFormat::Csv => table.write_csv(w)?,
+            OutFormat::Tsv => table.write_tsv(w)?,
+            OutFormat::Markdown => table.write_markdown(w)?,
+            _ => table.write_aligned(w)?,
+        },
+        (None, None) => match cli.to {
+            OutFormat::Yaml => write!(w, "{}", jaq_json::yaml::Document(val))?,
+            OutFormat::Toml => {
+                let toml = jaq_json::toml::to_string(val).map_err(invalid_data)?;
+                write!(w, "{}", toml.trim_end())?
+            }
+            _ => write!(w, "{}", FormatterFn(f))?,
+        },
+    };
+
+    if cli.raw_output0 {
+        w.write_all(b"\0")
+    } else if cli.join_output {
         // when running `jaq -jn '"prompt> " | (., input)'`,
         // this flush is necessary to make "prompt> " appear first
         w.flush()
@@ -532,6 +1359,8 @@ type StringColors = Vec<(String, Option<Color>)>;
 #[derive(Debug)]
 struct Report {
     message: String,
+    /// description of what was expected instead of the code of the last label
+    expected: Option<String>,
     labels: Vec<(core::ops::Range<usize>, StringColors, Color)>,
 }
 
@@ -556,6 +1385,7 @@ fn report_io(code: &str, (path, error): (&str, String)) -> Report {
     let path_range = load::span(code, path);
     Report {
         message: format!("could not load file {}: {}", path, error),
+        expected: None,
         labels: [(path_range, [(error, None)].into(), Color::Red)].into(),
     }
 }
@@ -584,6 +1414,7 @@ fn report_lex(code: &str, (expected, found): load::lex::Error<&str>) -> Report {
 
     Report {
         message: format!("expected {}", expected.as_str()),
+        expected: Some(expected.as_str().to_string()),
         labels,
     }
 }
@@ -600,30 +1431,72 @@ fn report_parse(code: &str, (expected, found): load::parse::Error<&str>) -> Repo
 
     Report {
         message: format!("expected {}", expected.as_str()),
+        expected: Some(expected.as_str().to_string()),
         labels: Vec::from([(found_range, found, Color::Red)]),
     }
 }
 
-fn report_compile(code: &str, (found, undefined): compile::Error<&str>) -> Report {
+fn report_compile(code: &str, ((found, undefined), hint): compile::Hinted<&str>) -> Report {
     use compile::Undefined::Filter;
     let found_range = load::span(code, found);
-    let wnoa = |exp, got| format!("wrong number of arguments (expected {exp}, found {got})");
-    let message = match (found, undefined) {
-        ("reduce", Filter(arity)) => wnoa("2", arity),
-        ("foreach", Filter(arity)) => wnoa("2 or 3", arity),
-        (_, undefined) => format!("undefined {}", undefined.as_str()),
+    let wnoa = |exp: &str, got| format!("wrong number of arguments (expected {exp}, found {got})");
+    let message = match (found, undefined, hint.arities_str()) {
+        ("reduce", Filter(arity), _) => wnoa("2", arity),
+        ("foreach", Filter(arity), _) => wnoa("2 or 3", arity),
+        (_, Filter(arity), Some(arities)) => wnoa(&arities, arity),
+        (_, undefined, _) => match hint.suggestion() {
+            Some(s) => format!("undefined {}; {s}", undefined.as_str()),
+            None => format!("undefined {}", undefined.as_str()),
+        },
     };
     let found = [(message.clone(), None)].into();
 
     Report {
         message,
+        expected: None,
         labels: Vec::from([(found_range, found, Color::Red)]),
     }
 }
 
+fn report_lint(code: &str, (found, lint): load::lint::Warning<&str>) -> Report {
+    let found_range = load::span(code, found);
+    let message = lint.as_str().to_string();
+    let found = [(message.clone(), None)].into();
+
+    Report {
+        message,
+        expected: None,
+        labels: Vec::from([(found_range, found, Color::Yellow)]),
+    }
+}
+
+/// Return the line and column of the given byte offset in the code, both starting at 1.
+fn line_column(code: &str, offset: usize) -> (usize, usize) {
+    let before = &code[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
 type CodeBlock = codesnake::Block<codesnake::CodeWidth<String>, String>;
 
 impl Report {
+    /// Return the report as JSON object that locates the code of its last label.
+    ///
+    /// Lines and columns start at 1, where columns count characters;
+    /// the span is given by byte offsets.
+    fn to_json(&self, path: &Path, code: &str, severity: &str) -> Val {
+        let span = self.labels.last().map_or(0..0, |(range, ..)| range.clone());
+        let (line, column) = line_column(code, span.start);
+        let str = |s: &str| Val::from(s.to_string());
+        let path = path.display().to_string();
+        let pos = Some((span.clone(), line, column));
+        let mut fields = diagnostic(Some(&path), pos, severity, &self.message);
+        fields.push(("expected", self.expected.as_deref().map_or(Val::Null, str)));
+        fields.push(("found", str(&code[span])));
+        json_obj(fields)
+    }
+
     fn into_block(self, idx: &codesnake::LineIndex) -> CodeBlock {
         use codesnake::{Block, CodeWidth, Label};
         let color_maybe = |(text, color): (_, Option<Color>)| match color {
@@ -645,7 +1518,8 @@ impl Report {
 }
 
 fn run_test(test: load::test::Test<String>) -> Result<(Val, Val), Error> {
-    let (ctx, filter) = parse(&PathBuf::new(), &test.filter, &[], &[]).map_err(Error::Report)?;
+    let (ctx, filter, _files) =
+        parse(Path::new(""), &test.filter, &[], &[]).map_err(Error::Report)?;
 
     let inputs = RcIter::new(Box::new(core::iter::empty()));
     let ctx = Ctx::new(ctx, &inputs);
@@ -658,8 +1532,8 @@ fn run_test(test: load::test::Test<String>) -> Result<(Val, Val), Error> {
     };
     let input = json(test.input)?;
     let expect: Result<Val, _> = test.output.into_iter().map(json).collect();
-    let obtain: Result<Val, _> = filter.run((ctx, input)).collect();
-    Ok((expect?, obtain.map_err(Error::Jaq)?))
+    let obtain: Result<Val, _> = filter.run_with_backtrace((ctx, input)).collect();
+    Ok((expect?, obtain.map_err(|(e, bt)| Error::Jaq(e, bt))?))
 }
 
 fn run_tests(read: impl BufRead) -> ExitCode {
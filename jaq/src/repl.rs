@@ -0,0 +1,164 @@
+//! Interactive read-eval-print loop.
+//!
+//! Inputs are read once at startup, and every filter line entered
+//! is run on all of them, as if jaq was called with that filter.
+//! Definitions and variables persist across lines.
+use crate::{binds, load_file, parse, print, read_slice, run, Cli, Error};
+use jaq_json::Val;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process::{ExitCode, Termination};
+
+const HELP: &str = "\
+Enter a filter to run it on all inputs, or one of:
+  def f: ...;        Define a filter for all following lines
+  :let $x [FILTER]   Bind $x to the last output of FILTER, or else of the previous line
+  :defs              Show definitions
+  :vars              Show variables, except for $ENV
+  :history           Show previously entered lines
+  :help              Show this help
+  :quit              Exit (as does end of input)";
+
+struct Repl<'a> {
+    cli: &'a Cli,
+    inputs: Vec<Val>,
+    defs: Vec<String>,
+    vars: Vec<(String, Val)>,
+    history: Vec<String>,
+    /// last output of the previous line
+    last: Option<Val>,
+}
+
+/// Read filters from standard input and print their outputs until end of input.
+pub fn repl(cli: &Cli) -> Result<ExitCode, Error> {
+    let inputs = if cli.null_input || cli.files.is_empty() {
+        Vec::from([Val::Null])
+    } else {
+        let mut inputs = Vec::new();
+        for path in &cli.files {
+            let err = |e| Error::Io(Some(path.display().to_string()), e);
+            let file = load_file(path).map_err(err)?;
+            for v in read_slice(cli, &file) {
+                inputs.push(v.map_err(Error::input)?)
+            }
+        }
+        inputs
+    };
+    let mut repl = Repl {
+        cli,
+        inputs,
+        defs: Vec::new(),
+        vars: binds(cli)?,
+        history: Vec::new(),
+        last: None,
+    };
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        eprint!("jaq> ");
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            eprintln!();
+            return Ok(ExitCode::SUCCESS);
+        }
+        let line = line.trim();
+        if matches!(line, ":quit" | ":q") {
+            return Ok(ExitCode::SUCCESS);
+        } else if !line.is_empty() {
+            if let Err(e) = repl.eval(line) {
+                e.report();
+            }
+            repl.history.push(line.to_string());
+        }
+    }
+}
+
+impl Repl<'_> {
+    fn eval(&mut self, line: &str) -> Result<(), Error> {
+        let mut out = io::stdout().lock();
+        match line.split_once(char::is_whitespace).unwrap_or((line, "")) {
+            (":help", _) => writeln!(out, "{HELP}")?,
+            (":history", _) => {
+                for (i, line) in self.history.iter().enumerate() {
+                    writeln!(out, "{:>4}  {line}", i + 1)?
+                }
+            }
+            (":defs", _) => self.defs.iter().try_for_each(|d| writeln!(out, "{d}"))?,
+            (":vars", _) => {
+                // `$ENV` is omitted, because it is usually large and not set by the user
+                for (name, v) in self.vars.iter().filter(|(name, _)| name != "ENV") {
+                    writeln!(out, "${name} = {v}")?
+                }
+            }
+            (":let", rest) => {
+                let rest = rest.trim();
+                let (name, filter) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let name = match name.strip_prefix('$') {
+                    Some(name) if !name.is_empty() => name,
+                    _ => {
+                        eprintln!("Error: expected `:let $NAME [FILTER]`");
+                        return Ok(());
+                    }
+                };
+                let v = if filter.trim().is_empty() {
+                    self.last.clone()
+                } else {
+                    self.run(filter, &mut |_| Ok(()))?
+                };
+                let v = match v {
+                    Some(v) => v,
+                    None => {
+                        eprintln!("Error: no output to bind to ${name}");
+                        return Ok(());
+                    }
+                };
+                match self.vars.iter_mut().find(|(n, _)| n == name) {
+                    Some((_, old)) => *old = v,
+                    None => self.vars.push((name.to_string(), v)),
+                }
+            }
+            (cmd, _) if cmd.starts_with(':') => {
+                eprintln!("Error: unknown command {cmd}, see :help")
+            }
+            ("def", _) => {
+                // check that the definition compiles before keeping it
+                self.compile(&format!("{line} ."))?;
+                self.defs.push(line.to_string())
+            }
+            _ => {
+                let cli = self.cli;
+                self.last = self.run(line, &mut |v| print(&mut out, cli, v))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn compile(&self, filter: &str) -> Result<(Vec<Val>, crate::Filter), Error> {
+        let defs = self.defs.iter().map(|d| &**d);
+        let code = defs.chain([filter]).collect::<Vec<_>>().join("\n");
+        let names: Vec<_> = self.vars.iter().map(|(name, _)| name.clone()).collect();
+        let path = PathBuf::from("<repl>");
+        let (vals, filter, _files) =
+            parse(&path, &code, &names, &self.cli.library_path).map_err(Error::Report)?;
+        let vars = self.vars.iter().map(|(_, v)| v.clone());
+        Ok((vars.chain(vals).collect(), filter))
+    }
+
+    /// Run a filter on all inputs and return its last output.
+    fn run(
+        &self,
+        filter: &str,
+        f: &mut dyn FnMut(&Val) -> io::Result<()>,
+    ) -> Result<Option<Val>, Error> {
+        let (vars, filter) = self.compile(filter)?;
+        let inputs = self.inputs.iter().cloned().map(Ok);
+        let mut last = None;
+        run(self.cli, &filter, vars, inputs, |v| {
+            f(&v)?;
+            last = Some(v);
+            Ok(())
+        })?;
+        Ok(last)
+    }
+}
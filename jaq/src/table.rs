@@ -0,0 +1,213 @@
+//! Output of arrays of objects as tables.
+use jaq_json::Val;
+use jaq_std::csv::escape_tsv;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::rc::Rc;
+use unicode_width::UnicodeWidthStr;
+
+/// Table whose columns are given by the union of keys of an array of objects.
+///
+/// The columns are ordered by the first occurrence of their key.
+/// A cell is `None` if its row lacks the key of its column.
+pub struct Table<'a> {
+    keys: Vec<&'a Rc<String>>,
+    rows: Vec<Vec<Option<&'a Val>>>,
+}
+
+impl<'a> Table<'a> {
+    /// Create a table from an array of objects.
+    pub fn new(v: &'a Val, sort_keys: bool) -> Result<Self, String> {
+        let err = || format!("cannot write {v} as table, expected array of objects");
+        let objs = match v {
+            Val::Arr(a) => a.iter().map(|x| match x {
+                Val::Obj(o) => Ok(o),
+                _ => Err(err()),
+            }),
+            _ => Err(err())?,
+        };
+        let objs = objs.collect::<Result<Vec<_>, _>>()?;
+
+        let mut seen = HashSet::new();
+        let keys = objs.iter().flat_map(|o| o.keys());
+        let mut keys: Vec<_> = keys.filter(|k| seen.insert(*k)).collect();
+        if sort_keys {
+            keys.sort()
+        }
+        let rows = objs
+            .iter()
+            .map(|o| keys.iter().map(|k| o.get(*k)).collect());
+        let rows = rows.collect();
+        Ok(Self { keys, rows })
+    }
+
+    /// Write the table as CSV, quoting strings like `@csv`.
+    ///
+    /// Arrays and objects are written as JSON strings.
+    pub fn write_csv(&self, w: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+        let cell = |v: Option<&Val>| match v {
+            None | Some(Val::Null) => String::new(),
+            Some(Val::Str(s)) => quote(s),
+            Some(v @ (Val::Arr(_) | Val::Obj(_))) => quote(&v.to_string()),
+            Some(v) => v.to_string(),
+        };
+        self.write_sep(w, ",", |k| quote(k), cell)
+    }
+
+    /// Write the table as TSV, escaping strings like `@tsv`.
+    pub fn write_tsv(&self, w: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        let cell = |v: Option<&Val>| match v {
+            None | Some(Val::Null) => String::new(),
+            Some(Val::Str(s)) => escape_tsv(s),
+            Some(v) => escape_tsv(&v.to_string()),
+        };
+        self.write_sep(w, "\t", escape_tsv, cell)
+    }
+
+    fn write_sep(
+        &self,
+        w: &mut (impl Write + ?Sized),
+        sep: &str,
+        key: impl Fn(&str) -> String,
+        cell: impl Fn(Option<&Val>) -> String,
+    ) -> io::Result<()> {
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+        let header = self.keys.iter().map(|k| key(k));
+        write!(w, "{}", header.collect::<Vec<_>>().join(sep))?;
+        for row in &self.rows {
+            let cells = row.iter().map(|v| cell(*v));
+            write!(w, "\n{}", cells.collect::<Vec<_>>().join(sep))?;
+        }
+        Ok(())
+    }
+
+    /// Return true if the table has no columns.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Escape the header and cells of the table and determine the layout of its columns.
+    ///
+    /// Columns that contain only numbers (besides missing cells and nulls) are right-aligned.
+    fn layout(&self, escape: impl Fn(&str) -> String, min_width: usize) -> Layout {
+        let cell = |v: &Option<&Val>| match v {
+            None | Some(Val::Null) => String::new(),
+            Some(Val::Str(s)) => escape(s),
+            Some(v) => escape(&v.to_string()),
+        };
+        let header: Vec<_> = self.keys.iter().map(|k| escape(k)).collect();
+        let rows: Vec<Vec<_>> = self
+            .rows
+            .iter()
+            .map(|r| r.iter().map(cell).collect())
+            .collect();
+
+        let numeric = |i: usize| {
+            let mut col = self.rows.iter().filter_map(|r| r[i]).peekable();
+            let num = |v: &Val| matches!(v, Val::Int(_) | Val::Float(_) | Val::Num(_));
+            col.peek().is_some() && col.all(|v| num(v) || *v == Val::Null)
+        };
+        let right = (0..header.len()).map(numeric).collect();
+        let widths = (0..header.len())
+            .map(|i| {
+                let col = rows.iter().map(|r| r[i].width());
+                col.chain([header[i].width(), min_width]).max().unwrap_or(0)
+            })
+            .collect();
+        Layout {
+            header,
+            rows,
+            right,
+            widths,
+        }
+    }
+
+    /// Write the table as Markdown table, where columns are padded to equal width.
+    pub fn write_markdown(&self, mut w: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        let Layout {
+            header,
+            rows,
+            right,
+            widths,
+        } = self.layout(escape_md, 3);
+
+        let line = |w: &mut dyn Write, cells: &[String]| {
+            for (i, c) in cells.iter().enumerate() {
+                let pad = " ".repeat(widths[i] - c.width());
+                if right[i] {
+                    write!(w, "| {pad}{c} ")?
+                } else {
+                    write!(w, "| {c}{pad} ")?
+                }
+            }
+            write!(w, "|")
+        };
+        let w: &mut dyn Write = &mut w;
+        line(w, &header)?;
+        write!(w, "\n|")?;
+        for (n, right) in widths.iter().zip(&right) {
+            let sep = if *right {
+                "-".repeat(n - 1) + ":"
+            } else {
+                "-".repeat(*n)
+            };
+            write!(w, " {sep} |")?
+        }
+        for row in &rows {
+            writeln!(w)?;
+            line(w, row)?;
+        }
+        Ok(())
+    }
+
+    /// Write the table for terminals, where columns are padded to equal width
+    /// and separated by two spaces.
+    ///
+    /// Strings are escaped like `@tsv`, so that every row takes a single line.
+    pub fn write_aligned(&self, mut w: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        let Layout {
+            header,
+            rows,
+            right,
+            widths,
+        } = self.layout(escape_tsv, 1);
+
+        let line = |w: &mut dyn Write, cells: &[String]| {
+            let cells = cells.iter().enumerate().map(|(i, c)| {
+                let pad = " ".repeat(widths[i] - c.width());
+                if right[i] {
+                    pad + c
+                } else {
+                    c.clone() + &pad
+                }
+            });
+            // do not write trailing whitespace
+            write!(w, "{}", cells.collect::<Vec<_>>().join("  ").trim_end())
+        };
+        let w: &mut dyn Write = &mut w;
+        line(w, &header)?;
+        writeln!(w)?;
+        let seps: Vec<_> = widths.iter().map(|n| "-".repeat(*n)).collect();
+        write!(w, "{}", seps.join("  "))?;
+        for row in &rows {
+            writeln!(w)?;
+            line(w, row)?;
+        }
+        Ok(())
+    }
+}
+
+/// Escaped cells of a table, together with the alignment and width of its columns.
+struct Layout {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+    right: Vec<bool>,
+    widths: Vec<usize>,
+}
+
+fn escape_md(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', "<br>")
+}
@@ -0,0 +1,246 @@
+//! Parallel evaluation of a filter on independent inputs.
+//!
+//! The input is split into chunks that end at the end of a line
+//! (or NUL-terminated string) that is also the end of a value,
+//! meaning that values may span several lines, but never several chunks.
+//! The chunks are distributed to worker threads, which share the compiled filter.
+//! Because values cannot be shared between threads,
+//! every worker parses its own copy of the global variables.
+//! Workers parse their chunk, run the filter on every value, and
+//! write the outputs to a buffer, which is then
+//! sent back and written in the original order of the chunks.
+use crate::{input, json_str, newlines, print, read_slice, Cli, Error, Filter};
+use jaq_core::{Ctx, RcIter, ValT};
+use jaq_json::Val;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+
+/// Size in bytes after which a chunk is completed by the end of its current value.
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Outputs of a chunk, and the truth value of its last output or an error.
+type Outputs = (Vec<u8>, Result<Option<bool>, Failure>);
+
+/// Index of a chunk, number of its first line, offset of its first byte, and its contents.
+type Job = (usize, usize, usize, Vec<u8>);
+
+/// Error of a worker, which can be sent between threads, unlike [`Error`].
+enum Failure {
+    Io(io::Error),
+    /// error when reading input values
+    Input(io::Error),
+    /// runtime error with its backtrace, whose values are serialised as JSON
+    Jaq(jaq_core::Error<String>, jaq_core::Backtrace),
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<Failure> for Error {
+    fn from(f: Failure) -> Self {
+        match f {
+            Failure::Io(e) => Self::Io(None, e),
+            Failure::Input(e) => Self::input(e),
+            Failure::Jaq(e, bt) => Self::Jaq(e.map(|s| json_str(&s).unwrap_or(Val::from(s))), bt),
+        }
+    }
+}
+
+/// Run the filter on the inputs read from `read` using `cli.threads` workers.
+///
+/// The inputs are read from the file at `path`, or from standard input if it is `None`.
+/// Return the truth value of the last output, like [`crate::run`].
+pub fn run(
+    cli: &Cli,
+    (vars, filter): (&[Val], &Filter),
+    path: Option<&Path>,
+    mut read: impl BufRead,
+    w: &mut dyn Write,
+) -> Result<Option<bool>, Error> {
+    let delim = if cli.raw_input0 { b'\0' } else { b'\n' };
+    let vars: Vec<String> = vars.iter().map(|v| v.to_string()).collect();
+    let (job_tx, job_rx) = mpsc::channel::<Job>();
+    let (out_tx, out_rx) = mpsc::channel::<(usize, Outputs)>();
+    let job_rx = Mutex::new(job_rx);
+
+    std::thread::scope(|s| {
+        for _ in 0..cli.threads {
+            let out_tx = out_tx.clone();
+            s.spawn(|| worker(cli, (&vars, filter), path, &job_rx, out_tx));
+        }
+        // make receiving fail if all workers have terminated
+        drop(out_tx);
+
+        let mut writer = Writer::new(w, out_rx);
+        let (mut sent, mut line, mut byte) = (0, 1, 0);
+        loop {
+            // limit the number of chunks that are kept in memory
+            while sent - writer.next >= 2 * cli.threads {
+                writer.recv()?;
+            }
+            let mut chunk = Vec::new();
+            let res = (&mut read).take(CHUNK_SIZE as u64).read_to_end(&mut chunk);
+            let res = res.and_then(|_| {
+                // every line end of raw input is the end of a value
+                let mut boundary = Boundary::default();
+                let mut end = cli.raw_input || boundary.scan(&chunk);
+                while !(end && chunk.ends_with(&[delim])) {
+                    let start = chunk.len();
+                    if read.read_until(delim, &mut chunk)? == 0 {
+                        break;
+                    }
+                    end = cli.raw_input || boundary.scan(&chunk[start..]);
+                }
+                Ok(())
+            });
+            if let Err(e) = res {
+                // report the error after the outputs of all previous chunks
+                let err = Err(Failure::Input(e));
+                writer.pending.insert(sent, (Vec::new(), err));
+                sent += 1;
+                break;
+            }
+            if chunk.is_empty() {
+                break;
+            }
+            // this fails only if all workers have terminated
+            let (lines, len) = (newlines(&chunk), chunk.len());
+            if job_tx.send((sent, line, byte, chunk)).is_err() {
+                break;
+            }
+            sent += 1;
+            line += lines;
+            byte += len;
+        }
+        // make workers terminate once they have processed all chunks
+        drop(job_tx);
+        while writer.next < sent {
+            writer.recv()?;
+        }
+        Ok(writer.last)
+    })
+}
+
+/// Scanner that determines whether JSON text ends outside of any value.
+///
+/// This considers only strings, arrays, and objects,
+/// because no other values can contain line breaks.
+#[derive(Default)]
+struct Boundary {
+    /// number of arrays and objects that have been opened, but not closed
+    depth: usize,
+    string: bool,
+    escape: bool,
+}
+
+impl Boundary {
+    /// Scan the next bytes and return true if they end outside of any value.
+    fn scan(&mut self, bytes: &[u8]) -> bool {
+        for c in bytes {
+            if self.escape {
+                self.escape = false
+            } else if self.string {
+                match c {
+                    b'\\' => self.escape = true,
+                    b'"' => self.string = false,
+                    _ => (),
+                }
+            } else {
+                match c {
+                    b'"' => self.string = true,
+                    b'[' | b'{' => self.depth += 1,
+                    b']' | b'}' => self.depth = self.depth.saturating_sub(1),
+                    _ => (),
+                }
+            }
+        }
+        !self.string && self.depth == 0
+    }
+}
+
+/// Process chunks until there are no more.
+fn worker(
+    cli: &Cli,
+    (vars, filter): (&[String], &Filter),
+    path: Option<&Path>,
+    jobs: &Mutex<mpsc::Receiver<Job>>,
+    out: mpsc::Sender<(usize, Outputs)>,
+) {
+    let next = || jobs.lock().ok()?.recv().ok();
+    // the variables have been printed as JSON by us, so parsing them cannot fail
+    let vars = vars.iter().map(|v| json_str(v).unwrap_or(Val::Null));
+    let msg = "cannot use `input` or `inputs` with --threads";
+    let inputs = core::iter::repeat_with(|| Err(msg.to_string()));
+    let inputs = RcIter::new(Box::new(inputs) as Box<dyn Iterator<Item = _>>);
+    let ctx = Ctx::new(vars, &inputs);
+    input::set_file(path);
+
+    while let Some((i, line, byte, chunk)) = next() {
+        input::set_start(line, byte);
+        let mut buf = Vec::new();
+        let mut last = None;
+        let mut eval = || {
+            for input in read_slice(cli, &chunk) {
+                let input = input.map_err(Failure::Input)?;
+                for output in filter.run_with_backtrace((ctx.clone(), input)) {
+                    let output =
+                        output.map_err(|(e, bt)| Failure::Jaq(e.map(|v| v.to_string()), bt))?;
+                    last = Some(output.as_bool());
+                    print(&mut buf, cli, &output)?;
+                }
+            }
+            Ok(last)
+        };
+        let result = eval();
+        if out.send((i, (buf, result))).is_err() {
+            return;
+        }
+    }
+}
+
+/// Writes outputs of chunks in their original order.
+struct Writer<'w> {
+    w: &'w mut dyn Write,
+    rx: mpsc::Receiver<(usize, Outputs)>,
+    /// outputs of chunks that cannot be written yet, because previous ones are missing
+    pending: BTreeMap<usize, Outputs>,
+    /// index of the next chunk to be written
+    next: usize,
+    last: Option<bool>,
+}
+
+impl<'w> Writer<'w> {
+    fn new(w: &'w mut dyn Write, rx: mpsc::Receiver<(usize, Outputs)>) -> Self {
+        let pending = BTreeMap::new();
+        Self {
+            w,
+            rx,
+            pending,
+            next: 0,
+            last: None,
+        }
+    }
+
+    /// Receive the outputs of a chunk and write all outputs that are ready.
+    fn recv(&mut self) -> Result<(), Error> {
+        if !self.pending.contains_key(&self.next) {
+            let (i, outputs) = self
+                .rx
+                .recv()
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "worker thread terminated"))?;
+            self.pending.insert(i, outputs);
+        }
+        while let Some((buf, result)) = self.pending.remove(&self.next) {
+            self.w.write_all(&buf)?;
+            self.last = result?.or(self.last);
+            self.next += 1;
+        }
+        self.w.flush()?;
+        Ok(())
+    }
+}
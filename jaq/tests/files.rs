@@ -0,0 +1,423 @@
+use std::{env, fs, io, process};
+
+fn jaq_output(args: &[&str], dir: &std::path::Path) -> io::Result<process::Output> {
+    process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+}
+
+fn jaq_status(args: &[&str], dir: &std::path::Path) -> io::Result<(Option<i32>, String)> {
+    let output = jaq_output(args, dir)?;
+    let stdout = String::from_utf8(output.stdout).expect("invalid UTF-8 in output");
+    Ok((output.status.code(), stdout))
+}
+
+fn jaq(args: &[&str], dir: &std::path::Path) -> io::Result<String> {
+    let (status, stdout) = jaq_status(args, dir)?;
+    assert_eq!(status, Some(0));
+    Ok(stdout)
+}
+
+#[test]
+fn in_place_error() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("x.json"), "[1] 2")?;
+
+    let (status, _) = jaq_status(&["-i", ".[0]", "x.json"], dir.path())?;
+    assert_eq!(status, Some(5));
+    // the file is left untouched, and the temporary file is removed
+    assert_eq!(fs::read_to_string(dir.path().join("x.json"))?, "[1] 2");
+    assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+    Ok(())
+}
+
+#[test]
+fn fmt() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (changed, unchanged) = ("def f:1;f|.+1\n", "def f: 1;\nf | . + 1\n");
+    fs::write(dir.path().join("x.jq"), changed)?;
+    fs::write(dir.path().join("y.jq"), unchanged)?;
+    let files = ["x.jq", "y.jq"];
+
+    let (status, out) = jaq_status(&[&["--fmt", "--check"], &files[..]].concat(), dir.path())?;
+    assert_eq!(status, Some(1));
+    assert_eq!(out, "x.jq\n");
+    assert_eq!(jaq(&["--fmt", "--check", "y.jq"], dir.path())?, "");
+
+    let (status, out) = jaq_status(&["--fmt", "--diff", "x.jq"], dir.path())?;
+    assert_eq!(status, Some(0));
+    let diff = "--- x.jq\n+++ x.jq\n@@ -1 +1,2 @@\n-def f:1;f|.+1\n+def f: 1;\n+f | . + 1\n";
+    assert_eq!(out, diff);
+
+    jaq(&[&["--fmt", "-i"], &files[..]].concat(), dir.path())?;
+    assert_eq!(fs::read_to_string(dir.path().join("x.jq"))?, unchanged);
+
+    let (status, _) = jaq_status(&["--fmt", "--check", "x.jq"], dir.path())?;
+    assert_eq!(status, Some(0));
+
+    // `fmt` as first argument is a filter, not a request to format files
+    let (status, _) = jaq_status(&["fmt", "-n"], dir.path())?;
+    assert_eq!(status, Some(3));
+    Ok(())
+}
+
+#[test]
+fn lint() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("f.jq"), "def f: 1;\ntry 1 catch 2")?;
+
+    let args = ["--lint", "--error-format=json", "-f", "f.jq"];
+    let output = jaq_output(&args, dir.path())?;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = [
+        r#"{"file":"f.jq","span":[22,23],"line":2,"column":13,"severity":"warning","message":"unreachable catch, because the term cannot fail","expected":null,"found":"2"}"#,
+        r#"{"file":"f.jq","span":[4,5],"line":1,"column":5,"severity":"warning","message":"unused definition","expected":null,"found":"f"}"#,
+    ];
+    assert_eq!(stderr, format!("{}\n", expected.join("\n")));
+
+    // the filter is not run, and errors are reported as usual
+    assert_eq!(
+        jaq_status(&["--lint", "error"], dir.path())?,
+        (Some(0), "".into())
+    );
+    let (status, _) = jaq_status(&["--lint", "f"], dir.path())?;
+    assert_eq!(status, Some(3));
+    Ok(())
+}
+
+#[test]
+fn dump() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let stdout = |args: &[&str]| -> io::Result<String> {
+        let output = jaq_output(args, dir.path())?;
+        assert_eq!(output.status.code(), Some(0));
+        Ok(String::from_utf8(output.stdout).expect("invalid UTF-8 in output"))
+    };
+
+    let ast = stdout(&["--dump-ast", "def f: .a; f"])?;
+    assert!(ast.starts_with("# <inline>\n"));
+    assert!(ast.contains(r#"name: "f" @ 4..5,"#));
+    assert!(ast.contains(r#""a" @ 8..9,"#));
+
+    let ir = stdout(&["--dump-ir", "def f: length; f"])?;
+    assert!(ir.starts_with("main = #"));
+    assert!(ir.contains(" (f/0) = Native(length/0, [])\n"));
+    assert!(ir
+        .lines()
+        .any(|l| l.contains("(main/0) = CallDef(#") && l.contains(" f/0, ")));
+
+    // errors are reported as usual
+    let (status, _) = jaq_status(&["--dump-ast", "("], dir.path())?;
+    assert_eq!(status, Some(3));
+    Ok(())
+}
+
+#[test]
+#[cfg(not(feature = "profile"))]
+fn profile_feature() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = jaq_output(&["--profile", "-n", "1"], dir.path())?;
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    assert!(stderr.contains("feature `profile`"), "{stderr}");
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "profile")]
+fn profile() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let args = ["--profile", "-n", "def f: 1, 2; [f, f]"];
+    let output = jaq_output(&args, dir.path())?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"[\n  1,\n  2,\n  1,\n  2\n]\n");
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let mut lines = stderr.lines();
+    assert!(lines.next().unwrap().ends_with("calls    outputs  filter"));
+    let f = lines.find(|l| l.contains(" f/0 #")).unwrap();
+    let f: Vec<_> = f.split_whitespace().collect();
+    assert_eq!(f[1..3], ["2", "4"]);
+
+    // statistics are printed also when the filter fails
+    let output = jaq_output(&["--profile", "-n", "error"], dir.path())?;
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    assert!(stderr.contains(" error/0 (native)\n"));
+    Ok(())
+}
+
+#[test]
+fn trace() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let args = [
+        "--trace",
+        "-n",
+        "--arg",
+        "a",
+        "b",
+        "def f: 1; f as $x | $__break__",
+    ];
+    let output = jaq_output(&args, dir.path())?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"null\n");
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = [
+        "<inline>:1:11: f as $x | $__break__ <- null",
+        "  <inline>:1:11: f <- null",
+        "    <inline>:1:8: 1 <- null",
+        "      -> 1",
+        "    -> 1",
+        "  <inline>:1:21: $__break__ <- null",
+        "    $ARGS = {\"positional\":[],\"named\":{\"a\":\"b\"}}",
+        "    $a = \"b\"",
+        "    $x = 1",
+        "    -> null",
+        "  -> null",
+    ];
+    assert_eq!(stderr, format!("{}\n", expected.join("\n")));
+    Ok(())
+}
+
+#[test]
+fn compare() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(
+        dir.path().join("a.json"),
+        r#"{"a": [1, 2], "b": {"c d": 1}}"#,
+    )?;
+    fs::write(
+        dir.path().join("b.json"),
+        r#"{"b": {"c d": 2}, "a": [1], "e": 0}"#,
+    )?;
+
+    let (status, out) = jaq_status(&["--compare", "-c", "a.json", "b.json"], dir.path())?;
+    assert_eq!(status, Some(1));
+    let changes = r#"{"path":["a",1],"old":2}
+{"path":["b","c d"],"old":1,"new":2}
+{"path":["e"],"new":0}
+"#;
+    assert_eq!(out, changes);
+
+    let (status, out) = jaq_status(&["--compare", "-rM", "a.json", "b.json"], dir.path())?;
+    assert_eq!(status, Some(1));
+    assert_eq!(
+        out,
+        ".a[1]: 2 (removed)\n.b[\"c d\"]: 1 -> 2\n.e: 0 (added)\n"
+    );
+
+    assert_eq!(jaq(&["--compare", "a.json", "a.json"], dir.path())?, "");
+    Ok(())
+}
+
+#[test]
+fn raw_output0_nul() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let filter = r#""a", "b\u0000c""#;
+    let (status, out) = jaq_status(&["-n", "--seq", "--raw-output0", filter], dir.path())?;
+    assert_eq!(status, Some(2));
+    // the record separator of the erroneous string is not printed
+    assert_eq!(out, "\u{1e}a\0");
+    Ok(())
+}
+
+#[test]
+fn non_json_from() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = jaq_output(&["--seq", "--from", "yaml", "."], dir.path())?;
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    assert!(
+        stderr.contains("--seq cannot be used with --from"),
+        "{stderr}"
+    );
+    Ok(())
+}
+
+#[test]
+fn continue_on_error_conflicts() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    for opts in [&["--seq"][..], &["--stream"], &["--from", "yaml"]] {
+        let args = [&["--continue-on-error"], opts, &["."]].concat();
+        let output = jaq_output(&args, dir.path())?;
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+        let msg = format!("--continue-on-error cannot be used with {}", opts[0]);
+        assert!(stderr.contains(&msg), "{stderr}");
+    }
+    Ok(())
+}
+
+#[test]
+fn input_position() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a.json"), "1\n\n[2,\n3] 4\n")?;
+    fs::write(dir.path().join("b.json"), "5")?;
+
+    let filter = "[input_filename, input_line_number]";
+    let out = jaq(&["-c", filter, "a.json", "b.json"], dir.path())?;
+    let expected = "[\"a.json\",1]\n[\"a.json\",3]\n[\"a.json\",4]\n[\"b.json\",1]\n";
+    assert_eq!(out, expected);
+
+    let out = jaq(&["-c", "--threads", "2", filter, "a.json"], dir.path())?;
+    assert_eq!(out, expected.replace("[\"b.json\",1]\n", ""));
+
+    // the line is unknown for streamed values
+    let out = jaq(
+        &["-c", "--stream", "first(input_line_number)", "b.json"],
+        dir.path(),
+    )?;
+    assert_eq!(out, "null\n");
+    Ok(())
+}
+
+#[test]
+fn parse_error() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a.json"), "{\"a\": 1}\n{\"a\": }\n")?;
+
+    let output = jaq_output(&["-c", ".a", "a.json"], dir.path())?;
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(output.stdout, b"1\n");
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let snippet = "  ╭─[a.json:2:7]\n  │\n2 │ {\"a\": }\n  ┆       ┬\n";
+    assert!(stderr.contains(snippet), "{stderr}");
+    Ok(())
+}
+
+#[test]
+fn error_format_json() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("f.jq"), "def f: 1;\nf, g(.)")?;
+
+    let output = jaq_output(&["--error-format=json", "-nf", "f.jq"], dir.path())?;
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = r#"{"file":"f.jq","span":[13,14],"line":2,"column":4,"severity":"error","message":"undefined filter","expected":null,"found":"g"}"#;
+    assert_eq!(stderr, format!("{expected}\n"));
+
+    let output = jaq_output(&["--error-format", "json", "-n", "[1"], dir.path())?;
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = r#"{"file":"<inline>","span":[2,2],"line":1,"column":3,"severity":"error","message":"expected closing bracket","expected":"closing bracket","found":""}"#;
+    assert_eq!(stderr, format!("{expected}\n"));
+
+    // input errors are located, and warnings are reported in the same format
+    fs::write(dir.path().join("a.json"), "{\"a\": 1}\n{\"a\": }\n2\n")?;
+    let args = [
+        "--error-format=json",
+        "--continue-on-error",
+        "-c",
+        ".",
+        "a.json",
+    ];
+    let output = jaq_output(&args, dir.path())?;
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"{\"a\":1}\n2\n");
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = r#"{"file":"a.json","span":[15,16],"line":2,"column":7,"severity":"warning","message":"value expected"}"#;
+    assert_eq!(stderr, format!("{expected}\n"));
+
+    let output = jaq_output(
+        &["--error-format=json", ".", "a.json", "b.json"],
+        dir.path(),
+    )?;
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = r#"{"file":"a.json","span":[15,16],"line":2,"column":7,"severity":"error","message":"value expected"}"#;
+    assert_eq!(stderr, format!("{expected}\n"));
+
+    let output = jaq_output(&["--error-format=json", ".", "b.json"], dir.path())?;
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    assert!(stderr.starts_with(
+        r#"{"file":"b.json","span":null,"line":null,"column":null,"severity":"error","message":"#
+    ));
+    Ok(())
+}
+
+#[test]
+fn undefined_hint() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let message = |filter: &str| -> io::Result<String> {
+        let output = jaq_output(&["--error-format=json", "-n", filter], dir.path())?;
+        assert_eq!(output.status.code(), Some(3));
+        let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+        let start = stderr.find(r#""message":""#).unwrap() + 11;
+        let len = stderr[start..].find(r#"",""#).unwrap();
+        Ok(stderr[start..start + len].to_string())
+    };
+    assert_eq!(
+        message("rnage(3)")?,
+        "undefined filter; did you mean `range`?"
+    );
+    assert_eq!(
+        message("range(1; 2; 3; 4)")?,
+        "wrong number of arguments (expected 1, 2, or 3, found 4)"
+    );
+    assert_eq!(
+        message(". as $value | $valeu")?,
+        "undefined variable; did you mean `$value`?"
+    );
+    Ok(())
+}
+
+#[test]
+fn runtime_error() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("f.jq"), "def f: .a + 1;\n[f]")?;
+    fs::write(dir.path().join("a.json"), "{\"a\": \"x\"}")?;
+
+    let output = jaq_output(&["-f", "f.jq", "a.json"], dir.path())?;
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let mut reports = stderr.split("──╯\n");
+    let error = reports.next().unwrap();
+    assert!(error.starts_with("Error: cannot calculate \"x\" + 1\n"));
+    assert!(error.contains("─[f.jq]"));
+    assert!(error.contains("error occurred here"));
+    let note = reports.next().unwrap();
+    assert!(note.starts_with("Note: in call to f/0\n"));
+    assert!(note.contains("called here"));
+    assert_eq!(reports.next(), Some(""));
+
+    // runtime errors are located also when running in parallel
+    let args = [
+        "--error-format=json",
+        "--threads",
+        "2",
+        "-f",
+        "f.jq",
+        "a.json",
+    ];
+    let output = jaq_output(&args, dir.path())?;
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = [
+        r#"{"file":"f.jq","span":[7,13],"line":1,"column":8,"severity":"error","message":"cannot calculate \"x\" + 1","expected":null,"found":".a + 1"}"#,
+        r#"{"file":"f.jq","span":[16,17],"line":2,"column":2,"severity":"note","message":"in call to f/0","expected":null,"found":"f"}"#,
+    ];
+    assert_eq!(stderr, format!("{}\n", expected.join("\n")));
+
+    // errors in the standard library are located at their call
+    let output = jaq_output(&["--error-format=json", "-n", "error(null)"], dir.path())?;
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = r#"{"file":"<inline>","span":[0,11],"line":1,"column":1,"severity":"error","message":"null","expected":null,"found":"error(null)"}"#;
+    assert_eq!(stderr, format!("{expected}\n"));
+
+    let args = [
+        "--error-format=json",
+        "-n",
+        r#"def f: error("boom"); def g: f; g"#,
+    ];
+    let output = jaq_output(&args, dir.path())?;
+    let stderr = String::from_utf8(output.stderr).expect("invalid UTF-8 in output");
+    let expected = [
+        r#"{"file":"<inline>","span":[7,20],"line":1,"column":8,"severity":"error","message":"\"boom\"","expected":null,"found":"error(\"boom\")"}"#,
+        r#"{"file":"<inline>","span":[29,30],"line":1,"column":30,"severity":"note","message":"in call to f/0","expected":null,"found":"f"}"#,
+        r#"{"file":"<inline>","span":[32,33],"line":1,"column":33,"severity":"note","message":"in call to g/0","expected":null,"found":"g"}"#,
+    ];
+    assert_eq!(stderr, format!("{}\n", expected.join("\n")));
+    Ok(())
+}
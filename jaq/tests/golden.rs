@@ -1,6 +1,6 @@
 use std::{env, io, process, str};
 
-fn golden_test(args: &[&str], input: &str, out_ex: &str) -> io::Result<()> {
+fn golden_test(args: &[&str], input: impl AsRef<[u8]>, out_ex: &str) -> io::Result<()> {
     let mut child = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
         .args(args)
         .stdin(process::Stdio::piped())
@@ -8,7 +8,7 @@ fn golden_test(args: &[&str], input: &str, out_ex: &str) -> io::Result<()> {
         .spawn()?;
 
     use io::Write;
-    child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    child.stdin.take().unwrap().write_all(input.as_ref())?;
     let output = child.wait_with_output()?;
     assert!(output.status.success());
 
@@ -70,6 +70,23 @@ test!(
     r#"{"positional":["a","--test","--"],"named":{"x":"y"}}"#
 );
 
+test!(
+    jsonargs,
+    &[
+        "-c",
+        "--jsonargs",
+        "$ARGS.positional",
+        "1",
+        "{\"a\": [null]}",
+        "--args",
+        "b",
+        "--jsonargs",
+        "\"c\""
+    ],
+    "0",
+    r#"[1,{"a":[null]},"b","c"]"#
+);
+
 test!(
     join_output,
     &["-j", "."],
@@ -88,6 +105,13 @@ bar
 ]"#
 );
 
+test!(
+    raw_output0,
+    &["--raw-output0", "."],
+    r#""foo" "bar\nbaz" [1]"#,
+    "foo\0bar\nbaz\0[\n  1\n]\0"
+);
+
 test!(
     compact,
     &["-c", "."],
@@ -122,6 +146,152 @@ test!(
 {"inputs":[0,1,2,3]}"#
 );
 
+test!(
+    stream,
+    &["-c", "--stream"],
+    r#"{"a": [1, {"b": null}], "c": {}} 2"#,
+    r#"[["a",0],1]
+[["a",1,"b"],null]
+[["a",1,"b"]]
+[["a",1]]
+[["c"],{}]
+[["c"]]
+[[],2]"#
+);
+
+test!(
+    stream_slurp,
+    &["-c", "--stream", "--slurp", "fromstream(.[])"],
+    "[1, [2]] {}",
+    r#"[1,[2]]
+{}"#
+);
+
+test!(
+    seq,
+    &["--seq", "-c"],
+    "\x1e1\n\x1e[2,\n\x1e{\"a\": [3]}\n",
+    "\x1e1\n\x1e{\"a\":[3]}"
+);
+
+test!(
+    from_yaml,
+    &["-c", "--from", "yaml"],
+    "a: [1, 2.5, x]\nb: {c: ~, 1: true}\n---\n- \"1\"\n",
+    r#"{"a":[1,2.5,"x"],"b":{"c":null,"1":true}}
+["1"]"#
+);
+
+test!(
+    to_yaml,
+    &["--to", "yaml"],
+    r#"{"a": [1, {"b": "x"}], "c": {}, "d": "true"} 2"#,
+    r#"---
+a:
+  - 1
+  - b: x
+c: {}
+d: "true"
+---
+2"#
+);
+
+test!(
+    from_toml,
+    &["-c", "--from", "toml"],
+    "a = 1\nd = 1979-05-27T07:32:00Z\n[b]\nc = [1.5, \"x\"]\n",
+    r#"{"a":1,"d":"1979-05-27T07:32:00Z","b":{"c":[1.5,"x"]}}"#
+);
+
+test!(
+    to_toml,
+    &["--to", "toml"],
+    r#"{"a": 1, "b": {"c": [1.5, "x"]}}"#,
+    r#"a = 1
+
+[b]
+c = [1.5, "x"]"#
+);
+
+test!(
+    from_cbor,
+    &["-c", "--from", "cbor"],
+    b"\xa2\x61a\x83\x01\xf9\x41\x00\xc2\x49\x01\x00\x00\x00\x00\x00\x00\x00\x00\x61b\x42hi\x20",
+    r#"{"a":[1,2.5,18446744073709551616],"b":"aGk="}
+-1"#
+);
+
+test!(
+    from_csv,
+    &["-c", "--from", "csv"],
+    "a,\"b\"\"c\"\r\n1,\"2\n3\"\n",
+    r#"["a","b\"c"]
+["1","2\n3"]"#
+);
+
+test!(
+    from_tsv_header,
+    &["-c", "--from", "tsv", "--header"],
+    "a\tb\n1\\t2\t3\n",
+    r#"{"a":"1\t2","b":"3"}"#
+);
+
+const TABLE: &str = r#"[{"a": "x,\"y\"", "b": 1}, {"c": [1], "a": "日本"}, {"b": null, "c": "z"}]"#;
+
+test!(
+    to_csv,
+    &["--to", "csv"],
+    TABLE,
+    r#""a","b","c"
+"x,""y""",1,
+"日本",,"[1]"
+,,"z""#
+);
+
+test!(
+    to_tsv_sorted,
+    &["--to", "tsv", "-S", "map(del(.a))"],
+    TABLE,
+    "b\tc\n1\t\n\t[1]\n\tz"
+);
+
+test!(
+    to_markdown,
+    &["--to", "markdown"],
+    TABLE,
+    r#"| a     |   b | c   |
+| ----- | --: | --- |
+| x,"y" |   1 |     |
+| 日本  |     | [1] |
+|       |     | z   |"#
+);
+
+test!(
+    to_table,
+    &["--to", "table", ".[0].a += \"\\n\""],
+    TABLE,
+    r#"a        b  c
+-------  -  ---
+x,"y"\n  1
+日本        [1]
+            z"#
+);
+
+test!(
+    to_csv_empty,
+    &["--to", "csv", ".[]"],
+    r#"[[{"a": 1}], [], [{"b": 2}]]"#,
+    "\"a\"\n1\n\"b\"\n2"
+);
+
+test!(
+    from_msgpack_bytes_array,
+    &["-c", "--from", "msgpack", "--bytes", "array"],
+    b"\x82\xa1a\x93\x01\xcb\x40\x04\x00\x00\x00\x00\x00\x00\xcf\xff\xff\xff\xff\xff\xff\xff\xff\xa1b\xc4\x02hi\xff",
+    r#"{"a":[1,2.5,18446744073709551615],"b":[104,105]}
+-1"#
+);
+
 const ONE23: &str = "One\nTwo\nThree\n";
 
 test!(raw_input_slurp, &["-Rs"], ONE23, r#""One\nTwo\nThree\n""#);
@@ -135,11 +305,19 @@ test!(
 "Three""#
 );
 
+test!(
+    raw_input0,
+    &["--raw-input0"],
+    "One\nTwo\0Three\0",
+    r#""One\nTwo"
+"Three""#
+);
+
 test!(
     fmt_str,
     &[],
     r#""\u0000\u200b\r\t\n asdf""#,
-    r#""\u0000​\r\t\n asdf""#
+    "\"\\u0000\u{200b}\\r\\t\\n asdf\""
 );
 
 test!(
@@ -149,9 +327,117 @@ test!(
     r#"{"विश्व":1}"#
 );
 
+test!(
+    ascii_output,
+    &["-ac", "., .a"],
+    r#"{"ä": "x", "a": "€ 𝄞"}"#,
+    r#"{"\u00e4":"x","a":"\u20ac \ud834\udd1e"}
+"\u20ac \ud834\udd1e""#
+);
+
+test!(
+    threads,
+    &["--threads", "3", "-c", "[., length]"],
+    "[1, 2]\n\"ab\" {}\n\n{\"a\": 1}",
+    r#"[[1,2],2]
+["ab",2]
+[{},0]
+[{"a":1},1]"#
+);
+
+test!(
+    threads_multiline,
+    &["--threads", "2", "-c", "--arg", "a", "b", ". + [$a]"],
+    "[\n\"[\\\"\", 1\n]\n".repeat(10000),
+    &"[\"[\\\"\",1,\"b\"]\n".repeat(10000)
+);
+
+test!(
+    threads_raw,
+    &["--threads", "2", "-R", "ascii_upcase"],
+    "a\nb\r\nc",
+    r#""A"
+"B"
+"C""#
+);
+
+test!(
+    repl,
+    &["--repl", "-nc", "--arg", "a", "b"],
+    ":let $x [1, 2]\ndef f: . + $x;\n[3] | f\n:let $y\n$y | length\n.[\n:vars\n:history",
+    r#"[3,1,2]
+3
+$a = "b"
+$ARGS = {"positional":[],"named":{"a":"b"}}
+$x = [1,2]
+$y = [3,1,2]
+   1  :let $x [1, 2]
+   2  def f: . + $x;
+   3  [3] | f
+   4  :let $y
+   5  $y | length
+   6  .[
+   7  :vars"#
+);
+
+test!(
+    input_position,
+    &[
+        "-c",
+        "[input_filename, input_line_number], input_line_number"
+    ],
+    "1 2\n\n  {\n}",
+    r#"[null,1]
+1
+[null,1]
+1
+[null,3]
+3"#
+);
+
+test!(
+    input_line_number_raw,
+    &["-R", "input_line_number"],
+    ONE23,
+    "1\n2\n3"
+);
+
+test!(
+    continue_on_error,
+    &["-c", "--continue-on-error", "[., input_line_number]"],
+    "1\n{\"a\": }\n[2,\n3]\n\"x\ny\"",
+    r#"[1,1]
+[[2,3],3]"#
+);
+
 test!(
     mods,
     &["-c", "-L", "tests", r#"include "a"; [a, data, d]"#],
     "0",
     r#"["bcddd",[1,2],3]"#
 );
+
+test!(
+    fmt,
+    &["--fmt"],
+    r#"# increment
+def inc( $x ):.+$x ;   # by x
+
+
+if . then inc(1)|[.,.+1,"a string that is so long that this array does not fit on one line"] elif .a?then{a:.a,"b":1}else-(.)end"#,
+    r#"# increment
+def inc($x): . + $x; # by x
+
+if . then
+  inc(1)
+  | [
+    .,
+    . + 1,
+    "a string that is so long that this array does not fit on one line"
+  ]
+elif .a? then
+  {a: .a, b: 1}
+else
+  -.
+end"#
+);
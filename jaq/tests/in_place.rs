@@ -0,0 +1,34 @@
+use std::{env, fs, io, process};
+
+fn jaq(args: &[&str], dir: &std::path::Path) -> io::Result<String> {
+    let output = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(args)
+        .current_dir(dir)
+        .output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout).expect("invalid UTF-8 in output"))
+}
+
+#[test]
+fn in_place() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (changed, unchanged) = ("{\"a\":1,\"b\":2}\n", "{\n  \"a\": 1\n}\n");
+    fs::write(dir.path().join("x.json"), changed)?;
+    fs::write(dir.path().join("y.json"), unchanged)?;
+    let files = ["del(.b)", "x.json", "y.json"];
+
+    let out = jaq(&[&["--dry-run"], &files[..]].concat(), dir.path())?;
+    assert_eq!(out, "x.json\n");
+
+    let out = jaq(&[&["--diff"], &files[..]].concat(), dir.path())?;
+    let diff = "--- x.json\n+++ x.json\n@@ -1 +1,3 @@\n-{\"a\":1,\"b\":2}\n+{\n+  \"a\": 1\n+}\n";
+    assert_eq!(out, diff);
+    assert_eq!(fs::read_to_string(dir.path().join("x.json"))?, changed);
+
+    jaq(&[&["--backup=.orig"], &files[..]].concat(), dir.path())?;
+    assert_eq!(fs::read_to_string(dir.path().join("x.json"))?, unchanged);
+    assert_eq!(fs::read_to_string(dir.path().join("x.json.orig"))?, changed);
+    // unchanged files are neither rewritten nor backed up
+    assert!(!dir.path().join("y.json.orig").exists());
+    Ok(())
+}